@@ -0,0 +1,44 @@
+//! Compares evaluation with and without memoization on a deliberately
+//! redundant expression, where the same subexpression is repeated many times.
+
+use calculator::{
+    parser::{Expression, Parser},
+    runtime::{evaluate, evaluate_memoized, Environment},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Creates an expression where an expensive factorial is repeated
+/// `2^depth` times. The value stays the same at every level.
+fn redundant_expression(depth: u32) -> Expression {
+    let mut input = "170!".to_string();
+    for _ in 0..depth {
+        input = format!("({input} + {input}) / 2");
+    }
+    Parser::new(&input)
+        .parse_expression()
+        .expect("the redundant expression is valid")
+}
+
+fn memoization(c: &mut Criterion) {
+    let expr = redundant_expression(12);
+    let mut group = c.benchmark_group("redundant tree");
+    // Evaluation consumes the expression, so we clone it outside of the measurement.
+    group.bench_function("unmemoized", |b| {
+        b.iter_batched(
+            || expr.clone(),
+            |expr| evaluate(expr, &mut Environment::default()),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("memoized", |b| {
+        b.iter_batched(
+            || expr.clone(),
+            |expr| evaluate_memoized(expr, &mut Environment::default()),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, memoization);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Measures each phase of the pipeline separately, so that a regression can
+//! be traced to the tokenizer, the parser or the runtime.
+//!
+//! The parser tokenizes its input itself, so the parsing times include the
+//! tokenizing times. Subtract them to get the time spent in the parser alone.
+
+use calculator::{parser::Parser, tokenizer::Tokenizer};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Representative inputs, by name.
+fn corpus() -> Vec<(&'static str, String)> {
+    // A short, typical input
+    let small = "1 + 2 * 3".to_string();
+    // Deeply nested parentheses, which stress the recursion of the pratt parser
+    let nested = format!("{}1{}", "(1 + ".repeat(200), ")".repeat(200));
+    // A long flat sum, which stresses the loop of the pratt parser
+    let wide = vec!["1"; 1000].join(" + ");
+    // Every kind of operator, with different binding powers
+    let operators =
+        vec!["-2^3! * 4 / 5 % + 6 <? 7 >? 8 == 9 and not 10 < 11 or √12"; 50].join(" + ");
+    // Long number literals, which stress the number parser
+    let numbers = vec!["123456789.987654321 + 0x1.fffp10"; 500].join(" * ");
+    vec![
+        ("small", small),
+        ("nested", nested),
+        ("wide", wide),
+        ("operators", operators),
+        ("numbers", numbers),
+    ]
+}
+
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    for (name, input) in corpus() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| Tokenizer::new(input).tokens())
+        });
+    }
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in corpus() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| Parser::new(input).parse_expression())
+        });
+    }
+    group.finish();
+}
+
+fn evaluate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate");
+    for (name, input) in corpus() {
+        let expr = Parser::new(&input)
+            .parse_expression()
+            .expect("the corpus inputs are valid");
+        group.bench_with_input(BenchmarkId::from_parameter(name), &expr, |b, expr| {
+            b.iter(|| expr.eval())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tokenize, parse, evaluate);
+criterion_main!(benches);
@@ -0,0 +1,181 @@
+//! Batch mode, used when the input comes from a file or a pipe
+//! instead of the interactive prompt.
+//!
+//! Every line is evaluated, and the results are printed all at once
+//! so that they can be aligned in a nice column. With `--json`, every
+//! result is printed as a JSON object on its own line instead, and with
+//! `--csv`, as an `input,result` row. Every result is followed by a newline,
+//! or by the delimiter given with `--delimiter`, like `\0` for `xargs -0`.
+//! Warnings, like overflows under `?overflow warn`, are printed to the
+//! standard error.
+
+use calculator::{
+    format::fixed,
+    runtime::RuntimeError,
+    session::{Output, Session, SessionError},
+};
+
+use crate::{clipboard, csv, error_message, json, with_total};
+
+/// How the results are printed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `input = result` lines, aligned on the `=` signs.
+    #[default]
+    Aligned,
+    /// One JSON object per result.
+    Json,
+    /// One `input,result` row per result. Errors are in the result column.
+    Csv,
+}
+
+/// Evaluates every line of the input, then prints the results in the given mode,
+/// each followed by the delimiter. Stops at the first `?quit`. Returns whether
+/// every `assert` passed.
+pub fn run(input: &str, mode: Mode, delimiter: &str) -> bool {
+    let mut session = Session::new();
+    let mut results = Vec::new();
+    let mut passed = true;
+    for line in input.lines() {
+        let output = session.execute(line);
+        if let Err(SessionError::Runtime(RuntimeError::AssertionFailed(..))) = output {
+            passed = false;
+        }
+        // Warnings go to the standard error, so the results can still be read
+        // by scripts. They are shown before the results, so they name the input.
+        if session.overflowed() {
+            eprintln!(
+                "warning: an operation overflowed to infinity in `{}`",
+                line.trim()
+            );
+        }
+        let result = match output {
+            Ok(Output::Result(result)) if mode == Mode::Json => json_result(result, &session, line),
+            Ok(Output::Result(result)) => with_total(session.format_result(result), &session),
+            // The rounded result is read back, so the JSON value is rounded too
+            Ok(Output::Rounded(result, decimals)) if mode == Mode::Json => json_result(
+                fixed(result, decimals).parse().unwrap_or(result),
+                &session,
+                line,
+            ),
+            Ok(Output::Rounded(result, decimals)) => with_total(fixed(result, decimals), &session),
+            Ok(Output::Digits(_, digits)) if mode == Mode::Json => json::digits(&digits),
+            Ok(Output::Digits(_, digits)) => with_total(digits, &session),
+            // The formatted text isn't a number, like `0xff`
+            Ok(Output::Formatted(_, text)) if mode == Mode::Json => json::text(&text),
+            Ok(Output::Formatted(_, text)) => with_total(text, &session),
+            Ok(Output::Text(text)) if mode == Mode::Json => json::text(&text),
+            Ok(Output::Text(text)) => text,
+            Ok(Output::Copy(text)) => match clipboard::copy(&text) {
+                Ok(()) if mode == Mode::Json => json::text(&format!("copied `{text}`")),
+                Ok(()) => format!("copied `{text}`"),
+                Err(e) if mode == Mode::Json => json::error(&e, None),
+                Err(e) => format!("error: {e}"),
+            },
+            Ok(Output::Quit) => break,
+            Ok(Output::Nothing) => continue,
+            Err(e) if mode == Mode::Json => {
+                let (message, span) = error_message(e, line);
+                json::error(&message, span)
+            }
+            Err(e) => format!("error: {}", error_message(e, line).0),
+        };
+        results.push((line.trim().to_string(), result));
+    }
+
+    let lines = match mode {
+        Mode::Aligned => align_results(&results),
+        Mode::Json => results.into_iter().map(|(_, result)| result).collect(),
+        Mode::Csv => results
+            .iter()
+            .map(|(input, result)| csv::row(&[input, result]))
+            .collect(),
+    };
+    print!("{}", delimited(&lines, delimiter));
+    passed
+}
+
+/// Serializes a result as JSON. JSON has no infinity or `NaN`, so the
+/// non-finite result of a division by zero is the division by zero error,
+/// like the one of `inv(0)`. Other non-finite results are `null`.
+fn json_result(result: f64, session: &Session, line: &str) -> String {
+    match session.division_by_zero() {
+        Some(span) if !result.is_finite() => {
+            let error = SessionError::Runtime(RuntimeError::DivisionByZero(span));
+            let (message, span) = error_message(error, line);
+            json::error(&message, span)
+        }
+        _ => json::value(result),
+    }
+}
+
+/// Joins lines, with the delimiter after each of them, including the last one,
+/// like newlines usually are.
+pub fn delimited(lines: &[String], delimiter: &str) -> String {
+    lines
+        .iter()
+        .map(|line| format!("{line}{delimiter}"))
+        .collect()
+}
+
+/// Formats `(input, result)` pairs as `input = result` lines, padding
+/// the inputs so that all the `=` signs are in the same column.
+pub fn align_results(results: &[(String, String)]) -> Vec<String> {
+    // We count characters and not bytes, since inputs may contain
+    // multibyte characters.
+    let width = results
+        .iter()
+        .map(|(input, _)| input.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    results
+        .iter()
+        .map(|(input, result)| format!("{input:<width$} = {result}"))
+        .collect()
+}
+
+/// Tests for batch mode.
+#[cfg(test)]
+mod tests {
+    use crate::batch::{align_results, delimited};
+
+    #[test]
+    fn test_align_results() {
+        let results = vec![
+            ("1 + 2".to_string(), "3".to_string()),
+            ("10 * 10 * 10".to_string(), "1000".to_string()),
+            ("1".to_string(), "1".to_string()),
+        ];
+        assert_eq!(
+            vec![
+                "1 + 2        = 3",
+                "10 * 10 * 10 = 1000",
+                "1            = 1",
+            ],
+            align_results(&results)
+        );
+    }
+
+    #[test]
+    fn test_delimited() {
+        let lines = vec!["x = 3".to_string(), "y = 6".to_string()];
+        assert_eq!("x = 3\ny = 6\n", delimited(&lines, "\n"));
+        assert_eq!("x = 3\0y = 6\0", delimited(&lines, "\0"));
+        assert_eq!("", delimited(&[], "\n"));
+    }
+
+    #[test]
+    fn test_align_results_empty() {
+        assert!(align_results(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_align_results_multibyte() {
+        let results = vec![
+            ("é".to_string(), "1".to_string()),
+            ("ab".to_string(), "2".to_string()),
+        ];
+        assert_eq!(vec!["é  = 1", "ab = 2"], align_results(&results));
+    }
+}
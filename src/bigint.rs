@@ -0,0 +1,293 @@
+//! Arbitrary-precision integers, used in big integer mode so that large
+//! results like `100!` or `2^128` are exact instead of rounded floats.
+//! They're only built with the `bigint` feature, which is on by default.
+
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// The largest integer up to which every integer is exactly a float, `2^53`.
+pub const MAX_EXACT_FLOAT: f64 = 9_007_199_254_740_992.0;
+
+/// The base of the limbs. A power of 10 makes showing the digits easy.
+const BASE: u64 = 1_000_000_000;
+
+/// The number of decimal digits in a limb.
+const LIMB_DIGITS: usize = 9;
+
+/// An integer of any size.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    /// Whether the integer is negative. Zero is never negative.
+    negative: bool,
+    /// The limbs of the magnitude in base `BASE`, least significant first.
+    /// There are no leading zero limbs, so zero has no limbs at all.
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    /// Creates an integer from its sign and limbs, removing leading zero limbs.
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Self {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    /// Converts a float, if it's an integer of at most `MAX_EXACT_FLOAT`.
+    /// Larger floats are rounded, so they may not be the integer that was meant.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if value.fract() != 0.0 || value.abs() > MAX_EXACT_FLOAT {
+            return None;
+        }
+        let magnitude = Self::from(value.abs() as u64);
+        Some(if value < 0.0 { -magnitude } else { magnitude })
+    }
+
+    /// Converts the integer to the nearest float.
+    pub fn to_f64(&self) -> f64 {
+        // Parsing rounds correctly, which adding up the limbs wouldn't
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Converts the integer to a `u64`, if it's non-negative and small enough.
+    pub fn to_u64(&self) -> Option<u64> {
+        if self.negative {
+            return None;
+        }
+        self.limbs.iter().rev().try_fold(0u64, |value, &limb| {
+            value.checked_mul(BASE)?.checked_add(limb as u64)
+        })
+    }
+
+    /// The number of decimal digits, without the sign. Zero has one digit.
+    pub fn digits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => (self.limbs.len() - 1) * LIMB_DIGITS + top.ilog10() as usize + 1,
+            None => 1,
+        }
+    }
+
+    /// The base 10 logarithm of the magnitude, roughly. It's only used to
+    /// know how many digits a power will have before computing it.
+    pub fn log10(&self) -> f64 {
+        match self.limbs[..] {
+            [] => f64::NEG_INFINITY,
+            [top] => (top as f64).log10(),
+            [.., next, top] => {
+                let top = top as f64 * BASE as f64 + next as f64;
+                top.log10() + ((self.limbs.len() - 2) * LIMB_DIGITS) as f64
+            }
+        }
+    }
+
+    /// Raises the integer to a power, by squaring.
+    pub fn pow(&self, mut exponent: u64) -> Self {
+        let mut result = Self::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = result * base.clone();
+            }
+            exponent /= 2;
+            if exponent > 0 {
+                base = base.clone() * base;
+            }
+        }
+        result
+    }
+}
+
+impl From<u64> for BigInt {
+    fn from(mut value: u64) -> Self {
+        let mut limbs = Vec::new();
+        while value > 0 {
+            limbs.push((value % BASE) as u32);
+            value /= BASE;
+        }
+        Self::new(false, limbs)
+    }
+}
+
+/// Compares the magnitudes of two integers.
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+/// Adds two magnitudes.
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Subtracts a magnitude from a larger or equal one.
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let subtracted = *b.get(i).unwrap_or(&0) as i64 + borrow;
+        let mut difference = limb as i64 - subtracted;
+        borrow = 0;
+        if difference < 0 {
+            difference += BASE as i64;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    result
+}
+
+/// Multiplies two magnitudes, limb by limb like on paper.
+fn multiply_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let product = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = product % BASE;
+            carry = product / BASE;
+        }
+        // The limbs past the end of `b` were never written, so the carry fits
+        result[i + b.len()] += carry;
+    }
+    result.into_iter().map(|limb| limb as u32).collect()
+}
+
+impl Add for BigInt {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        if self.negative == rhs.negative {
+            return Self::new(self.negative, add_magnitudes(&self.limbs, &rhs.limbs));
+        }
+        // The sign is the one of the larger magnitude
+        match compare_magnitudes(&self.limbs, &rhs.limbs) {
+            Ordering::Less => Self::new(rhs.negative, subtract_magnitudes(&rhs.limbs, &self.limbs)),
+            _ => Self::new(self.negative, subtract_magnitudes(&self.limbs, &rhs.limbs)),
+        }
+    }
+}
+
+impl Sub for BigInt {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Mul for BigInt {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.negative != rhs.negative,
+            multiply_magnitudes(&self.limbs, &rhs.limbs),
+        )
+    }
+}
+
+impl Neg for BigInt {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(!self.negative, self.limbs)
+    }
+}
+
+/// Big integers are shown with all their digits, like `340282366920938463463374607431768211456`.
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((top, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{top}")?;
+        for limb in rest.iter().rev() {
+            write!(f, "{limb:0width$}", width = LIMB_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tests for big integers.
+#[cfg(test)]
+mod tests {
+    use crate::bigint::{BigInt, MAX_EXACT_FLOAT};
+
+    /// Converts an integer float, panicking if it can't be.
+    fn big(value: f64) -> BigInt {
+        BigInt::from_f64(value).unwrap()
+    }
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!("0", big(0.0).to_string());
+        assert_eq!("0", big(-0.0).to_string());
+        assert_eq!("-42", big(-42.0).to_string());
+        assert_eq!("9007199254740992", big(MAX_EXACT_FLOAT).to_string());
+        assert_eq!(None, BigInt::from_f64(1.5));
+        assert_eq!(None, BigInt::from_f64(MAX_EXACT_FLOAT * 2.0));
+        assert_eq!(None, BigInt::from_f64(f64::INFINITY));
+        assert_eq!(None, BigInt::from_f64(f64::NAN));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // Carries and borrows cross limbs.
+        assert_eq!("1000000000", (big(999_999_999.0) + big(1.0)).to_string());
+        assert_eq!("999999999", (big(1e9) - big(1.0)).to_string());
+        assert_eq!("-3", (big(2.0) - big(5.0)).to_string());
+        assert_eq!("3", (big(-2.0) + big(5.0)).to_string());
+        assert_eq!("0", (big(-5.0) + big(5.0)).to_string());
+        assert_eq!(
+            "999999999998999000000000001",
+            (big(999_999_999_999.0) * big(999_999_999_999_999.0)).to_string()
+        );
+        assert_eq!("-6", (big(-2.0) * big(3.0)).to_string());
+        assert_eq!("0", (big(-2.0) * big(0.0)).to_string());
+        assert_eq!(big(0.0), -big(0.0));
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(
+            "340282366920938463463374607431768211456",
+            big(2.0).pow(128).to_string()
+        );
+        assert_eq!("-27", big(-3.0).pow(3).to_string());
+        assert_eq!("1", big(0.0).pow(0).to_string());
+        assert_eq!("0", big(0.0).pow(5).to_string());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let large = big(2.0).pow(128);
+        assert_eq!(2f64.powi(128), large.to_f64());
+        assert_eq!(39, large.digits());
+        assert!((large.log10() - 38.53).abs() < 0.01);
+        assert_eq!(None, large.to_u64());
+        assert_eq!(Some(u64::MAX), BigInt::from(u64::MAX).to_u64());
+        assert_eq!(None, big(-1.0).to_u64());
+        assert_eq!(1, big(0.0).digits());
+        assert_eq!(10, big(1e9).digits());
+    }
+}
@@ -0,0 +1,19 @@
+//! Access to the system clipboard, used by the `?copy` command.
+//! The clipboard needs an extra dependency, so it's only available
+//! with the `clipboard` feature. Without it, copying always fails.
+
+/// Copies some text to the system clipboard.
+/// Returns an error message if the clipboard isn't available.
+#[cfg(feature = "clipboard")]
+pub fn copy(text: &str) -> Result<(), String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|e| format!("could not copy to the clipboard: {e}"))
+}
+
+/// Copies some text to the system clipboard.
+/// Returns an error message if the clipboard isn't available.
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_text: &str) -> Result<(), String> {
+    Err("the calculator was built without the `clipboard` feature".to_string())
+}
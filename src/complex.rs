@@ -0,0 +1,233 @@
+//! Complex numbers, like `1 + 2i`. They are only used in complex mode,
+//! so real-only users never see them.
+
+use std::{
+    fmt::Display,
+    ops::{Add, Div, Mul, Neg, Sub},
+};
+
+/// A complex number, made of a real part and an imaginary part.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Complex {
+    /// The real part.
+    pub re: f64,
+    /// The imaginary part.
+    pub im: f64,
+}
+
+impl Complex {
+    /// The imaginary unit, whose square is `-1`.
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    /// Creates a complex number from its real and imaginary parts.
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    /// Whether the imaginary part is zero.
+    pub fn is_real(self) -> bool {
+        self.im == 0.0
+    }
+
+    /// Whether both parts are finite.
+    pub fn is_finite(self) -> bool {
+        self.re.is_finite() && self.im.is_finite()
+    }
+
+    /// The distance from zero, also called the modulus.
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// The angle from the positive real axis, from `-π` to `π`.
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    /// The principal square root, whose real part is never negative.
+    /// So the square root of `-4` is `2i`, not `-2i`.
+    pub fn sqrt(self) -> Self {
+        let abs = self.abs();
+        let re = ((abs + self.re) / 2.0).sqrt();
+        let im = ((abs - self.re) / 2.0).sqrt();
+        // The imaginary part has the same sign as the original one
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// `e` raised to the power of this number.
+    pub fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Self::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+
+    /// The principal natural logarithm.
+    pub fn ln(self) -> Self {
+        Self::new(self.abs().ln(), self.arg())
+    }
+
+    /// Raises this number to an integer power, by repeated squaring.
+    /// Unlike `powc`, this is exact for small numbers, so `(1 + 2i)^2` is `-3 + 4i`.
+    pub fn powi(self, exponent: i32) -> Self {
+        let mut result = Self::from(1.0);
+        let mut base = self;
+        let mut remaining = exponent.unsigned_abs();
+        while remaining > 0 {
+            if remaining % 2 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            remaining /= 2;
+        }
+        if exponent < 0 {
+            Self::from(1.0) / result
+        } else {
+            result
+        }
+    }
+
+    /// Raises this number to a complex power, using the principal logarithm.
+    pub fn powc(self, exponent: Self) -> Self {
+        // Small integer powers are computed exactly
+        if exponent.is_real() && exponent.re.fract() == 0.0 && exponent.re.abs() <= 1024.0 {
+            return self.powi(exponent.re as i32);
+        }
+        // Square roots are computed exactly too, so `(-4)^0.5` is `2i`
+        if exponent == Self::from(0.5) {
+            return self.sqrt();
+        }
+        // `0` to any other power is `0`, but its logarithm is infinite
+        if self == Self::default() {
+            return self;
+        }
+        (exponent * self.ln()).exp()
+    }
+}
+
+/// Real numbers are complex numbers with no imaginary part.
+impl From<f64> for Complex {
+    fn from(value: f64) -> Self {
+        Self::new(value, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        // Multiply both sides by the conjugate of `rhs`, so the denominator is real
+        let denominator = rhs.re * rhs.re + rhs.im * rhs.im;
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.re, -self.im)
+    }
+}
+
+/// Complex numbers are shown like `a + bi`. Zero parts are left out,
+/// so `2i` and `3` are shown as is, and `1i` is shown as `i`.
+impl Display for Complex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        /// Formats an imaginary part without its sign.
+        fn imaginary(im: f64) -> String {
+            if im.abs() == 1.0 {
+                "i".to_string()
+            } else {
+                format!("{}i", im.abs())
+            }
+        }
+
+        match (self.re, self.im) {
+            (re, 0.0) => write!(f, "{re}"),
+            (0.0, im) => {
+                let sign = if im < 0.0 { "-" } else { "" };
+                write!(f, "{sign}{}", imaginary(im))
+            }
+            (re, im) => {
+                let sign = if im < 0.0 { "-" } else { "+" };
+                write!(f, "{re} {sign} {}", imaginary(im))
+            }
+        }
+    }
+}
+
+/// Tests for complex numbers.
+#[cfg(test)]
+mod tests {
+    use crate::complex::Complex;
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+        assert_eq!(Complex::new(4.0, 1.0), a + b);
+        assert_eq!(Complex::new(-2.0, 3.0), a - b);
+        assert_eq!(Complex::new(5.0, 5.0), a * b);
+        assert_eq!(Complex::new(0.1, 0.7), a / b);
+        assert_eq!(Complex::new(-1.0, -2.0), -a);
+        assert_eq!(Complex::from(-1.0), Complex::I * Complex::I);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(Complex::I, Complex::from(-1.0).sqrt());
+        assert_eq!(Complex::new(0.0, 2.0), Complex::from(-4.0).sqrt());
+        assert_eq!(Complex::from(3.0), Complex::from(9.0).sqrt());
+        // The principal root of `-2i` is `1 - i`.
+        assert_eq!(Complex::new(1.0, -1.0), Complex::new(0.0, -2.0).sqrt());
+    }
+
+    #[test]
+    fn test_powers() {
+        assert_eq!(Complex::new(-3.0, 4.0), Complex::new(1.0, 2.0).powi(2));
+        assert_eq!(Complex::from(1.0), Complex::I.powi(4));
+        assert_eq!(Complex::new(0.0, -1.0), Complex::I.powi(-1));
+        // `i^i` is real.
+        let i_to_the_i = Complex::I.powc(Complex::I);
+        assert!((i_to_the_i.re - (-std::f64::consts::FRAC_PI_2).exp()).abs() < 1e-15);
+        assert!(i_to_the_i.im.abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("5 + 5i", Complex::new(5.0, 5.0).to_string());
+        assert_eq!("1 - i", Complex::new(1.0, -1.0).to_string());
+        assert_eq!("2i", Complex::new(0.0, 2.0).to_string());
+        assert_eq!("-i", Complex::new(0.0, -1.0).to_string());
+        assert_eq!("-0.5", Complex::from(-0.5).to_string());
+    }
+}
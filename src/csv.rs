@@ -0,0 +1,55 @@
+//! CSV output, used with the `--csv` flag so that the results can be
+//! opened in a spreadsheet.
+//!
+//! Every result is an `input,result` row, like `1 + 2,3`. Errors are in the
+//! result column, like `1 / x,error: unknown variable `x``.
+
+/// Formats fields as a CSV row.
+pub fn row(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|field| self::field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Formats a single field. Fields containing commas, quotes or line breaks
+/// are quoted, with the quotes inside doubled.
+fn field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Tests for CSV output.
+#[cfg(test)]
+mod tests {
+    use crate::csv::row;
+
+    #[test]
+    fn test_row() {
+        assert_eq!("1 + 2,3", row(&["1 + 2", "3"]));
+        assert_eq!("", row(&[]));
+    }
+
+    #[test]
+    fn test_row_quotes() {
+        assert_eq!("\"max(1, 2)\",2", row(&["max(1, 2)", "2"]));
+        assert_eq!("1000000,\"1,000,000\"", row(&["1000000", "1,000,000"]));
+        assert_eq!("\"say \"\"hi\"\"\",x", row(&["say \"hi\"", "x"]));
+    }
+
+    #[test]
+    fn test_error_row() {
+        assert_eq!(
+            "1/x,error: unknown variable `x`",
+            row(&["1/x", "error: unknown variable `x`"])
+        );
+        assert_eq!(
+            "\"inv(1, 2)\",\"error: expected 1 argument, found 2\"",
+            row(&["inv(1, 2)", "error: expected 1 argument, found 2"])
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Diagnostics describe what went wrong in an input, and where.
+//! They are plain data, so other programs (like an editor integration)
+//! can show errors their own way. The calculator prompt renders them
+//! in color in `main.rs`.
+
+use std::fmt::Display;
+
+use crate::{
+    parser::ParserError,
+    random::MAX_SEED,
+    tokenizer::{Span, Tokenizer, TokenizerError},
+};
+
+/// How serious a diagnostic is.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    /// The input can't be executed.
+    Error,
+    /// The input can be executed, but it might not do what the user wants.
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message about a location in the source input.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// What went wrong, without the severity, like "unknown variable `x`".
+    pub message: String,
+    /// Where it went wrong in the source input.
+    pub span: Span,
+    /// How serious it is.
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    /// Creates the diagnostic of a parser error. Errors found at the end of
+    /// the input point to its last character.
+    pub fn from_parser_error(error: ParserError, input: &str) -> Self {
+        let (message, span) = match error {
+            ParserError::UnrecognizedSpecial(span) => (
+                format!(
+                    "expected one of `?quit`, `?bits`, `?ulp`, `?explain`, `?copy`, `?sci`, `?digits`, `?time`, `?roman`, `?precision`, `?complex`, `?fixedpoint`, `?last`, `?money`, `?diff`, `?eps`, `?approx`, `?total`, `?eq`, `?autoclose`, `?seed`, `?sep`, `?int`, `?settings`, `?bigint`, `?export`, `?overflow`, `?profile`, `?format_marks`, `?store`, `?relerr`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedBinaryOp(span) => (
+                format!(
+                    "expected an operator like `+`, `^`, `!`, `<` or `and`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedExprStart(span) => (
+                format!(
+                    "expected one of `-`, `not`, `√`, `(`, or a number, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::BinaryOperatorAtStart(_, span) => (
+                format!(
+                    "binary operator `{}` cannot start an expression",
+                    &input[span]
+                ),
+                span,
+            ),
+            ParserError::UnclosedParenthesis(span) => (
+                format!("expected `)`, found `{}`", spanned_value(input, span)),
+                unwrap_span(input, span),
+            ),
+            ParserError::UnexpectedCloseParenthesis(span) => {
+                ("found `)` without a matching `(`".to_string(), span)
+            }
+            ParserError::TrailingTokens(span) => (
+                format!(
+                    "expected an operator or the end of the input, found `{}`",
+                    &input[span]
+                ),
+                span,
+            ),
+            ParserError::Tokenizer(TokenizerError::UnrecognizedCharacter(span)) => {
+                (format!("unrecognized character `{}`", &input[span]), span)
+            }
+            ParserError::Tokenizer(TokenizerError::NumberOverflow(span)) => (
+                "this number is too large to be represented".to_string(),
+                span,
+            ),
+            ParserError::Tokenizer(TokenizerError::MalformedNumber(span)) => {
+                ("this number is malformed".to_string(), span)
+            }
+            ParserError::Tokenizer(TokenizerError::UnterminatedText(span)) => {
+                ("expected a closing `\"`".to_string(), span)
+            }
+            ParserError::Tokenizer(TokenizerError::InvalidPlaceholder(span)) => (
+                format!(
+                    "invalid placeholder `{}`, placeholders are numbered from `$1`",
+                    &input[span]
+                ),
+                span,
+            ),
+            ParserError::EmptyInput => (
+                "expected an expression, found nothing".to_string(),
+                unwrap_span(input, None),
+            ),
+            ParserError::ExpectedToggle(span) => (
+                format!(
+                    "expected `on` or `off`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedKeyword(span, keyword) => (
+                format!(
+                    "expected `{keyword}`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedIntegerInRange(span, range) => (
+                format!(
+                    "expected an integer from {} to {}, found `{}`",
+                    range.start(),
+                    range.end(),
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedSeed(span) => (
+                format!(
+                    "expected a seed, an integer from 0 to {MAX_SEED}, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedIntegerInRangeOrOff(span, range) => (
+                format!(
+                    "expected an integer from {} to {} or `off`, found `{}`",
+                    range.start(),
+                    range.end(),
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedOverflowPolicy(span) => (
+                format!(
+                    "expected `allow`, `warn` or `error`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedSeparator(span) => (
+                format!(
+                    "expected a separator, `,` or `_`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedMark(span) => (
+                format!(
+                    "expected a mark, `,`, `.` or `_`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::SameMarks(span) => (
+                "the thousands separator and the decimal mark must be different".to_string(),
+                span,
+            ),
+            ParserError::ExpectedNumber(span) => (
+                format!("expected a number, found `{}`", spanned_value(input, span)),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedFunctionName(span) => (
+                format!(
+                    "expected a function name after `|>`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ImaginaryOutsideComplexMode(span) => (
+                format!(
+                    "`{}` is imaginary, but complex mode is off; turn it on with `?complex on`",
+                    &input[span]
+                ),
+                span,
+            ),
+            ParserError::NonIntegerLiteral(span) => (
+                format!(
+                    "`{}` isn't an integer, but integer mode is on; turn it off with `?int off`",
+                    &input[span]
+                ),
+                span,
+            ),
+            ParserError::InvalidRomanNumeral(span) => (
+                format!("`{}` is not a valid Roman numeral", &input[span]),
+                span,
+            ),
+            ParserError::InvalidAssignmentTarget(span) => (
+                format!(
+                    "expected a variable on the left of `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+            ParserError::ExpectedConstantDefinition(span) => (
+                format!(
+                    "expected a definition like `const g = 9.81`, found `{}`",
+                    spanned_value(input, span)
+                ),
+                unwrap_span(input, span),
+            ),
+        };
+
+        Self {
+            message,
+            span,
+            severity: Severity::Error,
+        }
+    }
+}
+
+/// Creates a diagnostic for every token of the input that couldn't be created,
+/// like unrecognized characters. Unlike parsing, which stops at the first
+/// error, this reports all of them at once.
+pub fn collect_lex_errors(input: &str) -> Vec<Diagnostic> {
+    Tokenizer::new(input)
+        .tokenize()
+        .filter_map(Result::err)
+        .map(|error| Diagnostic::from_parser_error(ParserError::Tokenizer(error), input))
+        .collect()
+}
+
+/// Gets the string the the span points to.
+/// If the span is `None`, returns `"<EOL>"` (end of line) instead
+fn spanned_value(input: &str, span: Option<Span>) -> &str {
+    span.map(|span| &input[span]).unwrap_or("<EOL>")
+}
+
+/// Unwraps an optional span. If the option was `None`,
+/// creates a span of the last character of the input instead.
+fn unwrap_span(input: &str, span: Option<Span>) -> Span {
+    span.unwrap_or(Span {
+        start: input.len().saturating_sub(1),
+        end: input.len(),
+    })
+}
+
+/// Tests for diagnostics.
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostic::{collect_lex_errors, Diagnostic, Severity},
+        parser::Parser,
+        tokenizer::Span,
+    };
+
+    /// Parses an invalid input and creates the diagnostic of its error.
+    fn diagnose(input: &str) -> Diagnostic {
+        match Parser::new(input).roman(true).parse() {
+            Err(error) => Diagnostic::from_parser_error(error, input),
+            Ok(_) => panic!("`{input}` is valid"),
+        }
+    }
+
+    /// Creates an error diagnostic.
+    fn error(message: &str, span: Span) -> Diagnostic {
+        Diagnostic {
+            message: message.to_string(),
+            span,
+            severity: Severity::Error,
+        }
+    }
+
+    #[test]
+    fn test_unrecognized_special() {
+        let diagnostic = diagnose("?foo");
+        assert!(diagnostic.message.ends_with("found `?foo`"));
+        assert_eq!(Span::from(0..4), diagnostic.span);
+        assert_eq!(Severity::Error, diagnostic.severity);
+    }
+
+    #[test]
+    fn test_expected_binary_op() {
+        assert_eq!(
+            error(
+                "expected an operator like `+`, `^`, `!`, `<` or `and`, found `not`",
+                Span::from(2..5)
+            ),
+            diagnose("1 not 2")
+        );
+    }
+
+    #[test]
+    fn test_expected_expr_start() {
+        // The input ended, so the diagnostic points to the last character.
+        assert_eq!(
+            error(
+                "expected one of `-`, `not`, `√`, `(`, or a number, found `<EOL>`",
+                Span::from(2..3)
+            ),
+            diagnose("1 +")
+        );
+    }
+
+    #[test]
+    fn test_binary_operator_at_start() {
+        assert_eq!(
+            error(
+                "binary operator `*` cannot start an expression",
+                Span::from(0..1)
+            ),
+            diagnose("* 3")
+        );
+    }
+
+    #[test]
+    fn test_unclosed_parenthesis() {
+        assert_eq!(
+            error("expected `)`, found `<EOL>`", Span::from(1..2)),
+            diagnose("(1")
+        );
+    }
+
+    #[test]
+    fn test_unexpected_close_parenthesis() {
+        assert_eq!(
+            error("found `)` without a matching `(`", Span::from(1..2)),
+            diagnose("1)")
+        );
+    }
+
+    #[test]
+    fn test_trailing_tokens() {
+        assert_eq!(
+            error(
+                "expected an operator or the end of the input, found `2`",
+                Span::from(2..3)
+            ),
+            diagnose("1 2")
+        );
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        assert_eq!(
+            error("expected a variable on the left of `=`", Span::from(2..3)),
+            diagnose("1 = 2")
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_errors() {
+        assert_eq!(
+            error("unrecognized character `@`", Span::from(2..3)),
+            diagnose("1 @")
+        );
+        assert_eq!(
+            error(
+                "this number is too large to be represented",
+                Span::from(0..400)
+            ),
+            diagnose(&"9".repeat(400))
+        );
+        assert_eq!(
+            error("this number is malformed", Span::from(0..2)),
+            diagnose("0x")
+        );
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let input = "";
+        let parser_error = Parser::new(input).parse_expression().unwrap_err();
+        assert_eq!(
+            error("expected an expression, found nothing", Span::from(0..0)),
+            Diagnostic::from_parser_error(parser_error, input)
+        );
+    }
+
+    #[test]
+    fn test_expected_toggle() {
+        assert_eq!(
+            error("expected `on` or `off`, found `yes`", Span::from(5..8)),
+            diagnose("?sci yes")
+        );
+    }
+
+    #[test]
+    fn test_expected_keyword() {
+        assert_eq!(
+            error("expected `auto`, found `max`", Span::from(11..14)),
+            diagnose("?precision max")
+        );
+    }
+
+    #[test]
+    fn test_expected_integer_in_range() {
+        assert_eq!(
+            error(
+                "expected an integer from 1 to 17, found `99`",
+                Span::from(8..10)
+            ),
+            diagnose("?digits 99")
+        );
+        assert_eq!(
+            error(
+                "expected an integer from 0 to 17 or `off`, found `on`",
+                Span::from(12..14)
+            ),
+            diagnose("?fixedpoint on")
+        );
+        assert_eq!(
+            error(
+                "expected a seed, an integer from 0 to 9007199254740992, found `-`",
+                Span::from(6..7)
+            ),
+            diagnose("?seed -1")
+        );
+    }
+
+    #[test]
+    fn test_invalid_roman_numeral() {
+        assert_eq!(
+            error("`IIII` is not a valid Roman numeral", Span::from(0..4)),
+            diagnose("IIII")
+        );
+    }
+
+    #[test]
+    fn test_expected_constant_definition() {
+        assert_eq!(
+            error(
+                "expected a definition like `const g = 9.81`, found `+=`",
+                Span::from(8..10)
+            ),
+            diagnose("const g += 1")
+        );
+    }
+
+    #[test]
+    fn test_invalid_placeholder() {
+        assert_eq!(
+            error(
+                "invalid placeholder `$0`, placeholders are numbered from `$1`",
+                Span::from(4..6)
+            ),
+            diagnose("1 + $0")
+        );
+    }
+
+    #[test]
+    fn test_collect_lex_errors() {
+        assert_eq!(
+            vec![
+                error("unrecognized character `@`", Span::from(2..3)),
+                error("unrecognized character `$`", Span::from(6..7)),
+            ],
+            collect_lex_errors("1 @ 2 $ 3")
+        );
+        assert!(collect_lex_errors("1 + 2").is_empty());
+    }
+
+    #[test]
+    fn test_imaginary_outside_complex_mode() {
+        assert_eq!(
+            error(
+                "`2i` is imaginary, but complex mode is off; turn it on with `?complex on`",
+                Span::from(4..6)
+            ),
+            diagnose("1 + 2i")
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Explanations of how an expression was evaluated, step by step.
+//! This is a teaching tool: it shows which operations are evaluated
+//! first because of precedence or parentheses, and what they evaluate to.
+
+use crate::{
+    parser::{BinaryOperation, Expression, ExpressionKind, PrecedenceTable, UnaryOperation},
+    runtime::{
+        binary, call_in, from_bool, to_bool, unary, unknown_variable, Environment, OverflowPolicy,
+        RuntimeError,
+    },
+};
+
+/// Explains how an expression is evaluated, one step per operation, in the
+/// order they are evaluated. The environment is the one the expression was
+/// evaluated in. Assignments modify it, like during a normal evaluation.
+/// An operator that overflows is an error, so the explanation stops there.
+pub fn explain(expr: &Expression, env: &mut Environment) -> Result<Vec<String>, RuntimeError> {
+    let mut steps = Vec::new();
+    let value = explain_node(expr, None, env, &mut steps)?;
+    // There is nothing to evaluate in a lone number
+    if steps.is_empty() {
+        steps.push(format!("{value} is already a number"));
+    }
+
+    Ok(steps)
+}
+
+/// Recursively evaluates an expression, adding a step for every operation.
+/// `parent` is the binary operation this expression is an operand of, if any.
+fn explain_node(
+    expr: &Expression,
+    parent: Option<&BinaryOperation>,
+    env: &mut Environment,
+    steps: &mut Vec<String>,
+) -> Result<f64, RuntimeError> {
+    let (step, value) = match expr.kind() {
+        ExpressionKind::Binary {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let lhs = explain_node(lhs, Some(operation), env, steps)?;
+            let rhs = explain_node(rhs, Some(operation), env, steps)?;
+            let value = binary(operation, lhs, rhs, OverflowPolicy::Error, expr.span)?;
+            let mut step = match operation {
+                BinaryOperation::AddPercentage => format!("{lhs} + {rhs}% = {value}"),
+                BinaryOperation::SubtractPercentage => format!("{lhs} - {rhs}% = {value}"),
+                _ => format!("{lhs} {} {rhs} = {value}", operation.symbol()),
+            };
+            if let Some(reason) = parent.and_then(|parent| evaluated_first(operation, parent)) {
+                step.push_str(&format!(" ({reason})"));
+            }
+            (step, value)
+        }
+        // Every comparison is a step, and so is the whole chain
+        ExpressionKind::Comparisons { first, comparisons } => {
+            let parent = comparisons.first().map(|(operation, _)| operation);
+            let mut lhs = explain_node(first, parent, env, steps)?;
+            let mut chain = lhs.to_string();
+            let mut all_true = true;
+            for (operation, operand) in comparisons {
+                let rhs = explain_node(operand, Some(operation), env, steps)?;
+                let value = binary(operation, lhs, rhs, OverflowPolicy::Error, expr.span)?;
+                steps.push(format!("{lhs} {} {rhs} = {value}", operation.symbol()));
+                chain.push_str(&format!(" {} {rhs}", operation.symbol()));
+                all_true &= to_bool(value);
+                lhs = rhs;
+            }
+            let value = from_bool(all_true);
+            (format!("{chain} = {value}"), value)
+        }
+        ExpressionKind::Unary { operation, operand } => {
+            let operand = explain_node(operand, None, env, steps)?;
+            let value = unary(operation, operand, OverflowPolicy::Error, expr.span)?;
+            let step = match operation {
+                UnaryOperation::Negation => format!("-({operand}) = {value}"),
+                UnaryOperation::Not => format!("not {operand} = {value}"),
+                UnaryOperation::Factorial => format!("{operand}! = {value}"),
+                UnaryOperation::Percent => format!("{operand}% = {value}"),
+                UnaryOperation::SquareRoot => format!("√{operand} = {value}"),
+            };
+            (step, value)
+        }
+        // Some arguments aren't values, like the expression and the variable
+        // of `sum`, so these calls are evaluated like usual, in a single step
+        ExpressionKind::Call { name, .. }
+            if matches!(
+                name.as_str(),
+                "sum" | "product" | "reduce" | "iterate" | "fmt" | "table"
+            ) =>
+        {
+            let value = expr.eval_in(env)?;
+            (format!("{expr} = {value}"), value)
+        }
+        ExpressionKind::Call { name, arguments } => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| explain_node(argument, None, env, steps))
+                .collect::<Result<Vec<_>, _>>()?;
+            let value = call_in(name, &arguments, env, expr.span)?;
+            let arguments = arguments
+                .iter()
+                .map(f64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            (format!("{name}({arguments}) = {value}"), value)
+        }
+        ExpressionKind::Assignment {
+            name,
+            value,
+            constant,
+        } => {
+            let value = env.assign(name, *constant, expr.span, |env| {
+                explain_node(value, None, env, steps)
+            })?;
+            (format!("{name} is now {value}"), value)
+        }
+        ExpressionKind::Variable(name) => match env.get(name) {
+            Some(value) => (format!("{name} is {value}"), value),
+            None => return Err(unknown_variable(name, expr.span)),
+        },
+        // Numbers don't need to be evaluated, so they aren't a step
+        ExpressionKind::Atom(num) => return Ok(num.0),
+        // Explanations only work with real numbers
+        ExpressionKind::Imaginary(_) => return Err(RuntimeError::ExpectedReal(expr.span)),
+        ExpressionKind::Text(_) => return Err(RuntimeError::UnexpectedText(expr.span)),
+        // Placeholders need template arguments, which explanations don't have
+        ExpressionKind::Placeholder(index) => {
+            return Err(RuntimeError::MissingArgument(*index, expr.span))
+        }
+    };
+
+    steps.push(step);
+    Ok(value)
+}
+
+/// Explains why an operation is evaluated before its parent operation, if
+/// it isn't obvious. Operations with the same precedence are evaluated in
+/// reading order, so they don't need an explanation.
+fn evaluated_first(operation: &BinaryOperation, parent: &BinaryOperation) -> Option<String> {
+    let (precedence, _) = PrecedenceTable::DEFAULT.infix(operation);
+    let (parent_precedence, _) = PrecedenceTable::DEFAULT.infix(parent);
+    if precedence > parent_precedence {
+        Some(format!(
+            "{} binds tighter than {}, so it is evaluated first",
+            name(operation),
+            name(parent)
+        ))
+    } else if precedence < parent_precedence {
+        // A looser operation can only be an operand if it was in parentheses
+        Some("in parentheses, so it is evaluated first".to_string())
+    } else {
+        None
+    }
+}
+
+/// The name of a binary operation, like "addition".
+fn name(operation: &BinaryOperation) -> &'static str {
+    match operation {
+        BinaryOperation::Addition | BinaryOperation::AddPercentage => "addition",
+        BinaryOperation::Subtraction | BinaryOperation::SubtractPercentage => "subtraction",
+        BinaryOperation::Multiplication => "multiplication",
+        BinaryOperation::Division => "division",
+        BinaryOperation::Exponentiation => "exponentiation",
+        BinaryOperation::Less
+        | BinaryOperation::LessOrEqual
+        | BinaryOperation::Greater
+        | BinaryOperation::GreaterOrEqual
+        | BinaryOperation::Equal
+        | BinaryOperation::NotEqual => "comparison",
+        BinaryOperation::And => "`and`",
+        BinaryOperation::Or => "`or`",
+        BinaryOperation::Minimum => "minimum",
+        BinaryOperation::Maximum => "maximum",
+    }
+}
+
+/// Tests for explanations.
+#[cfg(test)]
+mod tests {
+    use crate::{
+        explain::explain,
+        parser::Parser,
+        runtime::{Environment, RuntimeError},
+    };
+
+    /// Explains an input expression in the given environment.
+    fn explain_in(input: &str, env: &mut Environment) -> Result<Vec<String>, RuntimeError> {
+        let expr = Parser::new(input)
+            .parse_expression()
+            .unwrap_or_else(|_| panic!("`{input}` is not a valid expression"));
+        explain(&expr, env)
+    }
+
+    /// Explains an input expression in a new environment.
+    fn explain_input(input: &str) -> Vec<String> {
+        explain_in(input, &mut Environment::default()).unwrap()
+    }
+
+    #[test]
+    fn test_explain_precedence() {
+        assert_eq!(
+            vec![
+                "3 * 4 = 12 (multiplication binds tighter than addition, so it is evaluated first)",
+                "2 + 12 = 14",
+            ],
+            explain_input("2 + 3 * 4")
+        );
+    }
+
+    #[test]
+    fn test_explain_parentheses() {
+        assert_eq!(
+            vec![
+                "2 + 3 = 5 (in parentheses, so it is evaluated first)",
+                "5 * 4 = 20",
+            ],
+            explain_input("(2 + 3) * 4")
+        );
+    }
+
+    #[test]
+    fn test_explain_same_precedence() {
+        assert_eq!(vec!["10 - 3 = 7", "7 - 2 = 5"], explain_input("10 - 3 - 2"));
+    }
+
+    #[test]
+    fn test_explain_chained_comparisons() {
+        let mut env = Environment::default();
+        env.set("x".to_string(), 0.0);
+        assert_eq!(
+            Ok(vec![
+                "x is 0".to_string(),
+                "0 + 1 = 1".to_string(),
+                "x is now 1".to_string(),
+                "0 < 1 = 1".to_string(),
+                "1 < 10 = 1".to_string(),
+                "0 < 1 < 10 = 1".to_string(),
+            ]),
+            explain_in("0 < (x += 1) < 10", &mut env)
+        );
+        assert_eq!(Some(1.0), env.get("x"));
+    }
+
+    #[test]
+    fn test_explain_unary_and_calls() {
+        assert_eq!(
+            vec!["3! = 6", "-(6) = -6", "inv(4) = 0.25", "-6 + 0.25 = -5.75",],
+            explain_input("-3! + inv(4)")
+        );
+    }
+
+    #[test]
+    fn test_explain_series() {
+        assert_eq!(
+            vec!["sum(i, i, 1, 3) = 6", "6 * 2 = 12"],
+            explain_input("sum(i, i, 1, 3) * 2")
+        );
+        assert_eq!(
+            vec!["product((i ^ 2), i, 1, 3) = 36"],
+            explain_input("product(i^2, i, 1, 3)")
+        );
+        assert_eq!(
+            vec![
+                r#"reduce("+", 1, 2) = 3"#,
+                "iterate(sqrt, 16, 2) = 2",
+                "3 + 2 = 5"
+            ],
+            explain_input(r#"reduce("+", 1, 2) + iterate(sqrt, 16, 2)"#)
+        );
+        // The variable of the series is restored afterwards.
+        let mut env = Environment::default();
+        env.set("i".to_string(), 10.0);
+        explain_in("sum(i, i, 1, 3)", &mut env).unwrap();
+        assert_eq!(Some(10.0), env.get("i"));
+    }
+
+    #[test]
+    fn test_explain_variables() {
+        let mut env = Environment::default();
+        env.set("x".to_string(), 3.0);
+        assert_eq!(
+            Ok(vec![
+                "x is 3".to_string(),
+                "3 * 2 = 6".to_string(),
+                "y is now 6".to_string(),
+            ]),
+            explain_in("y = x * 2", &mut env)
+        );
+        assert_eq!(Some(6.0), env.get("y"));
+    }
+
+    #[test]
+    fn test_explain_percentage() {
+        assert_eq!(vec!["200 + 10% = 220"], explain_input("200 + 10%"));
+        assert_eq!(
+            vec!["10% = 0.1", "200 * 0.1 = 20"],
+            explain_input("200 * 10%")
+        );
+    }
+
+    #[test]
+    fn test_explain_number() {
+        assert_eq!(vec!["42 is already a number"], explain_input("42"));
+    }
+
+    #[test]
+    fn test_explain_error() {
+        assert!(matches!(
+            explain_in("1 + 171!", &mut Environment::default()),
+            Err(RuntimeError::Overflow(_))
+        ));
+    }
+}
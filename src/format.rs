@@ -0,0 +1,682 @@
+//! Formatting of results for display.
+
+use std::{
+    fmt::Display,
+    iter::{repeat, Peekable},
+    str::Chars,
+    time::Duration,
+};
+
+/// The smallest number of significant digits in scientific notation.
+pub const MIN_DIGITS: usize = 1;
+
+/// The largest number of significant digits in scientific notation.
+/// An `f64` never needs more than 17 significant digits to be exact.
+pub const MAX_DIGITS: usize = 17;
+
+/// The largest number of decimals a single result can be rounded to with `~N`.
+pub const MAX_DECIMALS: usize = 17;
+
+/// The largest width and precision of a conversion of `printf`, like `%.2f`.
+pub const MAX_CONVERSION_WIDTH: usize = 100;
+
+/// How results are formatted.
+/// By default, results use the shortest representation that reads back
+/// to the same number, which is what `f64`'s `Display` does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    /// Whether results are shown in scientific notation.
+    pub scientific: bool,
+    /// The number of significant digits in scientific notation.
+    /// If `None`, uses as many digits as needed to be exact.
+    pub digits: Option<usize>,
+    /// Whether results are shown like amounts of money, like `1,234.57`.
+    /// This takes priority over scientific notation.
+    pub money: bool,
+    /// The separator between groups of thousands in amounts of money,
+    /// and in other results if they're grouped.
+    pub separator: char,
+    /// The mark between the integer part and the fraction, like `,` in `1,5`.
+    pub decimal_mark: char,
+    /// Whether results that aren't amounts of money are grouped too.
+    pub grouped: bool,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            scientific: false,
+            digits: None,
+            money: false,
+            separator: ',',
+            decimal_mark: '.',
+            grouped: false,
+        }
+    }
+}
+
+impl Format {
+    /// Formats a result. Negative zero keeps its sign, like `-0`, since it can
+    /// evaluate differently than `0`. Amounts of money never show it, since
+    /// `-0.00` isn't an amount anyone owes.
+    pub fn format(&self, value: f64) -> String {
+        if self.money {
+            money(value, self.separator, self.decimal_mark)
+        } else if self.scientific {
+            with_marks(
+                &scientific(value, self.digits),
+                self.separator,
+                self.decimal_mark,
+            )
+        } else if self.grouped {
+            with_marks(&value.to_string(), self.separator, self.decimal_mark)
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+/// Formats a value in scientific notation, like `1.23e4`, with the
+/// given number of significant digits.
+pub fn scientific(value: f64, digits: Option<usize>) -> String {
+    match digits {
+        // The digit before the dot is significant too
+        Some(digits) => format!("{:.*e}", digits - 1, value),
+        None => format!("{value:e}"),
+    }
+}
+
+/// Formats a value rounded to the given number of decimals, like `0.33`.
+pub fn fixed(value: f64, decimals: usize) -> String {
+    format!("{value:.decimals$}")
+}
+
+/// Formats a value with a printf-style format, like `%.2f`, which is what
+/// `fmt` shows. The format has exactly one conversion, with any text around
+/// it, and `%%` is a percent sign. A conversion is made of, in order:
+/// * `%`,
+/// * flags, `-` to align left, or `0` to pad with zeros instead of spaces,
+/// * a width, the smallest number of characters, which is 0 if there is none,
+/// * a precision, like `.2`, which is 6 if there is none,
+/// * `f` for a fixed number of decimals, like `3.14`, `e` for scientific
+///   notation, like `3.14e+00`, or `g` for the shorter of both, with the
+///   precision as the number of significant digits and no trailing zeros.
+///
+/// Returns `None` if the format isn't valid.
+pub fn printf(value: f64, format: &str) -> Option<String> {
+    let mut formatted = String::new();
+    let mut converted = false;
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            formatted.push('%');
+            continue;
+        }
+        if converted {
+            return None;
+        }
+        converted = true;
+
+        let (mut left, mut zeros) = (false, false);
+        while let Some(flag) = chars.next_if(|c| matches!(c, '-' | '0')) {
+            match flag {
+                '-' => left = true,
+                _ => zeros = true,
+            }
+        }
+        let width = conversion_number(&mut chars);
+        let precision = match chars.next_if_eq(&'.') {
+            Some(_) => conversion_number(&mut chars),
+            None => 6,
+        };
+        if width > MAX_CONVERSION_WIDTH || precision > MAX_CONVERSION_WIDTH {
+            return None;
+        }
+        let number = match chars.next()? {
+            'f' => format!("{value:.precision$}"),
+            'e' => c_exponent(&format!("{value:.precision$e}")),
+            'g' => general(value, precision),
+            _ => return None,
+        };
+        formatted.push_str(&pad(&number, width, left, zeros));
+    }
+    converted.then_some(formatted)
+}
+
+/// Reads the width or the precision of a conversion, which is 0 if there
+/// are no digits. Numbers too large for a `usize` saturate.
+fn conversion_number(chars: &mut Peekable<Chars>) -> usize {
+    let mut number = 0usize;
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        let digit = digit as usize - '0' as usize;
+        number = number.saturating_mul(10).saturating_add(digit);
+    }
+    number
+}
+
+/// Writes the exponent of scientific notation like C does, with a sign and
+/// at least 2 digits, so `1.5e3` is `1.5e+03`.
+fn c_exponent(number: &str) -> String {
+    // Infinite and `NaN` values have no exponent
+    let Some((mantissa, exponent)) = number.split_once('e') else {
+        return number.to_string();
+    };
+    let (sign, digits) = match exponent.strip_prefix('-') {
+        Some(digits) => ('-', digits),
+        None => ('+', exponent),
+    };
+    format!("{mantissa}e{sign}{digits:0>2}")
+}
+
+/// Formats a value like the `g` conversion of C: with the given number of
+/// significant digits, in scientific notation if the exponent is less than -4
+/// or at least the number of digits, and without trailing zeros.
+fn general(value: f64, digits: usize) -> String {
+    let digits = digits.max(1);
+    // The exponent after rounding, so `9.99` with 2 digits has the exponent of `10`
+    let scientific = format!("{value:.*e}", digits - 1);
+    let Some((_, exponent)) = scientific.split_once('e') else {
+        return scientific;
+    };
+    let exponent = exponent.parse::<i64>().unwrap_or_default();
+    let number = if exponent < -4 || exponent >= digits as i64 {
+        c_exponent(&scientific)
+    } else {
+        format!("{value:.*}", (digits as i64 - 1 - exponent) as usize)
+    };
+
+    let (mantissa, exponent) = number.split_at(number.find('e').unwrap_or(number.len()));
+    let mantissa = match mantissa.contains('.') {
+        true => mantissa.trim_end_matches('0').trim_end_matches('.'),
+        false => mantissa,
+    };
+    format!("{mantissa}{exponent}")
+}
+
+/// Pads a formatted number to the given width, with spaces on the left, or
+/// on the right if `left` is true. With `zeros`, it's padded with zeros after
+/// the sign instead, unless it's infinite or `NaN`.
+fn pad(number: &str, width: usize, left: bool, zeros: bool) -> String {
+    let padding = width.saturating_sub(number.chars().count());
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", number),
+    };
+    if left {
+        format!("{number}{}", " ".repeat(padding))
+    } else if zeros && digits.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("{sign}{}{digits}", "0".repeat(padding))
+    } else {
+        format!("{}{number}", " ".repeat(padding))
+    }
+}
+
+/// Formats a value like an amount of money, like `-1,234.57`: rounded to
+/// 2 decimals with banker's rounding, with the separator between groups of
+/// thousands and the decimal mark before the cents.
+pub fn money(value: f64, separator: char, decimal_mark: char) -> String {
+    with_marks(&round_half_even(value, 2), separator, decimal_mark)
+}
+
+/// Puts the marks in a number written with a dot, like `-1234567.89`: the
+/// separator between groups of thousands of the integer part, and the decimal
+/// mark instead of the dot, so it's `-1.234.567,89` with `.` and `,`.
+///
+/// The sign stays in front of the first group. Numbers without a fraction,
+/// like `1234`, have no decimal mark, and the exponent of scientific notation,
+/// like in `1.5e10`, isn't grouped.
+pub fn with_marks(number: &str, separator: char, decimal_mark: char) -> String {
+    let (sign, number) = match number.strip_prefix('-') {
+        Some(number) => ("-", number),
+        None => ("", number),
+    };
+    let (mantissa, exponent) = number.split_at(number.find('e').unwrap_or(number.len()));
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+
+    let mut marked = sign.to_string();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            marked.push(separator);
+        }
+        marked.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        marked.push(decimal_mark);
+        marked.push_str(fraction);
+    }
+    marked.push_str(exponent);
+    marked
+}
+
+/// Formats a value rounded to the given number of decimals, where ties are
+/// rounded to the closest even digit. This is also called banker's rounding:
+/// `0.5` is `0`, `1.5` is `2` and `2.5` is `2`, so rounding errors cancel out.
+///
+/// Ties are decided on the shortest representation of the value, which is
+/// what the user sees. So `2.675` is a tie rounded to `2.68`, even though
+/// the closest float is slightly below `2.675`.
+pub fn round_half_even(value: f64, decimals: usize) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    // The digits of the value, with exactly `decimals` digits after the dot
+    let shortest = value.abs().to_string();
+    let (integer, fraction) = shortest.split_once('.').unwrap_or((&shortest, ""));
+    let kept_fraction = fraction.bytes().chain(repeat(b'0')).take(decimals);
+    let mut digits = integer
+        .bytes()
+        .chain(kept_fraction)
+        .map(|digit| digit - b'0')
+        .collect::<Vec<_>>();
+
+    // Round up above a half, or at exactly a half if the last digit is odd
+    let round_up = match fraction.as_bytes().get(decimals..).unwrap_or_default() {
+        [] => false,
+        [first, rest @ ..] => {
+            *first > b'5'
+                || (*first == b'5'
+                    && (rest.iter().any(|digit| *digit != b'0')
+                        || digits.last().is_some_and(|digit| digit % 2 == 1)))
+        }
+    };
+    if round_up {
+        // Carry the one through the trailing nines, like in `9.995`
+        match digits.iter().rposition(|digit| *digit != 9) {
+            Some(position) => {
+                digits[position] += 1;
+                digits[position + 1..].fill(0);
+            }
+            None => {
+                digits.fill(0);
+                digits.insert(0, 1);
+            }
+        }
+    }
+
+    let sign = if value < 0.0 && digits.iter().any(|digit| *digit != 0) {
+        "-"
+    } else {
+        ""
+    };
+    let digits = digits
+        .iter()
+        .map(|digit| char::from(b'0' + digit))
+        .collect::<String>();
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    if fraction.is_empty() {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{fraction}")
+    }
+}
+
+/// Formats how long an input took to execute, like `took 1.23ms`.
+pub fn format_duration(duration: Duration) -> String {
+    format!("took {duration:.2?}")
+}
+
+/// The blocks of a sparkline, from the lowest value to the highest.
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Draws values as a one-line sparkline, like `▁▃█` for `0, 25, 100`, with
+/// blocks scaled from the smallest value to the largest. If every value is
+/// the same, the blocks are in the middle. Infinities and `NaN` are blanks.
+pub fn sparkline(values: &[f64]) -> String {
+    let finite = values.iter().copied().filter(|value| value.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, f64::min);
+    let max = finite.fold(f64::NEG_INFINITY, f64::max);
+    let highest = (SPARK_BLOCKS.len() - 1) as f64;
+    values
+        .iter()
+        .map(|&value| match value {
+            value if !value.is_finite() => ' ',
+            _ if min == max => SPARK_BLOCKS[SPARK_BLOCKS.len() / 2 - 1],
+            value => SPARK_BLOCKS[((value - min) / (max - min) * highest).round() as usize],
+        })
+        .collect()
+}
+
+/// The bit layout of an IEEE-754 double precision float.
+#[derive(Debug, PartialEq)]
+pub struct FloatBits {
+    /// The sign bit. `1` means negative.
+    pub sign: u64,
+    /// The 11 exponent bits. The exponent is biased by 1023.
+    pub exponent: u64,
+    /// The 52 mantissa bits (also called the fraction).
+    pub mantissa: u64,
+}
+
+impl FloatBits {
+    /// Splits a float into its sign, exponent and mantissa fields.
+    pub fn new(value: f64) -> Self {
+        let bits = value.to_bits();
+        Self {
+            sign: bits >> 63,
+            exponent: (bits >> 52) & 0x7ff,
+            mantissa: bits & 0xf_ffff_ffff_ffff,
+        }
+    }
+}
+
+impl Display for FloatBits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sign {:01b} | exponent {:011b} | mantissa {:052b}",
+            self.sign, self.exponent, self.mantissa
+        )
+    }
+}
+
+/// The unit in the last place (ulp) of a value. This is the gap between
+/// the value and the next float away from zero, so it tells how precise
+/// the value is. The ulp of the largest float is the gap below it instead,
+/// since there is no larger float.
+pub fn ulp(value: f64) -> f64 {
+    let value = value.abs();
+    if value == f64::MAX {
+        value - value.next_down()
+    } else {
+        value.next_up() - value
+    }
+}
+
+/// Tests for formatting.
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::format::{
+        fixed, format_duration, money, printf, round_half_even, scientific, sparkline, ulp,
+        with_marks, FloatBits, Format,
+    };
+
+    #[test]
+    fn test_negative_zero() {
+        assert_eq!("-0", Format::default().format(-0.0));
+        let scientific = Format {
+            scientific: true,
+            ..Format::default()
+        };
+        assert_eq!("-0e0", scientific.format(-0.0));
+        assert_eq!("-0.00", fixed(-0.0, 2));
+        let money = Format {
+            money: true,
+            ..Format::default()
+        };
+        assert_eq!("0.00", money.format(-0.0));
+    }
+
+    #[test]
+    fn test_bits_one() {
+        assert_eq!(
+            FloatBits {
+                sign: 0,
+                exponent: 1023,
+                mantissa: 0
+            },
+            FloatBits::new(1.0)
+        );
+    }
+
+    #[test]
+    fn test_bits_minus_two() {
+        assert_eq!(
+            FloatBits {
+                sign: 1,
+                exponent: 1024,
+                mantissa: 0
+            },
+            FloatBits::new(-2.0)
+        );
+    }
+
+    #[test]
+    fn test_bits_one_tenth() {
+        // 0.1 can't be represented exactly, its mantissa is a repeating pattern.
+        assert_eq!(
+            FloatBits {
+                sign: 0,
+                exponent: 1019,
+                mantissa: 0x9_9999_9999_999a
+            },
+            FloatBits::new(0.1)
+        );
+    }
+
+    #[test]
+    fn test_bits_display() {
+        assert_eq!(
+            format!(
+                "sign 0 | exponent 01111111111 | mantissa {}",
+                "0".repeat(52)
+            ),
+            FloatBits::new(1.0).to_string()
+        );
+    }
+
+    #[test]
+    fn test_ulp_one() {
+        assert_eq!(f64::EPSILON, ulp(1.0));
+        // The sign doesn't matter.
+        assert_eq!(f64::EPSILON, ulp(-1.0));
+    }
+
+    #[test]
+    fn test_ulp_zero() {
+        // The smallest subnormal number.
+        assert_eq!(f64::from_bits(1), ulp(0.0));
+    }
+
+    #[test]
+    fn test_ulp_large() {
+        // Above `2^53`, floats are more than one integer apart.
+        assert_eq!(2.0, ulp(2f64.powi(53)));
+        assert_eq!(2f64.powi(971), ulp(f64::MAX));
+    }
+
+    #[test]
+    fn test_scientific_digits() {
+        assert_eq!("1.23e4", scientific(12345.0, Some(3)));
+        assert_eq!("1e4", scientific(12345.0, Some(1)));
+        assert_eq!("1.2345000e4", scientific(12345.0, Some(8)));
+        assert_eq!("-4.6e-3", scientific(-0.00456, Some(2)));
+    }
+
+    #[test]
+    fn test_scientific_exact() {
+        assert_eq!("1.2345e4", scientific(12345.0, None));
+        assert_eq!("1e-1", scientific(0.1, None));
+    }
+
+    #[test]
+    fn test_round_half_even() {
+        // Ties go to the even digit.
+        assert_eq!("0", round_half_even(0.5, 0));
+        assert_eq!("2", round_half_even(1.5, 0));
+        assert_eq!("2", round_half_even(2.5, 0));
+        assert_eq!("-2", round_half_even(-2.5, 0));
+        // `2.675` is a tie, as written, even though its float is `2.67499999...`.
+        assert_eq!("2.68", round_half_even(2.675, 2));
+        assert_eq!("2.66", round_half_even(2.665, 2));
+        // Above a half always rounds up.
+        assert_eq!("2.67", round_half_even(2.6651, 2));
+        assert_eq!("10.00", round_half_even(9.995, 2));
+        assert_eq!("0.50", round_half_even(0.5, 2));
+        assert_eq!("0.00", round_half_even(-0.001, 2));
+    }
+
+    #[test]
+    fn test_money() {
+        assert_eq!("1,234,567.89", money(1234567.891, ',', '.'));
+        assert_eq!("-1,000.00", money(-999.999, ',', '.'));
+        assert_eq!("100.00", money(100.0, ',', '.'));
+        assert_eq!("0.12", money(0.125, ',', '.'));
+        assert_eq!("inf", money(f64::INFINITY, ',', '.'));
+    }
+
+    #[test]
+    fn test_money_separators() {
+        assert_eq!("1,000,000.00", money(1_000_000.0, ',', '.'));
+        assert_eq!("1_000_000.00", money(1_000_000.0, '_', '.'));
+        assert_eq!("-12_345.68", money(-12345.678, '_', '.'));
+        assert_eq!("1.000.000,00", money(1_000_000.0, '.', ','));
+        let format = Format {
+            money: true,
+            separator: '_',
+            ..Format::default()
+        };
+        assert_eq!("1_000_000.00", format.format(1e6));
+    }
+
+    #[test]
+    fn test_printf() {
+        let pi = std::f64::consts::PI;
+        assert_eq!(Some("3.14".to_string()), printf(pi, "%.2f"));
+        assert_eq!(Some("3.141593".to_string()), printf(pi, "%f"));
+        assert_eq!(Some("3".to_string()), printf(pi, "%.0f"));
+        assert_eq!(Some("3".to_string()), printf(pi, "%.f"));
+        assert_eq!(Some("3.141593e+00".to_string()), printf(pi, "%e"));
+        assert_eq!(Some("1.235e+04".to_string()), printf(12345.678, "%.3e"));
+        assert_eq!(Some("-1.5e-07".to_string()), printf(-1.5e-7, "%.1e"));
+        assert_eq!(Some("1e+100".to_string()), printf(1e100, "%.0e"));
+        assert_eq!(Some("inf".to_string()), printf(f64::INFINITY, "%e"));
+    }
+
+    #[test]
+    fn test_printf_general() {
+        assert_eq!(Some("1.23456".to_string()), printf(1.23456, "%g"));
+        assert_eq!(Some("100".to_string()), printf(100.0, "%g"));
+        assert_eq!(Some("0.0001".to_string()), printf(0.0001, "%g"));
+        assert_eq!(Some("1e-05".to_string()), printf(0.00001, "%g"));
+        assert_eq!(Some("1.23457e+08".to_string()), printf(123456789.0, "%g"));
+        assert_eq!(Some("1e+02".to_string()), printf(99.9, "%.1g"));
+        assert_eq!(Some("0.5".to_string()), printf(0.5, "%.3g"));
+    }
+
+    #[test]
+    fn test_printf_width() {
+        assert_eq!(Some("    3.14".to_string()), printf(3.14259, "%8.2f"));
+        assert_eq!(Some("3.14    |".to_string()), printf(3.14259, "%-8.2f|"));
+        assert_eq!(Some("-0003.14".to_string()), printf(-3.14259, "%08.2f"));
+        assert_eq!(Some("     inf".to_string()), printf(f64::INFINITY, "%08f"));
+        // The width is the smallest number of characters, not the largest.
+        assert_eq!(Some("12345.0".to_string()), printf(12345.0, "%2.1f"));
+        // Text around the conversion is kept.
+        assert_eq!(Some("x = 12.5%".to_string()), printf(12.5, "x = %.1f%%"));
+    }
+
+    #[test]
+    fn test_printf_invalid() {
+        let invalid = [
+            "%d",
+            "%.2",
+            "%",
+            "no conversion",
+            "%f and %f",
+            "%.101f",
+            "%1000f",
+            "%q",
+        ];
+        for format in invalid {
+            assert_eq!(None, printf(1.0, format), "{format}");
+        }
+        assert_eq!(None, printf(1.0, "%99999999999999999999999f"));
+    }
+
+    #[test]
+    fn test_with_marks() {
+        // US and EU styles.
+        assert_eq!("1,234,567.89", with_marks("1234567.89", ',', '.'));
+        assert_eq!("1.234.567,89", with_marks("1234567.89", '.', ','));
+        assert_eq!("-1.234.567,89", with_marks("-1234567.89", '.', ','));
+        // Numbers without a fraction have no decimal mark.
+        assert_eq!("1.234.567", with_marks("1234567", '.', ','));
+        assert_eq!("-123", with_marks("-123", '.', ','));
+        assert_eq!("0,5", with_marks("0.5", '.', ','));
+        assert_eq!("-1,5e300", with_marks("-1.5e300", '.', ','));
+        assert_eq!("1e300", with_marks("1e300", '.', ','));
+        assert_eq!("-inf", with_marks("-inf", '.', ','));
+    }
+
+    #[test]
+    fn test_format_marks() {
+        let mut format = Format {
+            separator: '.',
+            decimal_mark: ',',
+            grouped: true,
+            ..Format::default()
+        };
+        assert_eq!("1.234.567,89", format.format(1234567.89));
+        assert_eq!("-12.345", format.format(-12345.0));
+        format.scientific = true;
+        assert_eq!("1,2345e4", format.format(12345.0));
+        format.money = true;
+        assert_eq!("12.345,00", format.format(12345.0));
+        // Without grouping, only amounts of money are grouped.
+        let format = Format {
+            separator: '_',
+            ..Format::default()
+        };
+        assert_eq!("1234567.89", format.format(1234567.89));
+    }
+
+    #[test]
+    fn test_fixed() {
+        assert_eq!("0.33", fixed(1.0 / 3.0, 2));
+        assert_eq!("1", fixed(2.0 / 3.0, 0));
+        assert_eq!("-1.500", fixed(-1.5, 3));
+    }
+
+    #[test]
+    fn test_format() {
+        let mut format = Format::default();
+        assert_eq!("12345", format.format(12345.0));
+        // Digits don't matter outside of scientific notation.
+        format.digits = Some(3);
+        assert_eq!("12345", format.format(12345.0));
+        format.scientific = true;
+        assert_eq!("1.23e4", format.format(12345.0));
+    }
+
+    #[test]
+    fn test_format_auto() {
+        let format = Format::default();
+        assert_eq!("0.1", format.format(0.1));
+        // Just enough digits to tell it apart from its neighbors.
+        assert_eq!("0.3333333333333333", format.format(1.0 / 3.0));
+        assert_eq!(1.0 / 3.0, format.format(1.0 / 3.0).parse::<f64>().unwrap());
+        assert_eq!("0.30000000000000004", format.format(0.1 + 0.2));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!("▁▃█", sparkline(&[0.0, 25.0, 100.0]));
+        assert_eq!(
+            "▁▂▃▄▅▆▇█",
+            sparkline(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0])
+        );
+        assert_eq!("█▁█", sparkline(&[-1.0, -3.0, -1.0]));
+        assert_eq!("▄▄", sparkline(&[2.0, 2.0]));
+        // Infinite values don't squash the others.
+        assert_eq!("▁ █", sparkline(&[1.0, f64::INFINITY, 2.0]));
+        assert_eq!("  ", sparkline(&[f64::NAN, f64::NEG_INFINITY]));
+        assert_eq!("", sparkline(&[]));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!("took 1.50ms", format_duration(Duration::from_micros(1500)));
+        assert!(format_duration(Duration::from_nanos(12)).ends_with("ns"));
+        assert!(format_duration(Duration::from_secs(2)).ends_with('s'));
+    }
+}
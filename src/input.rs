@@ -1,34 +1,139 @@
 //! A simple module for user inputs.
 //! Nothing too crazy going on here.
 
-use std::io::{stdin, Write};
+use std::io::{stdin, BufRead, Write};
 
+use calculator::tokenizer::Tokenizer;
 use owo_colors::OwoColorize;
 
 /// Draws a nice little prompt indicator indicating to the user
 /// that the calculator is ready to take inputs.
-fn prompt_indicator() {
+fn prompt_indicator(indicator: &str) {
     // Notice how we use `print!` and not `println!` here.
     // This is because we want the user input to be on the
     // same line as the prompt indicator.
-    print!("{}", "calc❯ ".green().bold());
+    print!("{}", indicator.green().bold());
     std::io::stdout()
         .flush()
         .expect("failed to write to standard output");
 }
 
-/// Simple utility that reads user inputs.
-fn read_user_input() -> String {
+/// Simple utility that reads a line of user input.
+/// Returns `None` if the input ended, or if it couldn't be read,
+/// like when it isn't valid UTF-8.
+fn read_user_input(reader: &mut impl BufRead) -> Option<String> {
     let mut input = String::new();
-    stdin()
-        .read_line(&mut input)
-        .expect("failed to read from standard input");
-
-    input
+    match reader.read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input),
+    }
 }
 
 /// Draws the prompt indicator and reads the user input.
-pub fn prompt() -> String {
-    prompt_indicator();
-    read_user_input()
+/// Returns `None` if there is no more input.
+pub fn prompt() -> Option<String> {
+    prompt_indicator("calc❯ ");
+    read_user_input(&mut stdin().lock())
+}
+
+/// Draws a secondary prompt indicator and reads the user input.
+/// This is used when the previous line isn't a complete input yet.
+/// Returns `None` if there is no more input.
+pub fn continuation_prompt() -> Option<String> {
+    prompt_indicator("...> ");
+    read_user_input(&mut stdin().lock())
+}
+
+/// Whether an input needs another line, either because it ends with `\`,
+/// or because it ends inside parentheses, like `(1 + 2`. If `autoclose`
+/// is true, the parentheses will be closed instead, so only `\` continues.
+/// The `\` is removed from the input.
+pub fn needs_continuation(input: &mut String, autoclose: bool) -> bool {
+    if let Some(stripped) = input.trim_end().strip_suffix('\\') {
+        input.truncate(stripped.len());
+        return true;
+    }
+    !autoclose && Tokenizer::new(input).needs_continuation()
+}
+
+/// Joins the next line to an input. The lines are joined with a space,
+/// so error messages are shown on a single line.
+pub fn join_line(input: &mut String, line: &str) {
+    input.truncate(input.trim_end().len());
+    input.push(' ');
+    input.push_str(line);
+}
+
+/// Tests for user inputs.
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufReader, Read};
+
+    use crate::input::{join_line, needs_continuation, read_user_input};
+
+    /// A reader that always fails.
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("the terminal went away"))
+        }
+    }
+
+    #[test]
+    fn test_read_user_input() {
+        let mut reader = "1 + 2\n3".as_bytes();
+        assert_eq!(Some("1 + 2\n".to_string()), read_user_input(&mut reader));
+        assert_eq!(Some("3".to_string()), read_user_input(&mut reader));
+        // The input ended.
+        assert_eq!(None, read_user_input(&mut reader));
+    }
+
+    #[test]
+    fn test_read_user_input_errors() {
+        assert_eq!(None, read_user_input(&mut BufReader::new(FailingReader)));
+        // Invalid UTF-8 can't be read either.
+        assert_eq!(None, read_user_input(&mut &[0xff, b'\n'][..]));
+    }
+
+    #[test]
+    fn test_backslash_continuation() {
+        let mut input = "1 + \\\n".to_string();
+        assert!(needs_continuation(&mut input, false));
+        assert_eq!("1 + ", input);
+        join_line(&mut input, "2\n");
+        assert!(!needs_continuation(&mut input, false));
+        assert_eq!("1 + 2\n", input);
+    }
+
+    #[test]
+    fn test_backslash_inside_parentheses() {
+        // Both kinds of continuation work together.
+        let mut input = "(1 +\\\n".to_string();
+        assert!(needs_continuation(&mut input, false));
+        join_line(&mut input, "2\n");
+        assert!(needs_continuation(&mut input, false));
+        join_line(&mut input, ") * 3\n");
+        assert!(!needs_continuation(&mut input, false));
+        assert_eq!("(1 + 2 ) * 3\n", input);
+    }
+
+    #[test]
+    fn test_no_continuation() {
+        let mut input = "1 + 2\n".to_string();
+        assert!(!needs_continuation(&mut input, false));
+        assert_eq!("1 + 2\n", input);
+        // A backslash elsewhere is left alone.
+        let mut input = "1 \\ 2".to_string();
+        assert!(!needs_continuation(&mut input, false));
+        assert_eq!("1 \\ 2", input);
+    }
+
+    #[test]
+    fn test_autoclose_continuation() {
+        let mut input = "(1 + 2\n".to_string();
+        assert!(!needs_continuation(&mut input, true));
+        let mut input = "(1 +\\\n".to_string();
+        assert!(needs_continuation(&mut input, true));
+    }
 }
@@ -0,0 +1,103 @@
+//! JSON output, used with the `--json` flag so that scripts can read
+//! the results without parsing the human-readable format.
+//!
+//! Every outcome is a single-line object with an `ok` field. Results look like
+//! `{"ok":true,"value":4.0}`, and errors look like
+//! `{"ok":false,"error":"division by zero","span":{"start":2,"end":3}}`.
+
+use calculator::tokenizer::Span;
+
+/// Serializes a successful result. JSON has no infinity or `NaN`,
+/// so non-finite values are `null`.
+pub fn value(value: f64) -> String {
+    let value = if value.is_finite() {
+        // The debug format always has a decimal point, like `4.0`
+        format!("{value:?}")
+    } else {
+        "null".to_string()
+    };
+    format!(r#"{{"ok":true,"value":{value}}}"#)
+}
+
+/// Serializes an exact integer result with all its digits. JSON numbers
+/// can have any number of digits, even if most readers round them.
+pub fn digits(digits: &str) -> String {
+    format!(r#"{{"ok":true,"value":{digits}}}"#)
+}
+
+/// Serializes a successful output that isn't a number, like a fraction
+/// or the output of a command.
+pub fn text(text: &str) -> String {
+    format!(r#"{{"ok":true,"text":{}}}"#, string(text))
+}
+
+/// Serializes an error. Errors that don't point to a location in the
+/// input, like a missing previous result, have no span.
+pub fn error(message: &str, span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!(
+            r#"{{"ok":false,"error":{},"span":{{"start":{},"end":{}}}}}"#,
+            string(message),
+            span.start,
+            span.end
+        ),
+        None => format!(r#"{{"ok":false,"error":{}}}"#, string(message)),
+    }
+}
+
+/// Serializes a string, escaping quotes, backslashes and control characters.
+fn string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str(r#"\""#),
+            '\\' => escaped.push_str(r"\\"),
+            '\n' => escaped.push_str(r"\n"),
+            '\r' => escaped.push_str(r"\r"),
+            '\t' => escaped.push_str(r"\t"),
+            c if c.is_control() => escaped.push_str(&format!(r"\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Tests for JSON output.
+#[cfg(test)]
+mod tests {
+    use calculator::tokenizer::Span;
+
+    use crate::json::{error, string, text, value};
+
+    #[test]
+    fn test_value() {
+        assert_eq!(r#"{"ok":true,"value":4.0}"#, value(4.0));
+        assert_eq!(r#"{"ok":true,"value":-0.5}"#, value(-0.5));
+        assert_eq!(r#"{"ok":true,"value":null}"#, value(f64::NAN));
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(r#"{"ok":true,"text":"3/4"}"#, text("3/4"));
+    }
+
+    #[test]
+    fn test_error() {
+        assert_eq!(
+            r#"{"ok":false,"error":"division by zero","span":{"start":0,"end":3}}"#,
+            error("division by zero", Some(Span::from(0..3)))
+        );
+        assert_eq!(
+            r#"{"ok":false,"error":"there is no previous result"}"#,
+            error("there is no previous result", None)
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(r#""a\"b\\c\nd\u0007""#, string("a\"b\\c\nd\u{7}"));
+        assert_eq!(r#""`√x`""#, string("`√x`"));
+    }
+}
@@ -0,0 +1,16 @@
+//! The calculator as a library. This lets other programs embed the
+//! tokenizer, the parser and the runtime. The interactive calculator
+//! in `main.rs` is built on top of it.
+
+#[cfg(feature = "bigint")]
+pub mod bigint;
+pub mod complex;
+pub mod diagnostic;
+pub mod explain;
+pub mod format;
+pub mod parser;
+pub mod random;
+pub mod roman;
+pub mod runtime;
+pub mod session;
+pub mod tokenizer;
@@ -1,87 +1,464 @@
-use input::prompt;
+use std::{
+    io::{stdin, IsTerminal, Read},
+    time::{Duration, Instant},
+};
+
+use batch::Mode;
+use calculator::{
+    diagnostic::Diagnostic,
+    format::{fixed, format_duration},
+    runtime::{
+        RuntimeError, MAX_BIGINT_DIGITS, MAX_DICE, MAX_ITERATIONS, MAX_TABLE_ROWS, MAX_TERMS,
+    },
+    session::{relative_error, Output, Session, SessionError},
+    tokenizer::Span,
+};
+use input::{continuation_prompt, join_line, needs_continuation, prompt};
 use owo_colors::OwoColorize;
-use parser::{ParseTree, Parser, ParserError};
-use runtime::evaluate;
-use tokenizer::Span;
 
 // Module declarations
+mod batch;
+mod clipboard;
+mod csv;
 mod input;
-mod parser;
-mod runtime;
-mod tokenizer;
+mod json;
+
+/// The command-line arguments.
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    /// An expression given with `-e`, evaluated instead of reading any input.
+    expression: Option<String>,
+    /// A file whose lines are evaluated.
+    path: Option<String>,
+    /// How the results are printed, changed with `--json` or `--csv`.
+    mode: Mode,
+    /// What is printed after every result, changed with `--delimiter`.
+    /// It's a newline if there is none.
+    delimiter: Option<String>,
+}
+
+impl Args {
+    /// Parses the command-line arguments, without the program name.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => parsed.mode = Mode::Json,
+                "--csv" => parsed.mode = Mode::Csv,
+                "-e" => match args.next() {
+                    Some(_) if parsed.expression.is_some() => {
+                        return Err("only one expression can be given with `-e`".to_string())
+                    }
+                    Some(expression) => parsed.expression = Some(expression),
+                    None => return Err("`-e` needs an expression".to_string()),
+                },
+                "--delimiter" => match args.next() {
+                    Some(delimiter) => parsed.delimiter = Some(unescape(&delimiter)),
+                    None => return Err("`--delimiter` needs a delimiter".to_string()),
+                },
+                _ if parsed.path.is_some() => {
+                    return Err(format!("only one file can be given, found `{arg}` too"))
+                }
+                _ => parsed.path = Some(arg),
+            }
+        }
+        if parsed.expression.is_some() && parsed.path.is_some() {
+            return Err("`-e` and a file can't both be given".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+/// Replaces the escapes `\0`, `\n`, `\t` and `\\` with the characters they
+/// stand for, since shells make these hard to type. Other backslashes are kept.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => unescaped.push('\0'),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
 
 fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}: {e}", "error".red().bold());
+            std::process::exit(1);
+        }
+    };
+
+    // If an expression or a file is given as argument, or if the input is piped into
+    // the calculator, evaluate the whole input at once. Otherwise, start the interactive prompt.
+    let batch_input = match (args.expression, args.path) {
+        (Some(expression), _) => Some(expression),
+        (None, Some(path)) => match std::fs::read_to_string(&path) {
+            Ok(contents) => Some(contents),
+            Err(e) => {
+                eprintln!("{}: could not read `{path}`: {e}", "error".red().bold());
+                std::process::exit(1);
+            }
+        },
+        (None, None) if !stdin().is_terminal() => {
+            let mut contents = String::new();
+            // Like an unreadable file, input that isn't UTF-8 is an error
+            if let Err(e) = stdin().read_to_string(&mut contents) {
+                eprintln!(
+                    "{}: could not read the standard input: {e}",
+                    "error".red().bold()
+                );
+                std::process::exit(1);
+            }
+            Some(contents)
+        }
+        (None, None) => None,
+    };
+
+    match batch_input {
+        // Failed assertions fail the whole script, so it can be used for checks
+        Some(contents) => {
+            let delimiter = args.delimiter.as_deref().unwrap_or("\n");
+            if !batch::run(&contents, args.mode, delimiter) {
+                std::process::exit(1);
+            }
+        }
+        // The prompt shows results for people, not scripts
+        None if args.mode != Mode::Aligned || args.delimiter.is_some() => {
+            eprintln!(
+                "{}: `--json`, `--csv` and `--delimiter` need `-e`, a file or piped input",
+                "error".red().bold()
+            );
+            std::process::exit(1);
+        }
+        None => repl(),
+    }
+}
+
+/// The interactive prompt loop.
+fn repl() {
+    let mut session = Session::new();
+
     loop {
-        // Get the user input and parse it
-        let input = prompt();
-        let parsed = Parser::new(&input).parse();
-
-        match parsed {
-            Ok(parse_tree) => match parse_tree {
-                // Evaluate and print the result
-                ParseTree::Expression(expr) => {
-                    let evaluated = evaluate(expr);
-                    println!("{evaluated}");
-                }
-                // Quit the calculator
-                ParseTree::Quit => break,
-                // Go to next prompt
-                ParseTree::Empty => continue,
+        // Get the user input and execute it. Quit if there is no more input,
+        // on a new line so the terminal's prompt isn't next to ours.
+        let Some(input) = read_input(session.autoclose()) else {
+            println!();
+            break;
+        };
+
+        let start = Instant::now();
+        let output = session.execute(&input);
+        let elapsed = start.elapsed();
+
+        match output {
+            // Print the result, how long it took if timing is on, and
+            // how many nodes were evaluated if profiling is on
+            Ok(Output::Result(result)) => {
+                let formatted = with_total(session.format_result(result), &session);
+                let formatted = with_relative_error(formatted, result, &session);
+                print_result(&formatted, &session, elapsed)
+            }
+            Ok(Output::Rounded(result, decimals)) => {
+                let formatted = with_total(fixed(result, decimals), &session);
+                let formatted = with_relative_error(formatted, result, &session);
+                print_result(&formatted, &session, elapsed)
+            }
+            Ok(Output::Digits(result, text) | Output::Formatted(result, text)) => {
+                let formatted = with_total(text, &session);
+                let formatted = with_relative_error(formatted, result, &session);
+                print_result(&formatted, &session, elapsed)
+            }
+            Ok(Output::Text(text)) => println!("{text}"),
+            // Copy to the clipboard, and show what was copied
+            Ok(Output::Copy(text)) => match clipboard::copy(&text) {
+                Ok(()) => println!("copied `{text}`"),
+                Err(e) => println!("{}", format!("{}: {e}", "error".red()).bold()),
             },
+            // Quit the calculator
+            Ok(Output::Quit) => break,
+            // Go to next prompt
+            Ok(Output::Nothing) => continue,
             Err(e) => {
                 // Display the error and go to next prompt
                 println!("{}", format_error(e, &input));
                 continue;
             }
         }
+        // The closed parentheses may not be where they were meant to be
+        if let Some(note) = autoclose_note(session.closed_parentheses()) {
+            println!("{}", note.dimmed());
+        }
+        if session.bigint_fallback() {
+            println!(
+                "{}: not exact on integers, evaluated with floats",
+                "warning".yellow().bold()
+            );
+        }
+        if session.overflowed() {
+            println!(
+                "{}: an operation overflowed to infinity",
+                "warning".yellow().bold()
+            );
+        }
     }
 }
 
-/// Gets the string the the span points to.
-/// If the span is `None`, returns `"<EOL>"` (end of line) instead
-fn spanned_value(input: &str, span: Option<Span>) -> &str {
-    span.map(|span| &input[span]).unwrap_or("<EOL>")
+/// A note saying how many parentheses were closed at the end of the input,
+/// if any.
+fn autoclose_note(closed: usize) -> Option<String> {
+    match closed {
+        0 => None,
+        1 => Some("note: closed 1 parenthesis at the end".to_string()),
+        closed => Some(format!("note: closed {closed} parentheses at the end")),
+    }
 }
 
-/// Unwraps an optional span. If the option was `None`,
-/// creates a span of the last character of the input instead.
-fn unwrap_span(input: &str, span: Option<Span>) -> Span {
-    span.unwrap_or(Span {
-        start: input.len() - 1,
-        end: input.len(),
-    })
+/// Adds the running total to a formatted result if it's on, like `3 (total 8)`.
+fn with_total(result: String, session: &Session) -> String {
+    match session.total() {
+        Some(total) => format!("{result} (total {})", session.format_result(total)),
+        None => result,
+    }
 }
 
-fn format_error(error: ParserError, input: &str) -> String {
-    // Create the error message and get the source span
-    let (msg, span) = match error {
-        ParserError::UnrecognizedSpecial(span) => (
-            format!("expected `?quit`, found `{}`", spanned_value(input, span)),
-            unwrap_span(input, span),
+/// Adds the relative error of a result to a formatted result if there is a
+/// reference, like `3.1429 (relative error 0.0004)`.
+fn with_relative_error(formatted: String, result: f64, session: &Session) -> String {
+    let Some(reference) = session.reference() else {
+        return formatted;
+    };
+    match relative_error(result, reference) {
+        Some(error) => format!(
+            "{formatted} (relative error {})",
+            session.format_result(error)
         ),
-        ParserError::ExpectedBinaryOp(span) => (
-            format!(
-                "expected one of `+`, `-`, `*`, `/`, found `{}`",
-                spanned_value(input, span)
-            ),
-            unwrap_span(input, span),
+        None => format!("{formatted} (relative error undefined, since the reference is 0)"),
+    }
+}
+
+/// Prints a formatted result, how long it took if timing is on, and how
+/// many expression nodes were evaluated if profiling is on.
+fn print_result(result: &str, session: &Session, elapsed: Duration) {
+    println!("{result}");
+    if session.timing() {
+        println!("{}", format_duration(elapsed).dimmed());
+    }
+    if let Some(nodes) = session.evaluated_nodes() {
+        println!("{}", profile_note(nodes).dimmed());
+    }
+}
+
+/// A note saying how many expression nodes were evaluated.
+fn profile_note(nodes: usize) -> String {
+    match nodes {
+        1 => "1 node evaluated".to_string(),
+        nodes => format!("{nodes} nodes evaluated"),
+    }
+}
+
+/// Reads a whole input from the user. If a line ends with `\` or inside
+/// parentheses, asks for more lines until the input is complete. If
+/// `autoclose` is true, open parentheses don't ask for more lines.
+/// Returns `None` if there is no more input.
+fn read_input(autoclose: bool) -> Option<String> {
+    let mut input = prompt()?;
+    while needs_continuation(&mut input, autoclose) {
+        // The input ended, so there won't be any more lines.
+        let Some(line) = continuation_prompt() else {
+            break;
+        };
+        join_line(&mut input, &line);
+    }
+    Some(input)
+}
+
+/// Creates the error message and gets the source span of an error, if
+/// the error points to a location in the input.
+fn error_message(error: SessionError, input: &str) -> (String, Option<Span>) {
+    match error {
+        SessionError::Parser(error) => {
+            let diagnostic = Diagnostic::from_parser_error(error, input);
+            (diagnostic.message, Some(diagnostic.span))
+        }
+        SessionError::Runtime(RuntimeError::UnknownVariable(name, span)) => {
+            (format!("unknown variable `{name}`"), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::Overflow(span)) => (
+            "the result is too large to be represented".to_string(),
+            Some(span),
         ),
-        ParserError::ExpectedExprStart(span) => (
+        SessionError::Runtime(RuntimeError::ExpectedNonNegativeInteger(span)) => {
+            ("expected a non-negative integer".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedFunctionCall(name, span)) => (
+            format!("`{name}` is a function, call it like `{name}(x)`"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::UnknownFunction(name, span)) => {
+            (format!("`{name}` is neither a function nor a variable"), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::WrongArgumentCount(expected, found, span)) => (
             format!(
-                "expected one of `-`, `(`, or a number, found `{}`",
-                spanned_value(input, span)
+                "expected {expected} argument{}, found {found}",
+                if expected == 1 { "" } else { "s" }
             ),
-            unwrap_span(input, span),
+            Some(span),
         ),
-        ParserError::UnclosedParenthesis(span) => (
-            format!("expected `)`, found `{}`", spanned_value(input, span)),
-            unwrap_span(input, span),
+        SessionError::Runtime(RuntimeError::WrongArgumentCountEither(first, second, found, span)) => (
+            format!("expected {first} or {second} arguments, found {found}"),
+            Some(span),
         ),
-    };
+        SessionError::Runtime(RuntimeError::DivisionByZero(span)) => {
+            ("division by zero".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedNonNegative(span)) => {
+            ("expected a non-negative number".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedAtLeastOne(span)) => {
+            ("expected a number of at least 1".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedBetweenMinusOneAndOne(span)) => (
+            "expected a number strictly between -1 and 1".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::ExpectedFromMinusOneToOne(span)) => {
+            ("expected a number from -1 to 1".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedPositiveInteger(span)) => {
+            ("expected a positive integer".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::NonIntegerResult(span)) => (
+            "the result isn't an integer, but integer mode is on; round divisions down with `?int floor`".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::ExpectedVariable(span)) => {
+            ("expected a variable".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedFinite(span)) => {
+            ("expected a finite number".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::InvalidStep(span)) => (
+            "the step must be non-zero, and go from the start towards the end".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::TooManyRows(span)) => (
+            format!("too many rows, the most is {MAX_TABLE_ROWS}"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::NestedTable(span)) => (
+            "`table` can't be used inside an expression, only on its own".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::AssertionFailed(actual, expected, span)) => (
+            format!("assertion failed, expected {expected}, found {actual}"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::ExpectedInteger(span)) => {
+            ("expected an integer".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::NoSuchResult(0, span)) => {
+            ("there is no previous result".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::NoSuchResult(1, span)) => {
+            ("there is only 1 previous result".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::NoSuchResult(count, span)) => (
+            format!("there are only {count} previous results"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::TooManyDigits(span)) => (
+            format!("too many digits, the most is {MAX_BIGINT_DIGITS}"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::TooManyTerms(span)) => (
+            format!("too many terms, the most is {MAX_TERMS}"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::UnexpectedText(span)) => {
+            ("expected a number, found text".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedText(span)) => (
+            "expected text between double quotes, like `\"%.2f\"`".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::UnknownOperator(span)) => (
+            "unknown operator, expected `\"+\"`, `\"*\"`, `\"min\"` or `\"max\"`".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::NoValues(span)) => (
+            "expected at least one value after the operator".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::InvalidFormat(span)) => (
+            "invalid format, expected one conversion like `%.2f`, `%e` or `%g`".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::NoTemplate(span)) => (
+            "there is no template, define one with placeholders like `$1 * $1 + 1`".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::BudgetExceeded(span)) => (
+            "too many operations for the evaluation budget".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::TooManyIterations(span)) => (
+            format!("too many iterations, the most is {MAX_ITERATIONS}"),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::ExpectedFunction(span)) => (
+            "expected the name of a function of a number, like `sqrt`".to_string(),
+            Some(span),
+        ),
+        SessionError::Runtime(RuntimeError::TooManyDice(span)) => {
+            (format!("too many dice, the most is {MAX_DICE}"), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ConstantReassignment(name, span)) => {
+            (format!("cannot reassign constant `{name}`"), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::ExpectedReal(span)) => {
+            ("expected a real number".to_string(), Some(span))
+        }
+        SessionError::Runtime(RuntimeError::MissingArgument(index, span)) => {
+            (format!("missing argument `${index}`"), Some(span))
+        }
+        SessionError::NoPreviousResult => ("there is no previous result".to_string(), None),
+        SessionError::NothingToUndo => ("there is no change of the settings to undo".to_string(), None),
+        SessionError::NothingToRedo => ("there is no undone change of the settings to redo".to_string(), None),
+        SessionError::MissingFeature(feature) => (
+            format!("the calculator was built without the `{feature}` feature"),
+            None,
+        ),
+    }
+}
+
+fn format_error(error: SessionError, input: &str) -> String {
+    // Create the error message and get the source span
+    let (msg, span) = error_message(error, input);
 
     // Format the first line, explaining the reason for the error
     let explanation_line = format!("{}: {}", "error".red(), msg);
 
+    // Errors that don't point to the source only have the explanation line
+    let Some(span) = span else {
+        return explanation_line.bold().to_string();
+    };
+
     // Format the line representing the source input
     let src_line = format!("      {input}");
 
@@ -100,3 +477,120 @@ fn format_error(error: ParserError, input: &str) -> String {
         src_underline
     )
 }
+
+/// Tests for the command-line arguments.
+#[cfg(test)]
+mod tests {
+    use calculator::session::Session;
+
+    use crate::{
+        autoclose_note, batch::Mode, profile_note, unescape, with_relative_error, with_total, Args,
+    };
+
+    /// Parses the given arguments.
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_profile_note() {
+        assert_eq!("1 node evaluated", profile_note(1));
+        assert_eq!("5 nodes evaluated", profile_note(5));
+    }
+
+    #[test]
+    fn test_autoclose_note() {
+        assert_eq!(None, autoclose_note(0));
+        assert_eq!(
+            Some("note: closed 1 parenthesis at the end".to_string()),
+            autoclose_note(1)
+        );
+        assert_eq!(
+            Some("note: closed 3 parentheses at the end".to_string()),
+            autoclose_note(3)
+        );
+    }
+
+    #[test]
+    fn test_with_total() {
+        let mut session = Session::new();
+        assert_eq!("5", with_total("5".to_string(), &session));
+        session.execute("?total on").unwrap();
+        session.execute("5").unwrap();
+        session.execute("3").unwrap();
+        assert_eq!("3 (total 8)", with_total("3".to_string(), &session));
+    }
+
+    #[test]
+    fn test_with_relative_error() {
+        let mut session = Session::new();
+        assert_eq!("5", with_relative_error("5".to_string(), 5.0, &session));
+        session.execute("?relerr 4").unwrap();
+        assert_eq!(
+            "5 (relative error 0.25)",
+            with_relative_error("5".to_string(), 5.0, &session)
+        );
+        session.execute("?relerr 0").unwrap();
+        assert_eq!(
+            "5 (relative error undefined, since the reference is 0)",
+            with_relative_error("5".to_string(), 5.0, &session)
+        );
+    }
+
+    #[test]
+    fn test_args() {
+        assert_eq!(Ok(Args::default()), parse(&[]));
+        assert_eq!(
+            Ok(Args {
+                expression: Some("1/0".to_string()),
+                path: None,
+                mode: Mode::Json,
+                delimiter: None,
+            }),
+            parse(&["-e", "1/0", "--json"])
+        );
+        assert_eq!(
+            Ok(Args {
+                expression: None,
+                path: Some("input.txt".to_string()),
+                mode: Mode::Aligned,
+                delimiter: None,
+            }),
+            parse(&["input.txt"])
+        );
+        assert_eq!(Mode::Csv, parse(&["--csv", "input.txt"]).unwrap().mode);
+        assert_eq!(Err("`-e` needs an expression".to_string()), parse(&["-e"]));
+        assert_eq!(
+            Some("\0".to_string()),
+            parse(&["--delimiter", "\\0"]).unwrap().delimiter
+        );
+        assert_eq!(
+            Err("`--delimiter` needs a delimiter".to_string()),
+            parse(&["--delimiter"])
+        );
+        // Inputs aren't silently dropped.
+        assert_eq!(
+            Err("only one expression can be given with `-e`".to_string()),
+            parse(&["-e", "1", "-e", "2"])
+        );
+        assert_eq!(
+            Err("only one file can be given, found `b.txt` too".to_string()),
+            parse(&["a.txt", "b.txt"])
+        );
+        assert_eq!(
+            Err("`-e` and a file can't both be given".to_string()),
+            parse(&["-e", "1", "a.txt"])
+        );
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!("\0", unescape("\\0"));
+        assert_eq!("\n", unescape("\\n"));
+        assert_eq!("a\tb", unescape("a\\tb"));
+        assert_eq!("\\", unescape("\\\\"));
+        assert_eq!("; ", unescape("; "));
+        // Unknown escapes and trailing backslashes are kept.
+        assert_eq!("\\x\\", unescape("\\x\\"));
+    }
+}
@@ -1,39 +1,380 @@
-use std::iter::Peekable;
+use std::{
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    iter::Peekable,
+    ops::RangeInclusive,
+};
 
-use crate::tokenizer::{OperationKind, Span, SpecialKind, Token, TokenKind, Tokenizer};
+use crate::{
+    format::{MAX_DECIMALS, MAX_DIGITS, MIN_DIGITS},
+    random::MAX_SEED,
+    roman::{is_roman_letters, roman_to_int},
+    runtime::{IntegerDivision, OverflowPolicy, MAX_HISTORY, MEMORY_SLOTS},
+    tokenizer::{
+        unclosed_parentheses, AssignmentKind, OperationKind, Span, SpecialKind, Token, TokenKind,
+        Tokenizer, TokenizerError,
+    },
+};
+
+/// The number of results `?last` shows when no number is given.
+pub const DEFAULT_LAST: usize = 10;
 
 /// Binary Operation.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum BinaryOperation {
     Addition,
     Subtraction,
     Multiplication,
     Division,
+    Exponentiation,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    /// Logical "and". It doesn't short-circuit: both operands are always
+    /// evaluated, so `0 and (x = 5)` still assigns `x`.
+    And,
+    /// Logical "or". Like "and", both operands are always evaluated.
+    Or,
+    /// `a + b%`, which adds `b` percent of `a` to `a`, like in `200 + 10%`.
+    AddPercentage,
+    /// `a - b%`, which subtracts `b` percent of `a` from `a`, like in `200 - 10%`.
+    SubtractPercentage,
+    /// `a <? b`, the smallest of `a` and `b`.
+    Minimum,
+    /// `a >? b`, the largest of `a` and `b`.
+    Maximum,
+}
+
+impl BinaryOperation {
+    /// Whether this operation is a comparison. Comparisons can be chained.
+    fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperation::Less
+                | BinaryOperation::LessOrEqual
+                | BinaryOperation::Greater
+                | BinaryOperation::GreaterOrEqual
+                | BinaryOperation::Equal
+                | BinaryOperation::NotEqual
+        )
+    }
+
+    /// The symbol of this operation, like `+`.
+    pub(crate) fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperation::Addition | BinaryOperation::AddPercentage => "+",
+            BinaryOperation::Subtraction | BinaryOperation::SubtractPercentage => "-",
+            BinaryOperation::Multiplication => "*",
+            BinaryOperation::Division => "/",
+            BinaryOperation::Exponentiation => "^",
+            BinaryOperation::Less => "<",
+            BinaryOperation::LessOrEqual => "<=",
+            BinaryOperation::Greater => ">",
+            BinaryOperation::GreaterOrEqual => ">=",
+            BinaryOperation::Equal => "==",
+            BinaryOperation::NotEqual => "!=",
+            BinaryOperation::And => "and",
+            BinaryOperation::Or => "or",
+            BinaryOperation::Minimum => "<?",
+            BinaryOperation::Maximum => ">?",
+        }
+    }
 }
 
 /// Unary operation.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum UnaryOperation {
     Negation,
+    /// Logical "not".
+    Not,
+    /// Factorial. This is a postfix operation.
+    Factorial,
+    /// Percentage, which divides by 100. This is a postfix operation.
+    Percent,
+    /// Square root, written `√`.
+    SquareRoot,
 }
 
 /// Arithmetic expression.
 /// This is the root of our syntax tree.
-#[derive(Debug, PartialEq)]
-pub enum Expression {
+#[derive(Debug, Clone)]
+pub struct Expression {
+    /// The kind of this expression. It's private, since changing it would make
+    /// `structural_hash` wrong. It's read with `kind` and `into_kind`.
+    kind: ExpressionKind,
+    /// The span of the source input this expression was parsed from.
+    pub span: Span,
+    /// The hash of the structure of this expression.
+    /// Hashing a whole tree is slow, so we compute it once when the expression
+    /// is created. This is also why `kind` can't be modified afterwards.
+    structural_hash: u64,
+}
+
+impl Expression {
+    /// Creates a new expression.
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        // The children's hashes are already computed, so this is fast.
+        let mut hasher = DefaultHasher::new();
+        kind.hash(&mut hasher);
+        Self {
+            kind,
+            span,
+            structural_hash: hasher.finish(),
+        }
+    }
+
+    /// The kind of this expression.
+    pub fn kind(&self) -> &ExpressionKind {
+        &self.kind
+    }
+
+    /// Consumes the expression and returns its kind.
+    pub fn into_kind(self) -> ExpressionKind {
+        self.kind
+    }
+
+    /// The number of arguments this expression needs as a template, which is
+    /// its largest placeholder. So `$1 * $3` needs 3 arguments, and an
+    /// expression without placeholders needs none.
+    pub fn placeholder_count(&self) -> usize {
+        match &self.kind {
+            ExpressionKind::Binary { lhs, rhs, .. } => {
+                lhs.placeholder_count().max(rhs.placeholder_count())
+            }
+            ExpressionKind::Comparisons { first, comparisons } => comparisons
+                .iter()
+                .map(|(_, operand)| operand.placeholder_count())
+                .fold(first.placeholder_count(), usize::max),
+            ExpressionKind::Unary { operand, .. } => operand.placeholder_count(),
+            ExpressionKind::Assignment { value, .. } => value.placeholder_count(),
+            ExpressionKind::Call { arguments, .. } => arguments
+                .iter()
+                .map(Expression::placeholder_count)
+                .max()
+                .unwrap_or_default(),
+            ExpressionKind::Placeholder(index) => *index,
+            ExpressionKind::Variable(_)
+            | ExpressionKind::Atom(_)
+            | ExpressionKind::Imaginary(_)
+            | ExpressionKind::Text(_) => 0,
+        }
+    }
+
+    /// Clones this expression, with the spans of every subexpression moved
+    /// by `delta` bytes, backward if it's negative. This is useful when an
+    /// expression is moved to another place in the input, like a template
+    /// expanded at a call site. Positions can't go before the start of the
+    /// input, so they stop at 0.
+    pub fn shift_spans(&self, delta: isize) -> Expression {
+        let shift = |expr: &Expression| Box::new(expr.shift_spans(delta));
+        let kind = match &self.kind {
+            ExpressionKind::Binary {
+                operation,
+                lhs,
+                rhs,
+            } => ExpressionKind::Binary {
+                operation: operation.clone(),
+                lhs: shift(lhs),
+                rhs: shift(rhs),
+            },
+            ExpressionKind::Comparisons { first, comparisons } => ExpressionKind::Comparisons {
+                first: shift(first),
+                comparisons: comparisons
+                    .iter()
+                    .map(|(operation, operand)| (operation.clone(), operand.shift_spans(delta)))
+                    .collect(),
+            },
+            ExpressionKind::Unary { operation, operand } => ExpressionKind::Unary {
+                operation: operation.clone(),
+                operand: shift(operand),
+            },
+            ExpressionKind::Assignment {
+                name,
+                value,
+                constant,
+            } => ExpressionKind::Assignment {
+                name: name.clone(),
+                value: shift(value),
+                constant: *constant,
+            },
+            ExpressionKind::Call { name, arguments } => ExpressionKind::Call {
+                name: name.clone(),
+                arguments: arguments
+                    .iter()
+                    .map(|argument| argument.shift_spans(delta))
+                    .collect(),
+            },
+            kind @ (ExpressionKind::Variable(_)
+            | ExpressionKind::Atom(_)
+            | ExpressionKind::Imaginary(_)
+            | ExpressionKind::Placeholder(_)
+            | ExpressionKind::Text(_)) => kind.clone(),
+        };
+        let span = Span {
+            start: self.span.start.saturating_add_signed(delta),
+            end: self.span.end.saturating_add_signed(delta),
+        };
+        Expression::new(kind, span)
+    }
+}
+
+/// Two expressions are equal if they have the same structure,
+/// no matter where they are in the source input. So spans are ignored.
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        // Comparing the hashes first quickly rules out most different expressions.
+        self.structural_hash == other.structural_hash && self.kind == other.kind
+    }
+}
+
+impl Eq for Expression {}
+
+/// Expressions are shown fully parenthesized, like `((1 + 2) * 3)`,
+/// so parsing them back gives the same expression.
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ExpressionKind::Binary {
+                operation:
+                    operation @ (BinaryOperation::AddPercentage | BinaryOperation::SubtractPercentage),
+                lhs,
+                rhs,
+            } => write!(f, "({lhs} {} {rhs}%)", operation.symbol()),
+            ExpressionKind::Binary {
+                operation,
+                lhs,
+                rhs,
+            } => write!(f, "({lhs} {} {rhs})", operation.symbol()),
+            ExpressionKind::Comparisons { first, comparisons } => {
+                write!(f, "({first}")?;
+                for (operation, operand) in comparisons {
+                    write!(f, " {} {operand}", operation.symbol())?;
+                }
+                write!(f, ")")
+            }
+            ExpressionKind::Unary { operation, operand } => match operation {
+                UnaryOperation::Negation => write!(f, "(-{operand})"),
+                UnaryOperation::Not => write!(f, "(not {operand})"),
+                UnaryOperation::Factorial => write!(f, "({operand}!)"),
+                UnaryOperation::Percent => write!(f, "({operand}%)"),
+                UnaryOperation::SquareRoot => write!(f, "(√{operand})"),
+            },
+            ExpressionKind::Assignment {
+                name,
+                value,
+                constant,
+            } => {
+                let keyword = if *constant { "const " } else { "" };
+                write!(f, "({keyword}{name} = {value})")
+            }
+            ExpressionKind::Call { name, arguments } => {
+                let arguments = arguments
+                    .iter()
+                    .map(Expression::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{name}({arguments})")
+            }
+            ExpressionKind::Variable(name) => write!(f, "{name}"),
+            ExpressionKind::Atom(num) => write!(f, "{}", num.0),
+            ExpressionKind::Imaginary(num) => write!(f, "{}i", num.0),
+            ExpressionKind::Placeholder(index) => write!(f, "${index}"),
+            ExpressionKind::Text(text) => write!(f, "\"{text}\""),
+        }
+    }
+}
+
+/// Spans are ignored when hashing too, to be consistent with equality.
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.structural_hash.hash(state);
+    }
+}
+
+/// The kind of an arithmetic expression.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum ExpressionKind {
     /// Binary expression.
     Binary {
         operation: BinaryOperation,
         lhs: Box<Expression>,
         rhs: Box<Expression>,
     },
+    /// Chained comparisons, like `a < b <= c`, which is true if every
+    /// comparison is. There are always at least two comparisons, each with
+    /// its right operand. Every operand is evaluated once, from left to right.
+    Comparisons {
+        first: Box<Expression>,
+        comparisons: Vec<(BinaryOperation, Expression)>,
+    },
     /// Unary expression.
     Unary {
         operation: UnaryOperation,
         operand: Box<Expression>,
     },
+    /// Assignment of a value to a variable. Constants, defined with
+    /// `const`, can't be assigned again.
+    Assignment {
+        name: String,
+        value: Box<Expression>,
+        constant: bool,
+    },
+    /// Function call, like `inv(4)`.
+    Call {
+        name: String,
+        arguments: Vec<Expression>,
+    },
+    /// Variable reference.
+    Variable(String),
     /// Atom, in this case a number.
-    Atom(f64),
+    Atom(Number),
+    /// Imaginary atom, like `2i`. The number is the imaginary part.
+    Imaginary(Number),
+    /// Template placeholder, like `$1`, replaced by an argument when
+    /// the template is evaluated. Placeholders are numbered from 1.
+    Placeholder(usize),
+    /// Text, like `"%.2f"`. It isn't a value, only the format of `fmt`.
+    Text(String),
+}
+
+/// A number in an atom.
+///
+/// `f64` is neither `Eq` nor `Hash`, so we can't use it directly in the
+/// syntax tree if we want to use expressions as map keys. Instead, numbers
+/// are compared and hashed by their bits:
+/// * `0.0` and `-0.0` are different numbers, since they can evaluate
+///   differently (`1/0` is infinity, `1/-0` is negative infinity).
+/// * All `NaN`s are equal to each other, no matter their bits. So every
+///   number is equal to itself, as `Eq` requires.
+#[derive(Debug, Clone, Copy)]
+pub struct Number(pub f64);
+
+impl Number {
+    /// The bits used to compare and hash the number.
+    fn bits(self) -> u64 {
+        if self.0.is_nan() {
+            f64::NAN.to_bits()
+        } else {
+            self.0.to_bits()
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits() == other.bits()
+    }
+}
+
+impl Eq for Number {}
+
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits().hash(state);
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -42,29 +383,290 @@ pub enum ParseTree {
     Expression(Expression),
     /// A quit instruction.
     Quit,
+    /// An instruction to show the bits of the last result.
+    Bits,
+    /// An instruction to show the unit in the last place of the last result.
+    Ulp,
+    /// An instruction to explain how the last expression was evaluated.
+    Explain,
+    /// An instruction to copy the last result to the clipboard.
+    Copy,
+    /// An instruction to turn scientific notation on or off.
+    Scientific(bool),
+    /// An instruction to set the number of significant digits in scientific notation.
+    Digits(usize),
+    /// An instruction to turn timing on or off.
+    Time(bool),
+    /// An instruction to turn roman mode on or off.
+    Roman(bool),
+    /// An instruction to turn complex mode on or off.
+    Complex(bool),
+    /// An instruction to show results with the shortest representation
+    /// that reads back to the same number.
+    PrecisionAuto,
+    /// An instruction to round every operation to the given number of decimals,
+    /// or to stop rounding if there is none.
+    FixedPoint(Option<usize>),
+    /// An instruction to show the given number of most recent results.
+    Last(usize),
+    /// An instruction to turn money formatting on or off.
+    Money(bool),
+    /// An instruction to compare the results of two expressions.
+    Diff(Expression, Expression),
+    /// An instruction to show the limits of floating-point numbers.
+    Eps,
+    /// An instruction to turn approximate equality on or off.
+    Approx(bool),
+    /// An instruction to turn the running total on or off.
+    Total(bool),
+    /// An instruction to set the running total back to zero.
+    TotalReset,
+    /// An instruction to compare two number literals, which may be
+    /// written in different bases, like `0xff` and `255`.
+    Eq(f64, f64),
+    /// An instruction to turn closing missing parentheses on or off.
+    AutoClose(bool),
+    /// An instruction to seed the random number generator of `rand`.
+    Seed(u64),
+    /// An instruction to change the separator between groups of digits, and
+    /// to group the digits of every result. The span is the separator's,
+    /// since it can't be the decimal mark.
+    Separator(char, Span),
+    /// An instruction to turn integer mode on with the given division mode,
+    /// or off if there is none.
+    Integer(Option<IntegerDivision>),
+    /// An instruction to turn big integer mode on or off.
+    BigInt(bool),
+    /// An instruction to show the variables as assignments that define them again.
+    Export,
+    /// An instruction to change how operations that overflow are handled.
+    Overflow(OverflowPolicy),
+    /// An instruction to turn counting the evaluated expression nodes on or off.
+    Profile(bool),
+    /// An instruction to group results with the given thousands separator and
+    /// decimal mark, or to stop grouping them if there are none.
+    FormatMarks(Option<(char, char)>),
+    /// An instruction to store the last result in the given memory slot, numbered from 1.
+    Store(usize),
+    /// An instruction to show the relative error of results to the value of
+    /// the given expression, or to stop showing it if there is none.
+    RelativeError(Option<Expression>),
+    /// An instruction to undo the last change of the settings.
+    SettingsUndo,
+    /// An instruction to redo the last undone change of the settings.
+    SettingsRedo,
     /// Nothing to parse.
     Empty,
 }
 
 /// An error catched by the parser.
+#[derive(Debug, PartialEq)]
 pub enum ParserError {
     /// The error occured because the special command was not recognized.
     UnrecognizedSpecial(Option<Span>),
-    /// The error occured because the parser expected a binary operator
-    /// (`+`,`-`,`*` or `/`) but got something else instead.
+    /// The error occured because the parser expected an operator
+    /// (`+`,`-`,`*`, `/`, `^`, `!`, a comparison, `and` or `or`) but got something else instead.
     ExpectedBinaryOp(Option<Span>),
     /// The error occured because the parser expected a new expression
-    /// (`-`, `(`, or a number), but got something else instead.
+    /// (`-`, `not`, `(`, or a number), but got something else instead.
     ExpectedExprStart(Option<Span>),
+    /// The error occured because a binary operator, like `*`, was where
+    /// a new expression should start.
+    BinaryOperatorAtStart(OperationKind, Span),
     /// The error occured because the parser expected a closing parenthesis
     /// but got something else instead.
     UnclosedParenthesis(Option<Span>),
+    /// The error occured because a closing parenthesis had no matching
+    /// opening parenthesis.
+    UnexpectedCloseParenthesis(Span),
+    /// The error occured because the input continued after a complete expression.
+    /// The span points to the first leftover token.
+    TrailingTokens(Span),
+    /// The error occured because something other than a variable
+    /// was on the left of an assignment.
+    InvalidAssignmentTarget(Option<Span>),
+    /// The error occured because `const` wasn't followed by a definition,
+    /// like `const g = 9.81`.
+    ExpectedConstantDefinition(Option<Span>),
+    /// The error occured because `|>` wasn't followed by a function name.
+    ExpectedFunctionName(Option<Span>),
+    /// The error occured because the input could not be tokenized.
+    Tokenizer(TokenizerError),
+    /// The error occured because an expression was expected, but the input was empty.
+    EmptyInput,
+    /// The error occured because a command expected `on` or `off`,
+    /// but got something else instead.
+    ExpectedToggle(Option<Span>),
+    /// The error occured because a command expected the given keyword,
+    /// but got something else instead.
+    ExpectedKeyword(Option<Span>, &'static str),
+    /// The error occured because a command expected a number literal,
+    /// but got something else instead.
+    ExpectedNumber(Option<Span>),
+    /// The error occured because a command expected a separator between
+    /// groups of digits, like `_`, but got something else instead.
+    ExpectedSeparator(Option<Span>),
+    /// The error occured because a command expected a mark, like the
+    /// thousands separator `,`, but got something else instead.
+    ExpectedMark(Option<Span>),
+    /// The error occured because the thousands separator and the decimal mark
+    /// of `?format_marks` were the same, or `?sep` was the decimal mark.
+    /// The span covers both marks of `?format_marks`, or the separator of `?sep`.
+    SameMarks(Span),
+    /// The error occured because a command expected an integer in the
+    /// given range, but got something else instead.
+    ExpectedIntegerInRange(Option<Span>, RangeInclusive<usize>),
+    /// The error occured because a command expected an integer in the
+    /// given range or `off`, like `?fixedpoint`, but got something else instead.
+    ExpectedIntegerInRangeOrOff(Option<Span>, RangeInclusive<usize>),
+    /// The error occured because `?seed` expected an integer from 0 to
+    /// `MAX_SEED`, but got something else instead.
+    ExpectedSeed(Option<Span>),
+    /// The error occured because a command expected an overflow policy,
+    /// `allow`, `warn` or `error`, but got something else instead.
+    ExpectedOverflowPolicy(Option<Span>),
+    /// The error occured because an imaginary number, like `2i`, was used
+    /// outside of complex mode.
+    ImaginaryOutsideComplexMode(Span),
+    /// The error occured because a number literal, like `3.5`, wasn't an
+    /// integer in integer mode.
+    NonIntegerLiteral(Span),
+    /// The error occured because an identifier in roman mode was made of
+    /// Roman numeral letters, but wasn't a valid Roman numeral.
+    InvalidRomanNumeral(Span),
+}
+
+impl From<TokenizerError> for ParserError {
+    fn from(value: TokenizerError) -> Self {
+        ParserError::Tokenizer(value)
+    }
+}
+
+/// The binding powers of the operators, used by the pratt parser to decide
+/// which operations are evaluated first. An operator with a higher binding power
+/// binds tighter, like `*` compared to `+`.
+///
+/// Infix operators have a left and a right binding power. If the left one is
+/// lower, the operator is left-associative, so `1 - 2 - 3` is `(1 - 2) - 3`.
+/// If the right one is lower, it's right-associative, so `2^3^2` is `2^(3^2)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrecedenceTable {
+    /// Assignments, like `=` and `+=`.
+    pub assignment: (u8, u8),
+    /// `or`.
+    pub or: (u8, u8),
+    /// `and`.
+    pub and: (u8, u8),
+    /// Comparisons, like `<` and `==`.
+    pub comparison: (u8, u8),
+    /// `<?` and `>?`.
+    pub min_max: (u8, u8),
+    /// `+` and `-`.
+    pub additive: (u8, u8),
+    /// `*` and `/`.
+    pub multiplicative: (u8, u8),
+    /// `^`.
+    pub exponentiation: (u8, u8),
+    /// The prefix `not`.
+    pub not: u8,
+    /// The prefix `-`. It binds looser than `^`, so `-3^2` is `-(3^2)`, which
+    /// is `-9` like in mathematics, but tighter than `*`, so `3 * -2` is `-6`.
+    pub negation: u8,
+    /// The prefix `√`.
+    pub sqrt: u8,
+    /// The postfix `!`.
+    pub factorial: u8,
+    /// The postfix `%`.
+    pub percent: u8,
+    /// `|>`. Its right-hand side is always a function name, so it only
+    /// has a left binding power.
+    pub pipe: u8,
+}
+
+impl PrecedenceTable {
+    /// The binding powers used by the calculator.
+    pub const DEFAULT: PrecedenceTable = PrecedenceTable {
+        // The right binding power is lower, so `x = y = 1` is `x = (y = 1)`.
+        assignment: (2, 1),
+        or: (3, 4),
+        and: (5, 6),
+        comparison: (7, 8),
+        // `<?` and `>?` bind tighter than comparisons, so `1 <? 2 < 3` is `(1 <? 2) < 3`,
+        // but looser than arithmetic, so `1 + 2 >? 4` is `(1 + 2) >? 4`.
+        min_max: (9, 10),
+        additive: (11, 12),
+        multiplicative: (13, 14),
+        // Exponentiation is right-associative, so `2^3^2` is `2^(3^2)`.
+        // It also binds tighter than negation, so `-3^2` is `-(3^2)`.
+        exponentiation: (18, 17),
+        // `not` binds looser than comparisons, so `not 1 == 2` is `not (1 == 2)`.
+        not: 7,
+        negation: 15,
+        // Like negation, `√2^2` is `√(2^2)`.
+        sqrt: 15,
+        // Factorial binds tighter than everything else, so `-3!` is `-(3!)`
+        // and `2^3!` is `2^(3!)`.
+        factorial: 19,
+        percent: 19,
+        // Pipes bind looser than everything but assignments, so `x = 1 + 3 |> sqrt`
+        // is `x = sqrt(1 + 3)`.
+        pipe: 2,
+    };
+
+    /// Describes the binding power of prefix operators.
+    fn prefix(&self, op: &UnaryOperation) -> u8 {
+        match op {
+            UnaryOperation::Not => self.not,
+            UnaryOperation::Negation => self.negation,
+            UnaryOperation::SquareRoot => self.sqrt,
+            UnaryOperation::Factorial | UnaryOperation::Percent => {
+                unreachable!("factorial and percent are postfix operators")
+            }
+        }
+    }
+
+    /// Describes the binding power of infix operators.
+    pub fn infix(&self, op: &BinaryOperation) -> (u8, u8) {
+        match op {
+            BinaryOperation::Or => self.or,
+            BinaryOperation::And => self.and,
+            BinaryOperation::Less
+            | BinaryOperation::LessOrEqual
+            | BinaryOperation::Greater
+            | BinaryOperation::GreaterOrEqual
+            | BinaryOperation::Equal
+            | BinaryOperation::NotEqual => self.comparison,
+            BinaryOperation::Minimum | BinaryOperation::Maximum => self.min_max,
+            BinaryOperation::Addition
+            | BinaryOperation::Subtraction
+            | BinaryOperation::AddPercentage
+            | BinaryOperation::SubtractPercentage => self.additive,
+            BinaryOperation::Multiplication | BinaryOperation::Division => self.multiplicative,
+            BinaryOperation::Exponentiation => self.exponentiation,
+        }
+    }
+}
+
+impl Default for PrecedenceTable {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
 }
 
 /// Parser datastructure.
 pub struct Parser<'a> {
     /// Tokenizer.
     tokenizer: Tokenizer<'a>,
+    /// Whether identifiers made of Roman numeral letters are numbers.
+    roman: bool,
+    /// Whether imaginary numbers are allowed, and `i` is the imaginary unit.
+    complex: bool,
+    /// The binding powers of the operators.
+    precedence: PrecedenceTable,
+    /// Whether parentheses left open at the end of the input are closed.
+    autoclose: bool,
+    /// Whether number literals must be integers.
+    integer: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -72,41 +674,477 @@ impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         Self {
             tokenizer: Tokenizer::new(input),
+            roman: false,
+            complex: false,
+            precedence: PrecedenceTable::DEFAULT,
+            autoclose: false,
+            integer: false,
+        }
+    }
+
+    /// Sets whether Roman numerals, like `XIV`, are parsed as numbers.
+    /// Otherwise, they are variables like any other identifier.
+    pub fn roman(mut self, roman: bool) -> Self {
+        self.roman = roman;
+        self
+    }
+
+    /// Sets whether imaginary numbers, like `2i`, are allowed. In complex mode,
+    /// `i` alone is the imaginary unit instead of a variable.
+    pub fn complex(mut self, complex: bool) -> Self {
+        self.complex = complex;
+        self
+    }
+
+    /// Sets the binding powers of the operators, to change their precedence.
+    pub fn precedence(mut self, precedence: PrecedenceTable) -> Self {
+        self.precedence = precedence;
+        self
+    }
+
+    /// Sets whether parentheses left open at the end of the input are closed,
+    /// so that `(1 + (2 * 3` is parsed like `(1 + (2 * 3))`.
+    pub fn autoclose(mut self, autoclose: bool) -> Self {
+        self.autoclose = autoclose;
+        self
+    }
+
+    /// Sets whether number literals must be integers, like in integer mode.
+    pub fn integer(mut self, integer: bool) -> Self {
+        self.integer = integer;
+        self
+    }
+
+    /// Tokenizes the whole input. Also returns the number of parentheses
+    /// closed at the end of the input, if they are closed automatically.
+    fn tokens(
+        self,
+    ) -> Result<(Peekable<impl ExactSizeIterator<Item = Token>>, usize), ParserError> {
+        // Tokenize the whole input first, so tokenizer errors are reported
+        // before we start parsing.
+        let mut tokens = self.tokenizer.tokenize().collect::<Result<Vec<_>, _>>()?;
+        if self.roman {
+            for token in &mut tokens {
+                Self::roman_numeral(token)?;
+            }
+        }
+        for token in &mut tokens {
+            Self::imaginary_unit(token, self.complex)?;
+        }
+        if self.integer {
+            for token in &tokens {
+                Self::integer_literal(token)?;
+            }
+        }
+        let missing = match self.autoclose {
+            true => unclosed_parentheses(&tokens),
+            false => 0,
+        };
+        // The closing parentheses are empty, at the end of the input
+        let end = tokens.last().map_or(0, |token| token.span.end);
+        tokens.extend((0..missing).map(|_| Token {
+            kind: TokenKind::CloseParenthesis,
+            span: Span::from(end..end),
+        }));
+        Ok((tokens.into_iter().peekable(), missing))
+    }
+
+    /// Turns an identifier token into a number token if it's a Roman numeral.
+    fn roman_numeral(token: &mut Token) -> Result<(), ParserError> {
+        if let TokenKind::Identifier(identifier) = &token.kind {
+            if is_roman_letters(identifier) {
+                let value =
+                    roman_to_int(identifier).ok_or(ParserError::InvalidRomanNumeral(token.span))?;
+                token.kind = TokenKind::Number(f64::from(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a number token is an integer.
+    fn integer_literal(token: &Token) -> Result<(), ParserError> {
+        match token.kind {
+            TokenKind::Number(num) if num.fract() != 0.0 => {
+                Err(ParserError::NonIntegerLiteral(token.span))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that imaginary numbers are only used in complex mode.
+    /// In complex mode, turns the `i` identifier into the imaginary unit.
+    fn imaginary_unit(token: &mut Token, complex: bool) -> Result<(), ParserError> {
+        match &token.kind {
+            TokenKind::Imaginary(_) if !complex => {
+                Err(ParserError::ImaginaryOutsideComplexMode(token.span))
+            }
+            TokenKind::Identifier(identifier) if complex && identifier == "i" => {
+                token.kind = TokenKind::Imaginary(1.0);
+                Ok(())
+            }
+            _ => Ok(()),
         }
     }
 
     /// Entrypoint for parsing.
     pub fn parse(self) -> Result<ParseTree, ParserError> {
-        let mut tokens = self.tokenizer.tokenize();
+        self.parse_counted().map(|(parse_tree, ..)| parse_tree)
+    }
+
+    /// Entrypoint for parsing, which also returns the number of tokens in the
+    /// input, without whitespace. Tools can use it to measure how complex an input is.
+    pub fn parse_with_token_count(self) -> Result<(ParseTree, usize), ParserError> {
+        self.parse_counted()
+            .map(|(parse_tree, count, _)| (parse_tree, count))
+    }
+
+    /// Entrypoint for parsing, which also returns the number of parentheses
+    /// left open at the end of the input and closed with `autoclose`, so
+    /// that the user can be told about them.
+    pub fn parse_with_closed_parentheses(self) -> Result<(ParseTree, usize), ParserError> {
+        self.parse_counted()
+            .map(|(parse_tree, _, closed)| (parse_tree, closed))
+    }
+
+    /// Parses the whole input to its parse tree, the number of tokens and
+    /// the number of parentheses closed with `autoclose`.
+    fn parse_counted(self) -> Result<(ParseTree, usize, usize), ParserError> {
+        let precedence = self.precedence;
+        let (mut tokens, closed) = self.tokens()?;
+        // The tokens are already collected, so counting them is free
+        let count = tokens.len();
         let parse_tree = match tokens.peek() {
             // If there are not tokens to parse, return an empty parse tree.
-            None => Ok(ParseTree::Empty),
-            // If the first token is a special token, handle it.
-            Some(token) if token.kind == TokenKind::Special(SpecialKind::Quit) => {
-                Ok(ParseTree::Quit)
-            }
-            Some(token) if token.kind == TokenKind::Special(SpecialKind::Unrecognized) => {
-                Err(ParserError::UnrecognizedSpecial(Some(token.span)))
+            None => ParseTree::Empty,
+            // If the first token is a special token, handle the command.
+            Some(&Token {
+                kind: TokenKind::Special(kind),
+                span,
+            }) => {
+                tokens.next();
+                let command = Self::command(kind, span, &mut tokens, &precedence)?;
+                Self::end(&mut tokens)?;
+                command
             }
-            // Otherwise, parse the tokens using a pratt parser.
-            _ => Ok(ParseTree::Expression(Self::pratt_parser(&mut tokens, 0)?)),
+            // Otherwise, parse the tokens as an expression.
+            _ => ParseTree::Expression(Self::expression(&mut tokens, &precedence)?),
         };
 
-        parse_tree
+        Ok((parse_tree, count, closed))
     }
 
-    /// Describes the binding power of unary operators.
-    fn prefix_binding_power(op: &UnaryOperation) -> u8 {
-        match op {
-            UnaryOperation::Negation => 5,
+    /// Entrypoint for parsing a bare expression, without the special commands
+    /// used by the calculator prompt. Empty inputs and special commands are errors.
+    pub fn parse_expression(self) -> Result<Expression, ParserError> {
+        let precedence = self.precedence;
+        let (mut tokens, _) = self.tokens()?;
+        match tokens.peek() {
+            None => Err(ParserError::EmptyInput),
+            Some(Token {
+                kind: TokenKind::Special(_),
+                span,
+            }) => Err(ParserError::UnrecognizedSpecial(Some(*span))),
+            _ => Self::expression(&mut tokens, &precedence),
         }
     }
 
-    /// Describes the binding power of infix operators.
-    fn infix_binding_power(op: &BinaryOperation) -> (u8, u8) {
-        match op {
-            BinaryOperation::Addition | BinaryOperation::Subtraction => (1, 2),
-            BinaryOperation::Multiplication | BinaryOperation::Division => (3, 4),
+    /// Parses a whole expression using a pratt parser. All the tokens must be consumed,
+    /// so the input can't continue after a complete expression, like in `1 2`.
+    fn expression(
+        tokens: &mut Peekable<impl Iterator<Item = Token>>,
+        precedence: &PrecedenceTable,
+    ) -> Result<Expression, ParserError> {
+        let expr = Self::pratt_parser(tokens, precedence, 0)?;
+        Self::end(tokens)?;
+        Ok(expr)
+    }
+
+    /// Checks that there are no tokens left after an expression or a command,
+    /// like in `1 2` or `?quit now`.
+    fn end(tokens: &mut impl Iterator<Item = Token>) -> Result<(), ParserError> {
+        // The pratt parser stops at a closing parenthesis, even if there is
+        // no opening parenthesis to match it, like in `1 + 2)`.
+        match tokens.next() {
+            None => Ok(()),
+            Some(Token {
+                kind: TokenKind::CloseParenthesis,
+                span,
+            }) => Err(ParserError::UnexpectedCloseParenthesis(span)),
+            Some(token) => Err(ParserError::TrailingTokens(token.span)),
+        }
+    }
+
+    /// Parses a special command and its arguments.
+    fn command(
+        kind: SpecialKind,
+        span: Span,
+        tokens: &mut Peekable<impl Iterator<Item = Token>>,
+        precedence: &PrecedenceTable,
+    ) -> Result<ParseTree, ParserError> {
+        match kind {
+            SpecialKind::Quit => Ok(ParseTree::Quit),
+            SpecialKind::Bits => Ok(ParseTree::Bits),
+            SpecialKind::Ulp => Ok(ParseTree::Ulp),
+            SpecialKind::Explain => Ok(ParseTree::Explain),
+            SpecialKind::Copy => Ok(ParseTree::Copy),
+            SpecialKind::Eps => Ok(ParseTree::Eps),
+            SpecialKind::Export => Ok(ParseTree::Export),
+            SpecialKind::Eq => Ok(ParseTree::Eq(Self::number(tokens)?, Self::number(tokens)?)),
+            SpecialKind::Scientific => Ok(ParseTree::Scientific(Self::toggle(tokens)?)),
+            SpecialKind::Digits => Ok(ParseTree::Digits(Self::integer_in_range(
+                tokens,
+                MIN_DIGITS..=MAX_DIGITS,
+            )?)),
+            SpecialKind::Time => Ok(ParseTree::Time(Self::toggle(tokens)?)),
+            SpecialKind::Profile => Ok(ParseTree::Profile(Self::toggle(tokens)?)),
+            SpecialKind::Roman => Ok(ParseTree::Roman(Self::toggle(tokens)?)),
+            SpecialKind::Complex => Ok(ParseTree::Complex(Self::toggle(tokens)?)),
+            SpecialKind::Money => Ok(ParseTree::Money(Self::toggle(tokens)?)),
+            SpecialKind::Approx => Ok(ParseTree::Approx(Self::toggle(tokens)?)),
+            SpecialKind::BigInt => Ok(ParseTree::BigInt(Self::toggle(tokens)?)),
+            SpecialKind::AutoClose => Ok(ParseTree::AutoClose(Self::toggle(tokens)?)),
+            SpecialKind::Sep => {
+                let (separator, span) = Self::separator(tokens)?;
+                Ok(ParseTree::Separator(separator, span))
+            }
+            SpecialKind::FormatMarks => match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(identifier),
+                    ..
+                }) if identifier == "off" => {
+                    tokens.next();
+                    Ok(ParseTree::FormatMarks(None))
+                }
+                _ => {
+                    let (separator, separator_span) = Self::mark(tokens)?;
+                    let (decimal_mark, decimal_mark_span) = Self::mark(tokens)?;
+                    if separator == decimal_mark {
+                        let span = separator_span.merge(decimal_mark_span);
+                        return Err(ParserError::SameMarks(span));
+                    }
+                    Ok(ParseTree::FormatMarks(Some((separator, decimal_mark))))
+                }
+            },
+            SpecialKind::RelErr => match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(identifier),
+                    ..
+                }) if identifier == "off" => {
+                    tokens.next();
+                    Ok(ParseTree::RelativeError(None))
+                }
+                _ => Ok(ParseTree::RelativeError(Some(Self::expression(
+                    tokens, precedence,
+                )?))),
+            },
+            SpecialKind::Store => Ok(ParseTree::Store(Self::integer_in_range(
+                tokens,
+                1..=MEMORY_SLOTS,
+            )?)),
+            SpecialKind::Overflow => Ok(ParseTree::Overflow(Self::overflow_policy(tokens)?)),
+            SpecialKind::Seed => match tokens.next() {
+                Some(Token {
+                    kind: TokenKind::Number(num),
+                    ..
+                }) if num.fract() == 0.0 && (0.0..=MAX_SEED as f64).contains(&num) => {
+                    Ok(ParseTree::Seed(num as u64))
+                }
+                t => Err(ParserError::ExpectedSeed(t.map(|token| token.span))),
+            },
+            SpecialKind::Precision => {
+                Self::keyword(tokens, "auto")?;
+                Ok(ParseTree::PrecisionAuto)
+            }
+            SpecialKind::FixedPoint => match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(identifier),
+                    ..
+                }) if identifier == "off" => {
+                    tokens.next();
+                    Ok(ParseTree::FixedPoint(None))
+                }
+                _ => match Self::integer_in_range(tokens, 0..=MAX_DECIMALS) {
+                    Ok(decimals) => Ok(ParseTree::FixedPoint(Some(decimals))),
+                    Err(ParserError::ExpectedIntegerInRange(span, range)) => {
+                        Err(ParserError::ExpectedIntegerInRangeOrOff(span, range))
+                    }
+                    Err(error) => Err(error),
+                },
+            },
+            SpecialKind::Int => match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(identifier),
+                    ..
+                }) if identifier == "floor" => {
+                    tokens.next();
+                    Ok(ParseTree::Integer(Some(IntegerDivision::Floor)))
+                }
+                _ => Ok(ParseTree::Integer(
+                    Self::toggle(tokens)?.then_some(IntegerDivision::Exact),
+                )),
+            },
+            SpecialKind::Settings => match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(identifier),
+                    ..
+                }) if identifier == "redo" => {
+                    tokens.next();
+                    Ok(ParseTree::SettingsRedo)
+                }
+                _ => {
+                    Self::keyword(tokens, "undo")?;
+                    Ok(ParseTree::SettingsUndo)
+                }
+            },
+            SpecialKind::Total => match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(identifier),
+                    ..
+                }) if identifier == "reset" => {
+                    tokens.next();
+                    Ok(ParseTree::TotalReset)
+                }
+                _ => Ok(ParseTree::Total(Self::toggle(tokens)?)),
+            },
+            SpecialKind::Last => match tokens.peek() {
+                None => Ok(ParseTree::Last(DEFAULT_LAST)),
+                Some(_) => Ok(ParseTree::Last(Self::integer_in_range(
+                    tokens,
+                    1..=MAX_HISTORY,
+                )?)),
+            },
+            // The two expressions are separated by `;`, like `?diff 1/3 ; 0.333`
+            SpecialKind::Diff => {
+                let first = Self::pratt_parser(tokens, precedence, 0)?;
+                match tokens.next() {
+                    Some(Token {
+                        kind: TokenKind::Semicolon,
+                        ..
+                    }) => {}
+                    Some(Token {
+                        kind: TokenKind::CloseParenthesis,
+                        span,
+                    }) => return Err(ParserError::UnexpectedCloseParenthesis(span)),
+                    t => return Err(ParserError::ExpectedKeyword(t.map(|token| token.span), ";")),
+                }
+                let second = Self::expression(tokens, precedence)?;
+                Ok(ParseTree::Diff(first, second))
+            }
+            SpecialKind::Unrecognized => Err(ParserError::UnrecognizedSpecial(Some(span))),
+        }
+    }
+
+    /// Parses an `on` or `off` command argument.
+    fn toggle(tokens: &mut impl Iterator<Item = Token>) -> Result<bool, ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Identifier(toggle),
+                ..
+            }) if toggle == "on" => Ok(true),
+            Some(Token {
+                kind: TokenKind::Identifier(toggle),
+                ..
+            }) if toggle == "off" => Ok(false),
+            t => Err(ParserError::ExpectedToggle(t.map(|token| token.span))),
+        }
+    }
+
+    /// Parses a command argument that must be the given keyword.
+    fn keyword(
+        tokens: &mut impl Iterator<Item = Token>,
+        keyword: &'static str,
+    ) -> Result<(), ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Identifier(identifier),
+                ..
+            }) if identifier == keyword => Ok(()),
+            t => Err(ParserError::ExpectedKeyword(
+                t.map(|token| token.span),
+                keyword,
+            )),
+        }
+    }
+
+    /// Parses a separator between groups of digits, `,` or `_`, and its span.
+    fn separator(tokens: &mut impl Iterator<Item = Token>) -> Result<(char, Span), ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Comma,
+                span,
+            }) => Ok((',', span)),
+            Some(Token {
+                kind: TokenKind::Underscore,
+                span,
+            }) => Ok(('_', span)),
+            t => Err(ParserError::ExpectedSeparator(t.map(|token| token.span))),
+        }
+    }
+
+    /// Parses an overflow policy, `allow`, `warn` or `error`.
+    fn overflow_policy(
+        tokens: &mut impl Iterator<Item = Token>,
+    ) -> Result<OverflowPolicy, ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Identifier(identifier),
+                span,
+            }) => match identifier.as_str() {
+                "allow" => Ok(OverflowPolicy::Allow),
+                "warn" => Ok(OverflowPolicy::Warn),
+                "error" => Ok(OverflowPolicy::Error),
+                _ => Err(ParserError::ExpectedOverflowPolicy(Some(span))),
+            },
+            t => Err(ParserError::ExpectedOverflowPolicy(
+                t.map(|token| token.span),
+            )),
+        }
+    }
+
+    /// Parses a mark, `,`, `.` or `_`, and its span.
+    fn mark(tokens: &mut impl Iterator<Item = Token>) -> Result<(char, Span), ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Comma,
+                span,
+            }) => Ok((',', span)),
+            Some(Token {
+                kind: TokenKind::Dot,
+                span,
+            }) => Ok(('.', span)),
+            Some(Token {
+                kind: TokenKind::Underscore,
+                span,
+            }) => Ok(('_', span)),
+            t => Err(ParserError::ExpectedMark(t.map(|token| token.span))),
+        }
+    }
+
+    /// Parses a number literal command argument.
+    fn number(tokens: &mut impl Iterator<Item = Token>) -> Result<f64, ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Number(num),
+                ..
+            }) => Ok(num),
+            t => Err(ParserError::ExpectedNumber(t.map(|token| token.span))),
+        }
+    }
+
+    /// Parses an integer command argument, which must be in the given range.
+    fn integer_in_range(
+        tokens: &mut impl Iterator<Item = Token>,
+        range: RangeInclusive<usize>,
+    ) -> Result<usize, ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Number(num),
+                ..
+            }) if num.fract() == 0.0 && range.contains(&(num as usize)) => Ok(num as usize),
+            t => Err(ParserError::ExpectedIntegerInRange(
+                t.map(|token| token.span),
+                range,
+            )),
         }
     }
 
@@ -114,6 +1152,7 @@ impl<'a> Parser<'a> {
     /// This is the main parsing function.
     fn pratt_parser(
         tokens: &mut Peekable<impl Iterator<Item = Token>>,
+        precedence: &PrecedenceTable,
         min_bp: u8,
     ) -> Result<Expression, ParserError> {
         // Handles tokens that can start an expression
@@ -121,48 +1160,124 @@ impl<'a> Parser<'a> {
             // Numbers
             Some(Token {
                 kind: TokenKind::Number(num),
-                ..
-            }) => Expression::Atom(num),
+                span,
+            }) => Expression::new(ExpressionKind::Atom(Number(num)), span),
+            // Imaginary numbers
+            Some(Token {
+                kind: TokenKind::Imaginary(num),
+                span,
+            }) => Expression::new(ExpressionKind::Imaginary(Number(num)), span),
+            // Template placeholders
+            Some(Token {
+                kind: TokenKind::Placeholder(index),
+                span,
+            }) => Expression::new(ExpressionKind::Placeholder(index), span),
+            // Text, like the format of `fmt`
+            Some(Token {
+                kind: TokenKind::Text(text),
+                span,
+            }) => Expression::new(ExpressionKind::Text(text), span),
+            // Function calls. An identifier followed by `(` is a function name.
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            }) if matches!(
+                tokens.peek(),
+                Some(Token {
+                    kind: TokenKind::OpenParenthesis,
+                    ..
+                })
+            ) =>
+            {
+                Self::call(name, span, tokens, precedence)?
+            }
+            // Variables. A function name alone, like `sqrt`, is a variable too,
+            // since it can be assigned, and the runtime suggests calling it otherwise.
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            }) => Expression::new(ExpressionKind::Variable(name), span),
             // Unary operators
             Some(Token {
-                kind: TokenKind::Operation(OperationKind::Minus),
-                ..
+                kind:
+                    TokenKind::Operation(
+                        kind @ (OperationKind::Minus | OperationKind::Not | OperationKind::Sqrt),
+                    ),
+                span,
             }) => {
-                let op = UnaryOperation::Negation;
+                let op = match kind {
+                    OperationKind::Not => UnaryOperation::Not,
+                    OperationKind::Sqrt => UnaryOperation::SquareRoot,
+                    _ => UnaryOperation::Negation,
+                };
                 // Recursive pratt parser call
-                let rhs = Self::pratt_parser(tokens, Self::prefix_binding_power(&op))?;
-                Expression::Unary {
-                    operation: op,
-                    operand: Box::new(rhs),
-                }
+                let rhs = Self::pratt_parser(tokens, precedence, precedence.prefix(&op))?;
+                let span = span.merge(rhs.span);
+                Expression::new(
+                    ExpressionKind::Unary {
+                        operation: op,
+                        operand: Box::new(rhs),
+                    },
+                    span,
+                )
             }
             // Parenthesis
             Some(Token {
                 kind: TokenKind::OpenParenthesis,
-                ..
+                span: opening_span,
             }) => {
                 // Recursive pratt parser call
-                let lhs = Self::pratt_parser(tokens, 0)?;
+                let mut lhs = Self::pratt_parser(tokens, precedence, 0)?;
                 // Consume the closing parenthesis
                 let closing_parenthesis = tokens.next();
                 // Check if parenthesis is matched
-                if !matches!(
-                    closing_parenthesis,
+                match closing_parenthesis {
                     Some(Token {
                         kind: TokenKind::CloseParenthesis,
-                        ..
-                    })
-                ) {
-                    return Err(ParserError::UnclosedParenthesis(
-                        closing_parenthesis.map(|token| token.span),
-                    ));
+                        span: closing_span,
+                    }) => {
+                        // The expression's span now includes the parentheses
+                        lhs.span = opening_span.merge(closing_span);
+                    }
+                    _ => {
+                        return Err(ParserError::UnclosedParenthesis(
+                            closing_parenthesis.map(|token| token.span),
+                        ))
+                    }
                 }
 
                 lhs
             }
+            // Constant definitions
+            Some(Token {
+                kind: TokenKind::Const,
+                span,
+            }) => Self::constant(span, tokens, precedence)?,
+            // Binary operators need a left-hand side, so they can't start an expression.
+            // `-` was handled above, since it's also a prefix operator.
+            Some(Token {
+                kind: TokenKind::Operation(kind),
+                span,
+            }) if !matches!(kind, OperationKind::Bang | OperationKind::Percent) => {
+                return Err(ParserError::BinaryOperatorAtStart(kind, span))
+            }
+            // A dot on its own is only a mark of `?format_marks`. In expressions,
+            // it's still an unrecognized character, so `.5` isn't taken for `0.5`.
+            Some(Token {
+                kind: TokenKind::Dot,
+                span,
+            }) => {
+                return Err(ParserError::Tokenizer(
+                    TokenizerError::UnrecognizedCharacter(span),
+                ))
+            }
             t => return Err(ParserError::ExpectedExprStart(t.map(|token| token.span))),
         };
 
+        // Whether the last operation parsed in this loop was a comparison.
+        // This lets us detect chained comparisons.
+        let mut last_was_comparison = false;
+
         loop {
             let op = match tokens.peek() {
                 // Break if end of input is reached.
@@ -173,22 +1288,111 @@ impl<'a> Parser<'a> {
                     ..
                 }) => break,
 
+                // Postfix operators are handled separately, since they don't have a
+                // right-hand side.
+                Some(Token {
+                    kind:
+                        TokenKind::Operation(kind @ (OperationKind::Bang | OperationKind::Percent)),
+                    span,
+                }) => {
+                    let (operation, bp) = match kind {
+                        OperationKind::Percent => (UnaryOperation::Percent, precedence.percent),
+                        _ => (UnaryOperation::Factorial, precedence.factorial),
+                    };
+                    let span = *span;
+                    if bp < min_bp {
+                        break;
+                    }
+                    // Consume the operation token
+                    tokens.next();
+                    let span = lhs.span.merge(span);
+                    lhs = Expression::new(
+                        ExpressionKind::Unary {
+                            operation,
+                            operand: Box::new(lhs),
+                        },
+                        span,
+                    );
+                    continue;
+                }
+
+                // Assignments are handled separately, since they aren't `BinaryOperation`s.
+                Some(Token {
+                    kind: TokenKind::Assignment(kind),
+                    span,
+                }) => {
+                    let (kind, span) = (*kind, *span);
+                    let (l_bp, r_bp) = precedence.assignment;
+                    if l_bp < min_bp {
+                        break;
+                    }
+                    // Consume the assignment token
+                    tokens.next();
+                    lhs = Self::assignment(lhs, kind, span, tokens, precedence, r_bp)?;
+                    continue;
+                }
+
+                // Pipes are handled separately, since their right-hand side is a function name.
+                Some(Token {
+                    kind: TokenKind::Pipe,
+                    ..
+                }) => {
+                    if precedence.pipe < min_bp {
+                        break;
+                    }
+                    // Consume the pipe token
+                    tokens.next();
+                    lhs = Self::pipe(lhs, tokens)?;
+                    // The call isn't a comparison, so the next one can't be chained
+                    last_was_comparison = false;
+                    continue;
+                }
+
                 // Transform tokens into `BinaryOperation`s.
                 Some(Token {
                     kind: TokenKind::Operation(op),
-                    ..
+                    span,
                 }) => match op {
                     OperationKind::Plus => BinaryOperation::Addition,
                     OperationKind::Minus => BinaryOperation::Subtraction,
                     OperationKind::Star => BinaryOperation::Multiplication,
                     OperationKind::Slash => BinaryOperation::Division,
+                    OperationKind::Caret => BinaryOperation::Exponentiation,
+                    OperationKind::Less => BinaryOperation::Less,
+                    OperationKind::LessEqual => BinaryOperation::LessOrEqual,
+                    OperationKind::Greater => BinaryOperation::Greater,
+                    OperationKind::GreaterEqual => BinaryOperation::GreaterOrEqual,
+                    OperationKind::EqualEqual => BinaryOperation::Equal,
+                    OperationKind::BangEqual => BinaryOperation::NotEqual,
+                    OperationKind::LessQuestion => BinaryOperation::Minimum,
+                    OperationKind::GreaterQuestion => BinaryOperation::Maximum,
+                    OperationKind::And => BinaryOperation::And,
+                    OperationKind::Or => BinaryOperation::Or,
+                    // `not` and `√` are prefix operators only, and `!` and `%` were handled above
+                    OperationKind::Not
+                    | OperationKind::Sqrt
+                    | OperationKind::Bang
+                    | OperationKind::Percent => {
+                        return Err(ParserError::ExpectedBinaryOp(Some(*span)))
+                    }
                 },
 
-                t => return Err(ParserError::ExpectedBinaryOp(t.map(|token| token.span))),
+                Some(Token {
+                    kind: TokenKind::Dot,
+                    span,
+                }) => {
+                    return Err(ParserError::Tokenizer(
+                        TokenizerError::UnrecognizedCharacter(*span),
+                    ))
+                }
+
+                // Break if any other token is reached, like a number in `1 2`.
+                // It's up to the caller to decide whether that token is expected.
+                _ => break,
             };
 
             // Handle binding powers
-            let (l_bp, r_bp) = Self::infix_binding_power(&op);
+            let (l_bp, r_bp) = precedence.infix(&op);
             if l_bp < min_bp {
                 break;
             }
@@ -197,15 +1401,1443 @@ impl<'a> Parser<'a> {
             tokens.next();
 
             // Recursive pratt parser call
-            let rhs = Self::pratt_parser(tokens, r_bp)?;
+            let rhs = Self::pratt_parser(tokens, precedence, r_bp)?;
+
+            // Comparisons are chained like in math notation, so `a < b < c`
+            // means `a < b and b < c`, with `b` only evaluated once.
+            let chained = last_was_comparison && op.is_comparison();
+            last_was_comparison = op.is_comparison();
+            lhs = if chained {
+                Self::chain(lhs, op, rhs)
+            } else {
+                Self::binary_or_percentage(op, lhs, rhs)
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parses the function name after `|>`, and desugars `x |> f` into `f(x)`.
+    /// Like other calls, the runtime checks that the function exists.
+    fn pipe(
+        argument: Expression,
+        tokens: &mut impl Iterator<Item = Token>,
+    ) -> Result<Expression, ParserError> {
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            }) => {
+                let span = argument.span.merge(span);
+                Ok(Expression::new(
+                    ExpressionKind::Call {
+                        name,
+                        arguments: vec![argument],
+                    },
+                    span,
+                ))
+            }
+            t => Err(ParserError::ExpectedFunctionName(t.map(|token| token.span))),
+        }
+    }
+
+    /// Parses the comma-separated arguments of a call to the function `name`.
+    /// The next token must be the opening parenthesis.
+    fn call(
+        name: String,
+        name_span: Span,
+        tokens: &mut Peekable<impl Iterator<Item = Token>>,
+        precedence: &PrecedenceTable,
+    ) -> Result<Expression, ParserError> {
+        // Consume the opening parenthesis
+        tokens.next();
+
+        let mut arguments = Vec::new();
+        // A function can be called without arguments, like `f()`
+        if let Some(Token {
+            kind: TokenKind::CloseParenthesis,
+            span,
+        }) = tokens.peek()
+        {
+            let span = name_span.merge(*span);
+            tokens.next();
+            return Ok(Expression::new(
+                ExpressionKind::Call { name, arguments },
+                span,
+            ));
+        }
+
+        loop {
+            // Recursive pratt parser call. The first argument of `iterate` is a
+            // function name, like in `iterate(sqrt, 16, 2)`, which is parsed as a variable.
+            arguments.push(Self::pratt_parser(tokens, precedence, 0)?);
+            // Either there is another argument, or the call is over
+            match tokens.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::CloseParenthesis,
+                    span,
+                }) => {
+                    return Ok(Expression::new(
+                        ExpressionKind::Call { name, arguments },
+                        name_span.merge(span),
+                    ))
+                }
+                t => return Err(ParserError::UnclosedParenthesis(t.map(|token| token.span))),
+            }
+        }
+    }
 
-            lhs = Expression::Binary {
-                operation: op,
+    /// Creates a binary expression, like `binary`. Adding or subtracting a percentage,
+    /// like in `200 + 10%`, is relative to the left operand: it's `200 + 10% of 200`,
+    /// which is `220`. Anywhere else, a percentage is divided by 100, so `200 * 10%` is `20`.
+    fn binary_or_percentage(
+        operation: BinaryOperation,
+        lhs: Expression,
+        rhs: Expression,
+    ) -> Expression {
+        let percentage_operation = match operation {
+            BinaryOperation::Addition => BinaryOperation::AddPercentage,
+            BinaryOperation::Subtraction => BinaryOperation::SubtractPercentage,
+            _ => return Self::binary(operation, lhs, rhs),
+        };
+        let ExpressionKind::Unary {
+            operation: UnaryOperation::Percent,
+            ..
+        } = rhs.kind()
+        else {
+            return Self::binary(operation, lhs, rhs);
+        };
+
+        // The percentage itself becomes the right operand
+        let span = lhs.span.merge(rhs.span);
+        let ExpressionKind::Unary { operand, .. } = rhs.into_kind() else {
+            unreachable!("the right operand is a percentage");
+        };
+        Expression::new(
+            ExpressionKind::Binary {
+                operation: percentage_operation,
+                lhs: Box::new(lhs),
+                rhs: operand,
+            },
+            span,
+        )
+    }
+
+    /// Creates a binary expression spanning both of its operands.
+    fn binary(operation: BinaryOperation, lhs: Expression, rhs: Expression) -> Expression {
+        let span = lhs.span.merge(rhs.span);
+        Expression::new(
+            ExpressionKind::Binary {
+                operation,
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
+            },
+            span,
+        )
+    }
+
+    /// Adds a comparison to the chain of comparisons `lhs`, which is either
+    /// a single comparison or already a chain.
+    fn chain(lhs: Expression, operation: BinaryOperation, rhs: Expression) -> Expression {
+        let span = lhs.span.merge(rhs.span);
+        let (first, mut comparisons) = match lhs.into_kind() {
+            ExpressionKind::Binary {
+                operation,
+                lhs,
+                rhs,
+            } => (lhs, vec![(operation, *rhs)]),
+            ExpressionKind::Comparisons { first, comparisons } => (first, comparisons),
+            _ => unreachable!("only comparisons are chained"),
+        };
+        comparisons.push((operation, rhs));
+        Expression::new(ExpressionKind::Comparisons { first, comparisons }, span)
+    }
+
+    /// Parses an assignment to the `target` expression, which must be a variable.
+    /// Compound assignments like `x += 1` are desugared into `x = x + 1`.
+    fn assignment(
+        target: Expression,
+        kind: AssignmentKind,
+        span: Span,
+        tokens: &mut Peekable<impl Iterator<Item = Token>>,
+        precedence: &PrecedenceTable,
+        r_bp: u8,
+    ) -> Result<Expression, ParserError> {
+        let ExpressionKind::Variable(name) = &target.kind else {
+            return Err(ParserError::InvalidAssignmentTarget(Some(span)));
+        };
+        let name = name.clone();
+
+        // Recursive pratt parser call
+        let rhs = Self::pratt_parser(tokens, precedence, r_bp)?;
+
+        let operation = match kind {
+            AssignmentKind::Equal => None,
+            AssignmentKind::PlusEqual => Some(BinaryOperation::Addition),
+            AssignmentKind::MinusEqual => Some(BinaryOperation::Subtraction),
+            AssignmentKind::StarEqual => Some(BinaryOperation::Multiplication),
+            AssignmentKind::SlashEqual => Some(BinaryOperation::Division),
+        };
+        let span = target.span.merge(rhs.span);
+        let value = match operation {
+            // The target variable is reused as the left operand
+            Some(operation) => Self::binary(operation, target, rhs),
+            None => rhs,
+        };
+
+        Ok(Expression::new(
+            ExpressionKind::Assignment {
+                name,
+                value: Box::new(value),
+                constant: false,
+            },
+            span,
+        ))
+    }
+
+    /// Parses a constant definition after the `const` keyword, like `const g = 9.81`.
+    /// Unlike variables, constants can't be defined with compound assignments.
+    fn constant(
+        span: Span,
+        tokens: &mut Peekable<impl Iterator<Item = Token>>,
+        precedence: &PrecedenceTable,
+    ) -> Result<Expression, ParserError> {
+        let name = match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }) => name,
+            t => {
+                return Err(ParserError::ExpectedConstantDefinition(
+                    t.map(|token| token.span),
+                ))
+            }
+        };
+        match tokens.next() {
+            Some(Token {
+                kind: TokenKind::Assignment(AssignmentKind::Equal),
+                ..
+            }) => {}
+            t => {
+                return Err(ParserError::ExpectedConstantDefinition(
+                    t.map(|token| token.span),
+                ))
+            }
+        }
+
+        // Recursive pratt parser call
+        let value = Self::pratt_parser(tokens, precedence, precedence.assignment.1)?;
+        let span = span.merge(value.span);
+        Ok(Expression::new(
+            ExpressionKind::Assignment {
+                name,
+                value: Box::new(value),
+                constant: true,
+            },
+            span,
+        ))
+    }
+}
+
+/// Tests for the parser.
+#[cfg(test)]
+mod tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use proptest::prelude::*;
+
+    use crate::{
+        parser::{
+            BinaryOperation, Expression, ExpressionKind, Number, ParseTree, Parser, ParserError,
+            PrecedenceTable, UnaryOperation,
+        },
+        random::MAX_SEED,
+        runtime::{IntegerDivision, OverflowPolicy},
+        tokenizer::{OperationKind, Span, TokenizerError},
+    };
+
+    /// Hashes a value with the default hasher.
+    fn hash(value: &impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Creates an atom.
+    fn atom(num: f64) -> Expression {
+        Expression::new(ExpressionKind::Atom(Number(num)), Span::from(0..0))
+    }
+
+    /// Parses an input expression.
+    fn parse(input: &str) -> Expression {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => expr,
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    #[test]
+    fn test_span_binary() {
+        let expr = parse("1 + 23");
+        assert_eq!(Span::from(0..6), expr.span);
+    }
+
+    #[test]
+    fn test_span_parenthesis() {
+        let expr = parse(" (1 + 2) ");
+        assert_eq!(Span::from(1..8), expr.span);
+    }
+
+    #[test]
+    fn test_span_chained_comparison() {
+        let expr = parse("1 < 2 < 3");
+        assert_eq!(Span::from(0..9), expr.span);
+        let ExpressionKind::Comparisons { first, comparisons } = expr.into_kind() else {
+            panic!("expected chained comparisons");
+        };
+        assert_eq!(Span::from(0..1), first.span);
+        let spans: Vec<_> = comparisons
+            .iter()
+            .map(|(_, operand)| operand.span)
+            .collect();
+        assert_eq!(vec![Span::from(4..5), Span::from(8..9)], spans);
+    }
+
+    #[test]
+    fn test_span_compound_assignment() {
+        let expr = parse("x += 10");
+        assert_eq!(Span::from(0..7), expr.span);
+        let ExpressionKind::Assignment { value, .. } = expr.into_kind() else {
+            panic!("expected an assignment");
+        };
+        // The synthesized `x + 10`
+        assert_eq!(Span::from(0..7), value.span);
+    }
+
+    #[test]
+    fn test_constant_definition() {
+        let expr = parse("const g = 9.81 * 1");
+        assert_eq!(Span::from(0..18), expr.span);
+        assert_eq!(
+            ExpressionKind::Assignment {
+                name: "g".to_string(),
+                value: Box::new(parse("9.81 * 1")),
+                constant: true,
+            },
+            expr.into_kind()
+        );
+        for (input, span) in [
+            ("const 1 = 2", Some(Span::from(6..7))),
+            ("const g += 1", Some(Span::from(8..10))),
+            ("const g", None),
+        ] {
+            assert_eq!(
+                Err(ParserError::ExpectedConstantDefinition(span)),
+                Parser::new(input).parse()
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed_number() {
+        // A radix prefix or a hexadecimal float without its digits.
+        for (input, span) in [("0b12", 0..4), ("1 + 0x", 4..6), ("0x1p", 0..4)] {
+            assert_eq!(
+                Err(ParserError::Tokenizer(TokenizerError::MalformedNumber(
+                    span.into()
+                ))),
+                Parser::new(input).parse(),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unexpected_close_parenthesis() {
+        assert_eq!(
+            Err(ParserError::UnexpectedCloseParenthesis(Span::from(5..6))),
+            Parser::new("1 + 2)").parse()
+        );
+        assert_eq!(
+            Err(ParserError::UnexpectedCloseParenthesis(Span::from(7..8))),
+            Parser::new("(1 + 2))").parse_expression()
+        );
+        // A lone `)` is where an expression should start.
+        assert_eq!(
+            Err(ParserError::ExpectedExprStart(Some(Span::from(0..1)))),
+            Parser::new(")").parse()
+        );
+    }
+
+    #[test]
+    fn test_binary_operator_at_start() {
+        assert_eq!(
+            Err(ParserError::BinaryOperatorAtStart(
+                OperationKind::Slash,
+                Span::from(0..1)
+            )),
+            Parser::new("/ 4").parse()
+        );
+        assert_eq!(
+            Err(ParserError::BinaryOperatorAtStart(
+                OperationKind::Star,
+                Span::from(0..1)
+            )),
+            Parser::new("* 3").parse()
+        );
+        assert_eq!(
+            Err(ParserError::BinaryOperatorAtStart(
+                OperationKind::Slash,
+                Span::from(4..5)
+            )),
+            Parser::new("2 * / 4").parse()
+        );
+        assert_eq!(
+            Err(ParserError::BinaryOperatorAtStart(
+                OperationKind::And,
+                Span::from(1..4)
+            )),
+            Parser::new("(and 1)").parse()
+        );
+        // `-` is also a prefix operator.
+        assert!(matches!(
+            parse("- 3").into_kind(),
+            ExpressionKind::Unary {
+                operation: UnaryOperation::Negation,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_expected_binary_op() {
+        // Prefix-only operators after a complete operand.
+        assert_eq!(
+            Err(ParserError::ExpectedBinaryOp(Some(Span::from(2..5)))),
+            Parser::new("1 not 2").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedBinaryOp(Some(Span::from(2..5)))),
+            Parser::new("4 √ 9").parse()
+        );
+    }
+
+    #[test]
+    fn test_trailing_tokens() {
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(2..3))),
+            Parser::new("1 2").parse()
+        );
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(2..3))),
+            Parser::new("3 4 + 5").parse()
+        );
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(6..7))),
+            Parser::new("3 + 4 x").parse_expression()
+        );
+        // Inside parentheses, the closing parenthesis is what's missing.
+        assert_eq!(
+            Err(ParserError::UnclosedParenthesis(Some(Span::from(3..4)))),
+            Parser::new("(1 2)").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_trailing_tokens() {
+        // Commands without arguments.
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(6..9))),
+            Parser::new("?quit now").parse()
+        );
+        assert_eq!(
+            Err(ParserError::UnexpectedCloseParenthesis(Span::from(6..7))),
+            Parser::new("?bits )").parse()
+        );
+        // Commands with arguments.
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(8..9))),
+            Parser::new("?sci on 5").parse()
+        );
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(8..9))),
+            Parser::new("?eq 1 2 3").parse()
+        );
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(8..9))),
+            Parser::new("?last 3 4").parse()
+        );
+    }
+
+    #[test]
+    fn test_complete_expressions() {
+        for input in [
+            "1",
+            "3 + 4 * 5",
+            "-(1 + 2)!",
+            "x = y += 2",
+            "1 < 2 and not 0",
+        ] {
+            assert!(Parser::new(input).parse().is_ok(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_balanced_parentheses() {
+        assert_eq!(parse("1 + 2"), parse("((1) + (2))"));
+    }
+
+    #[test]
+    fn test_call() {
+        let expr = parse("inv(1, x + 2)");
+        assert_eq!(Span::from(0..13), expr.span);
+        let ExpressionKind::Call { name, arguments } = expr.into_kind() else {
+            panic!("expected a call");
+        };
+        assert_eq!("inv", name);
+        assert_eq!(vec![parse("1"), parse("x + 2")], arguments);
+    }
+
+    #[test]
+    fn test_function_without_call() {
+        // Function names alone are variables, so they can be assigned.
+        assert_eq!(
+            Ok(ParseTree::Expression(Expression::new(
+                ExpressionKind::Variable("sqrt".to_string()),
+                Span::from(0..4)
+            ))),
+            Parser::new("sqrt").parse()
+        );
+        assert_eq!(
+            &ExpressionKind::Assignment {
+                name: "hex".to_string(),
+                value: Box::new(parse("3")),
+                constant: false
+            },
+            parse("hex = 3").kind()
+        );
+        assert!(Parser::new("sqrt(0)").parse().is_ok());
+    }
+
+    #[test]
+    fn test_call_without_arguments() {
+        assert_eq!(
+            &ExpressionKind::Call {
+                name: "f".to_string(),
+                arguments: vec![]
+            },
+            parse("f()").kind()
+        );
+    }
+
+    #[test]
+    fn test_autoclose() {
+        assert_eq!(
+            Err(ParserError::UnclosedParenthesis(None)),
+            Parser::new("(1 + (2 * 3").parse()
+        );
+        for (input, expected) in [("(1 + (2 * 3", "(1 + (2 * 3))"), ("inv(2", "inv(2)")] {
+            let Ok(ParseTree::Expression(expr)) = Parser::new(input).autoclose(true).parse() else {
+                panic!("`{input}` is not closed");
             };
+            assert_eq!(parse(expected).to_string(), expr.to_string());
         }
+        let closed = |input| {
+            Parser::new(input)
+                .autoclose(true)
+                .parse_with_closed_parentheses()
+                .map(|(_, closed)| closed)
+        };
+        assert_eq!(Ok(2), closed("(1 + (2 * 3"));
+        assert_eq!(Ok(0), closed("(1 + (2 * 3))"));
+        // Closing parentheses at the end can't fix extra ones.
+        assert_eq!(
+            Err(ParserError::UnexpectedCloseParenthesis(Span::from(5..6))),
+            Parser::new("1 + 2)").autoclose(true).parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::AutoClose(true)),
+            Parser::new("?autoclose on").parse()
+        );
+    }
 
-        Ok(lhs)
+    #[test]
+    fn test_call_unclosed() {
+        assert_eq!(
+            Err(ParserError::UnclosedParenthesis(None)),
+            Parser::new("inv(1, 2").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedExprStart(Some(Span::from(6..7)))),
+            Parser::new("inv(1,)").parse()
+        );
+    }
+
+    #[test]
+    fn test_pipe() {
+        assert_eq!(parse("sqrt(16)"), parse("16 |> sqrt"));
+        assert_eq!(parse("inv(sqrt(16))"), parse("16 |> sqrt |> inv"));
+        // Pipes bind looser than operators, but tighter than assignments.
+        assert_eq!(parse("sqrt(1 + 3)"), parse("1 + 3 |> sqrt"));
+        assert_eq!(parse("sqrt(1 < 3)"), parse("1 < 3 |> sqrt"));
+        assert_eq!(parse("x = sqrt(4)"), parse("x = 4 |> sqrt"));
+        assert_eq!(parse("sqrt(4) * 2"), parse("(4 |> sqrt) * 2"));
+        assert_eq!(Span::from(0..10), parse("16 |> sqrt").span);
+        // Like other calls, whether the function exists is checked when evaluating.
+        assert_eq!(parse("foo(16)"), parse("16 |> foo"));
+    }
+
+    #[test]
+    fn test_pipe_errors() {
+        assert_eq!(
+            Err(ParserError::ExpectedFunctionName(Some(Span::from(6..7)))),
+            Parser::new("16 |> 2").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedFunctionName(None)),
+            Parser::new("16 |>").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedExprStart(Some(Span::from(0..2)))),
+            Parser::new("|> sqrt").parse()
+        );
+    }
+
+    #[test]
+    fn test_iterate_function_name() {
+        let expr = parse("iterate(sqrt, 16, 2)");
+        let ExpressionKind::Call { name, arguments } = expr.kind() else {
+            panic!("`iterate` is a call");
+        };
+        assert_eq!("iterate", name);
+        assert_eq!(
+            &ExpressionKind::Variable("sqrt".to_string()),
+            arguments[0].kind()
+        );
+        assert_eq!(Span::from(8..12), arguments[0].span);
+    }
+
+    #[test]
+    fn test_custom_precedence() {
+        // Swap the precedence of `+` and `*`.
+        let precedence = PrecedenceTable {
+            additive: PrecedenceTable::DEFAULT.multiplicative,
+            multiplicative: PrecedenceTable::DEFAULT.additive,
+            ..PrecedenceTable::default()
+        };
+        let expr = Parser::new("1 + 2 * 3")
+            .precedence(precedence)
+            .parse_expression()
+            .unwrap();
+        assert_eq!(parse("(1 + 2) * 3"), expr);
+        // The defaults are unchanged.
+        assert_eq!(parse("1 + (2 * 3)"), parse("1 + 2 * 3"));
+    }
+
+    #[test]
+    fn test_equality_ignores_spans() {
+        assert_eq!(parse("1+2"), parse(" 1 +  2"));
+    }
+
+    #[test]
+    fn test_tokenizer_error() {
+        assert_eq!(
+            Err(ParserError::Tokenizer(
+                TokenizerError::UnrecognizedCharacter(Span::from(2..3))
+            )),
+            Parser::new("1 @ 2").parse()
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_error_before_parser_error() {
+        // `+ +` is a parser error, but the tokenizer error comes first.
+        assert_eq!(
+            Err(ParserError::Tokenizer(TokenizerError::NumberOverflow(
+                Span::from(4..404)
+            ))),
+            Parser::new(&format!("+ + {}", "9".repeat(400))).parse()
+        );
+    }
+
+    #[test]
+    fn test_command_toggle() {
+        assert_eq!(
+            Ok(ParseTree::Scientific(true)),
+            Parser::new("?sci on").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Scientific(false)),
+            Parser::new("?sci off").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedToggle(Some(Span::from(5..8)))),
+            Parser::new("?sci yes").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedToggle(None)),
+            Parser::new("?sci").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Approx(true)),
+            Parser::new("?approx on").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_relerr() {
+        assert_eq!(
+            Ok(ParseTree::RelativeError(Some(parse("355 / 113")))),
+            Parser::new("?relerr 355 / 113").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::RelativeError(None)),
+            Parser::new("?relerr off").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedExprStart(None)),
+            Parser::new("?relerr").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_diff() {
+        assert_eq!(
+            Ok(ParseTree::Diff(parse("1 / 3"), parse("0.333"))),
+            Parser::new("?diff 1 / 3 ; 0.333").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedKeyword(Some(Span::from(8..9)), ";")),
+            Parser::new("?diff 1 2").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedKeyword(None, ";")),
+            Parser::new("?diff 1").parse()
+        );
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(12..13))),
+            Parser::new("?diff 1 ; 2 ; 3").parse()
+        );
+        // Outside of `?diff`, `;` doesn't separate anything.
+        assert_eq!(
+            Err(ParserError::TrailingTokens(Span::from(2..3))),
+            Parser::new("1 ; 2").parse()
+        );
+    }
+
+    #[test]
+    fn test_token_count() {
+        let count = |input| {
+            Parser::new(input)
+                .parse_with_token_count()
+                .map(|(_, count)| count)
+        };
+        assert_eq!(Ok(0), count(""));
+        assert_eq!(Ok(0), count("   "));
+        assert_eq!(Ok(1), count("42"));
+        assert_eq!(Ok(3), count("1 + 2"));
+        assert_eq!(Ok(3), count("1+2"));
+        assert_eq!(Ok(7), count("(1 + x) * 2"));
+        assert_eq!(Ok(6), count("inv(1, 2)"));
+        assert_eq!(Ok(2), count("?total on"));
+        assert_eq!(
+            Ok((ParseTree::Expression(parse("2 * 3")), 3)),
+            Parser::new("2 * 3").parse_with_token_count()
+        );
+        // Invalid inputs have no count.
+        assert!(count("1 +").is_err());
+    }
+
+    #[test]
+    fn test_command_total() {
+        assert_eq!(Ok(ParseTree::Total(true)), Parser::new("?total on").parse());
+        assert_eq!(
+            Ok(ParseTree::Total(false)),
+            Parser::new("?total off").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::TotalReset),
+            Parser::new("?total reset").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedToggle(Some(Span::from(7..12)))),
+            Parser::new("?total clear").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_eq() {
+        assert_eq!(
+            Ok(ParseTree::Eq(255.0, 255.0)),
+            Parser::new("?eq 0xff 255").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Eq(2.0, 3.0)),
+            Parser::new("?eq 0b10 3").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedNumber(Some(Span::from(6..7)))),
+            Parser::new("?eq 1 x").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedNumber(None)),
+            Parser::new("?eq 1").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_sep() {
+        assert_eq!(
+            Ok(ParseTree::Separator('_', Span::from(5..6))),
+            Parser::new("?sep _").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Separator(',', Span::from(5..6))),
+            Parser::new("?sep ,").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedSeparator(Some(Span::from(5..6)))),
+            Parser::new("?sep x").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedSeparator(None)),
+            Parser::new("?sep").parse()
+        );
+        // An underscore alone isn't an expression.
+        assert_eq!(
+            Err(ParserError::ExpectedExprStart(Some(Span::from(0..1)))),
+            Parser::new("_").parse()
+        );
+    }
+
+    #[test]
+    fn test_dot_in_expression() {
+        // A dot outside of a number is unrecognized, like before it was a mark.
+        for (input, span) in [(".5", 0..1), ("1 . 5", 2..3), ("1 + .", 4..5)] {
+            assert_eq!(
+                Err(ParserError::Tokenizer(
+                    TokenizerError::UnrecognizedCharacter(Span::from(span))
+                )),
+                Parser::new(input).parse()
+            );
+        }
+    }
+
+    #[test]
+    fn test_command_seed() {
+        assert_eq!(Ok(ParseTree::Seed(42)), Parser::new("?seed 42").parse());
+        assert_eq!(
+            Err(ParserError::ExpectedSeed(Some(Span::from(6..9)))),
+            Parser::new("?seed 1.5").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Seed(MAX_SEED)),
+            Parser::new("?seed 9007199254740992").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedSeed(Some(Span::from(6..22)))),
+            Parser::new("?seed 9007199254740994").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_eps() {
+        assert_eq!(Ok(ParseTree::Eps), Parser::new("?eps").parse());
+        assert_eq!(
+            Err(ParserError::UnrecognizedSpecial(Some(Span::from(0..8)))),
+            Parser::new("?epsilon").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_export() {
+        assert_eq!(Ok(ParseTree::Export), Parser::new("?export").parse());
+    }
+
+    #[test]
+    fn test_command_copy() {
+        assert_eq!(Ok(ParseTree::Copy), Parser::new("?copy").parse());
+    }
+
+    #[test]
+    fn test_command_spaced() {
+        assert_eq!(Ok(ParseTree::Quit), Parser::new("? quit").parse());
+        assert_eq!(Ok(ParseTree::Quit), Parser::new("?  quit").parse());
+        assert_eq!(
+            Ok(ParseTree::Scientific(true)),
+            Parser::new("? sci on").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_store() {
+        assert_eq!(Ok(ParseTree::Store(1)), Parser::new("?store 1").parse());
+        assert_eq!(Ok(ParseTree::Store(9)), Parser::new("?store 9").parse());
+        for input in ["?store", "?store 0", "?store 10", "?store 1.5"] {
+            assert!(
+                matches!(
+                    Parser::new(input).parse(),
+                    Err(ParserError::ExpectedIntegerInRange(_, _))
+                ),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_command_time() {
+        assert_eq!(Ok(ParseTree::Time(true)), Parser::new("?time on").parse());
+        assert_eq!(Ok(ParseTree::Time(false)), Parser::new("?time off").parse());
+        assert_eq!(
+            Ok(ParseTree::Profile(true)),
+            Parser::new("?profile on").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedToggle(None)),
+            Parser::new("?time").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_precision() {
+        assert_eq!(
+            Ok(ParseTree::PrecisionAuto),
+            Parser::new("?precision auto").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedKeyword(
+                Some(Span::from(11..14)),
+                "auto"
+            )),
+            Parser::new("?precision max").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedKeyword(None, "auto")),
+            Parser::new("?precision").parse()
+        );
+    }
+
+    #[test]
+    fn test_integer_mode() {
+        assert_eq!(
+            parse("7 / 2"),
+            Parser::new("7 / 2")
+                .integer(true)
+                .parse_expression()
+                .unwrap()
+        );
+        assert_eq!(
+            Err(ParserError::NonIntegerLiteral(Span::from(4..7))),
+            Parser::new("1 + 3.5").integer(true).parse()
+        );
+        // A trailing dot or an exponent can still be an integer.
+        assert!(Parser::new("3. + 0x10").integer(true).parse().is_ok());
+        assert!(Parser::new("2e3 * 1.5e1").integer(true).parse().is_ok());
+        assert_eq!(
+            Err(ParserError::NonIntegerLiteral(Span::from(0..4))),
+            Parser::new("2e-1").integer(true).parse()
+        );
+        assert!(Parser::new("3.5").parse().is_ok());
+    }
+
+    #[test]
+    fn test_command_int() {
+        assert_eq!(
+            Ok(ParseTree::Integer(Some(IntegerDivision::Exact))),
+            Parser::new("?int on").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Integer(Some(IntegerDivision::Floor))),
+            Parser::new("?int floor").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Integer(None)),
+            Parser::new("?int off").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedToggle(Some(Span::from(5..9)))),
+            Parser::new("?int ceil").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_format_marks() {
+        assert_eq!(
+            Ok(ParseTree::FormatMarks(Some((',', '.')))),
+            Parser::new("?format_marks , .").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::FormatMarks(Some(('.', ',')))),
+            Parser::new("?format_marks . ,").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::FormatMarks(Some(('_', '.')))),
+            Parser::new("?format_marks _ .").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::FormatMarks(None)),
+            Parser::new("?format_marks off").parse()
+        );
+        assert_eq!(
+            Err(ParserError::SameMarks(Span::from(14..17))),
+            Parser::new("?format_marks , ,").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedMark(Some(Span::from(16..17)))),
+            Parser::new("?format_marks , x").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedMark(None)),
+            Parser::new("?format_marks .").parse()
+        );
+        // A dot alone is still an unrecognized character in expressions.
+        assert_eq!(
+            Err(ParserError::Tokenizer(
+                TokenizerError::UnrecognizedCharacter(Span::from(0..1))
+            )),
+            Parser::new(".").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_overflow() {
+        assert_eq!(
+            Ok(ParseTree::Overflow(OverflowPolicy::Allow)),
+            Parser::new("?overflow allow").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Overflow(OverflowPolicy::Warn)),
+            Parser::new("?overflow warn").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::Overflow(OverflowPolicy::Error)),
+            Parser::new("?overflow error").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedOverflowPolicy(Some(Span::from(
+                10..14
+            )))),
+            Parser::new("?overflow wrap").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedOverflowPolicy(None)),
+            Parser::new("?overflow").parse()
+        );
+    }
+
+    #[test]
+    fn test_command_settings() {
+        assert_eq!(
+            Ok(ParseTree::SettingsUndo),
+            Parser::new("?settings undo").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::SettingsRedo),
+            Parser::new("?settings redo").parse()
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedKeyword(None, "undo")),
+            Parser::new("?settings").parse()
+        );
+    }
+
+    #[test]
+    fn test_roman_mode() {
+        assert_eq!(
+            parse("14 + 6"),
+            Parser::new("XIV + VI")
+                .roman(true)
+                .parse_expression()
+                .unwrap()
+        );
+        assert_eq!(
+            Err(ParserError::InvalidRomanNumeral(Span::from(4..8))),
+            Parser::new("1 + IIII").roman(true).parse_expression()
+        );
+        // Other identifiers are still variables.
+        assert_eq!(
+            parse("x"),
+            Parser::new("x").roman(true).parse_expression().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_roman_mode_off() {
+        assert_eq!(
+            &ExpressionKind::Variable("XIV".to_string()),
+            parse("XIV").kind()
+        );
+        assert_eq!(Ok(ParseTree::Roman(true)), Parser::new("?roman on").parse());
+    }
+
+    #[test]
+    fn test_complex_mode() {
+        let expr = Parser::new("2i + i").complex(true).parse_expression();
+        assert_eq!(
+            Ok(ExpressionKind::Imaginary(Number(1.0))),
+            expr.map(|expr| match expr.into_kind() {
+                ExpressionKind::Binary { rhs, .. } => rhs.into_kind(),
+                _ => panic!("expected a binary expression"),
+            })
+        );
+        assert_eq!(
+            Ok(ParseTree::Complex(true)),
+            Parser::new("?complex on").parse()
+        );
+    }
+
+    #[test]
+    fn test_complex_mode_off() {
+        assert_eq!(
+            Err(ParserError::ImaginaryOutsideComplexMode(Span::from(4..6))),
+            Parser::new("1 + 2i").parse()
+        );
+        // `i` is a variable like any other.
+        assert_eq!(
+            &ExpressionKind::Variable("i".to_string()),
+            parse("i").kind()
+        );
+    }
+
+    #[test]
+    fn test_command_digits() {
+        assert_eq!(Ok(ParseTree::Digits(3)), Parser::new("?digits 3").parse());
+        assert_eq!(Ok(ParseTree::Digits(17)), Parser::new("?digits 17").parse());
+        for input in ["?digits 0", "?digits 18", "?digits 2.5", "?digits"] {
+            assert!(matches!(
+                Parser::new(input).parse(),
+                Err(ParserError::ExpectedIntegerInRange(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_command_fixed_point() {
+        assert_eq!(
+            Ok(ParseTree::FixedPoint(Some(2))),
+            Parser::new("?fixedpoint 2").parse()
+        );
+        assert_eq!(
+            Ok(ParseTree::FixedPoint(None)),
+            Parser::new("?fixedpoint off").parse()
+        );
+        for input in ["?fixedpoint 18", "?fixedpoint on", "?fixedpoint"] {
+            assert!(matches!(
+                Parser::new(input).parse(),
+                Err(ParserError::ExpectedIntegerInRangeOrOff(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_command_last() {
+        assert_eq!(Ok(ParseTree::Last(3)), Parser::new("?last 3").parse());
+        // The default is 10 results.
+        assert_eq!(Ok(ParseTree::Last(10)), Parser::new("?last").parse());
+        for input in ["?last 0", "?last -1", "?last 1.5", "?last 1001"] {
+            assert!(matches!(
+                Parser::new(input).parse(),
+                Err(ParserError::ExpectedIntegerInRange(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_placeholder_count() {
+        assert_eq!(0, parse("1 + x").placeholder_count());
+        assert_eq!(1, parse("$1 * $1 + 1").placeholder_count());
+        assert_eq!(3, parse("f($3) - $1").placeholder_count());
+    }
+
+    /// The spans of an expression and all its subexpressions, in pre-order.
+    fn spans(expr: &Expression) -> Vec<Span> {
+        let children = match expr.kind() {
+            ExpressionKind::Binary { lhs, rhs, .. } => vec![lhs.as_ref(), rhs.as_ref()],
+            ExpressionKind::Comparisons { first, comparisons } => std::iter::once(first.as_ref())
+                .chain(comparisons.iter().map(|(_, operand)| operand))
+                .collect(),
+            ExpressionKind::Unary { operand, .. } => vec![operand.as_ref()],
+            ExpressionKind::Assignment { value, .. } => vec![value.as_ref()],
+            ExpressionKind::Call { arguments, .. } => arguments.iter().collect(),
+            _ => vec![],
+        };
+        std::iter::once(expr.span)
+            .chain(children.into_iter().flat_map(spans))
+            .collect()
+    }
+
+    #[test]
+    fn test_shift_spans() {
+        let expr = Parser::new("x = -inv(2, $1)! + 3 * 4i")
+            .complex(true)
+            .parse_expression()
+            .unwrap();
+        for delta in [0, 7, -1, -10] {
+            let shifted = expr.shift_spans(delta);
+            // The structure is the same, only the spans moved.
+            assert_eq!(expr, shifted);
+            assert_eq!(expr.to_string(), shifted.to_string());
+            assert_eq!(hash(&expr), hash(&shifted));
+            let expected = spans(&expr)
+                .into_iter()
+                .map(|span| Span {
+                    start: span.start.saturating_add_signed(delta),
+                    end: span.end.saturating_add_signed(delta),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(expected, spans(&shifted));
+        }
+        assert_eq!(Span::from(10..35), expr.shift_spans(10).span);
+        // Spans moved backward stop at the start of the input.
+        let shifted = expr.shift_spans(-4);
+        assert_eq!(Span::from(0..21), shifted.span);
+        let ExpressionKind::Assignment { value, .. } = shifted.into_kind() else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(Span::from(0..21), value.span);
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        assert_eq!(parse("1+2"), Parser::new("1+2").parse_expression().unwrap());
+    }
+
+    #[test]
+    fn test_parse_expression_special() {
+        assert_eq!(
+            Err(ParserError::UnrecognizedSpecial(Some(Span::from(0..5)))),
+            Parser::new("?quit").parse_expression()
+        );
+    }
+
+    #[test]
+    fn test_parse_expression_empty() {
+        assert_eq!(
+            Err(ParserError::EmptyInput),
+            Parser::new("  ").parse_expression()
+        );
+    }
+
+    #[test]
+    fn test_equal_trees_hash_equal() {
+        let lhs = parse("(1 + x) * 2^3");
+        let rhs = parse(" ( 1+x )*2 ^ 3 ");
+        assert_eq!(lhs, rhs);
+        assert_eq!(hash(&lhs), hash(&rhs));
+    }
+
+    #[test]
+    fn test_different_trees_are_not_equal() {
+        assert_ne!(parse("1 + 2"), parse("2 + 1"));
+        assert_ne!(parse("x"), parse("y"));
+    }
+
+    #[test]
+    fn test_signed_zero_atoms() {
+        // `0.0` and `-0.0` are different atoms.
+        assert_ne!(atom(0.0), atom(-0.0));
+        assert_ne!(hash(&atom(0.0)), hash(&atom(-0.0)));
+        assert_eq!(atom(-0.0), atom(-0.0));
+    }
+
+    #[test]
+    fn test_nan_atoms() {
+        // All `NaN`s are equal, even with different bits.
+        let other_nan = f64::from_bits(f64::NAN.to_bits() + 1);
+        assert!(other_nan.is_nan());
+        assert_eq!(atom(f64::NAN), atom(other_nan));
+        assert_eq!(hash(&atom(f64::NAN)), hash(&atom(other_nan)));
+    }
+
+    /// Creates an expression with an empty span, like the parser would create it.
+    /// `a + b%` is always parsed as a percentage of `a`, so a sum or difference
+    /// whose right-hand side is a percent becomes a percentage operation.
+    fn generated(kind: ExpressionKind) -> Expression {
+        let kind = match kind {
+            ExpressionKind::Binary {
+                operation: operation @ (BinaryOperation::Addition | BinaryOperation::Subtraction),
+                lhs,
+                rhs,
+            } => match rhs.into_kind() {
+                ExpressionKind::Unary {
+                    operation: UnaryOperation::Percent,
+                    operand,
+                } => ExpressionKind::Binary {
+                    operation: match operation {
+                        BinaryOperation::Addition => BinaryOperation::AddPercentage,
+                        _ => BinaryOperation::SubtractPercentage,
+                    },
+                    lhs,
+                    rhs: operand,
+                },
+                rhs => ExpressionKind::Binary {
+                    operation,
+                    lhs,
+                    rhs: Box::new(Expression::new(rhs, Span::from(0..0))),
+                },
+            },
+            kind => kind,
+        };
+        Expression::new(kind, Span::from(0..0))
+    }
+
+    /// Generates numbers that can be written as literals.
+    /// Negative numbers are negations, so they aren't literals.
+    fn literal() -> impl Strategy<Value = f64> {
+        use proptest::num::f64::{NORMAL, POSITIVE, SUBNORMAL, ZERO};
+        prop_oneof![
+            (0..1000u32).prop_map(f64::from),
+            POSITIVE | ZERO | NORMAL | SUBNORMAL,
+        ]
+    }
+
+    /// Generates the comparisons that can be chained.
+    fn comparison() -> impl Strategy<Value = BinaryOperation> {
+        prop::sample::select(vec![
+            BinaryOperation::Less,
+            BinaryOperation::LessOrEqual,
+            BinaryOperation::Greater,
+            BinaryOperation::GreaterOrEqual,
+            BinaryOperation::Equal,
+            BinaryOperation::NotEqual,
+        ])
+    }
+
+    /// Generates random expressions that the parser can create, covering every operator.
+    fn expression() -> impl Strategy<Value = Expression> {
+        // `i` is the imaginary unit, and `not`, `and` and `or` are keywords,
+        // so they can't be variables.
+        let leaf = prop_oneof![
+            literal().prop_map(|num| ExpressionKind::Atom(Number(num))),
+            literal().prop_map(|num| ExpressionKind::Imaginary(Number(num))),
+            prop::sample::select(vec!["x", "y", "rate"])
+                .prop_map(|name| ExpressionKind::Variable(name.to_string())),
+            (1..4usize).prop_map(ExpressionKind::Placeholder),
+        ]
+        .prop_map(generated);
+
+        leaf.prop_recursive(6, 64, 3, |inner| {
+            prop_oneof![
+                (
+                    prop::sample::select(vec![
+                        BinaryOperation::Addition,
+                        BinaryOperation::Subtraction,
+                        BinaryOperation::Multiplication,
+                        BinaryOperation::Division,
+                        BinaryOperation::Exponentiation,
+                        BinaryOperation::Less,
+                        BinaryOperation::LessOrEqual,
+                        BinaryOperation::Greater,
+                        BinaryOperation::GreaterOrEqual,
+                        BinaryOperation::Equal,
+                        BinaryOperation::NotEqual,
+                        BinaryOperation::And,
+                        BinaryOperation::Or,
+                        BinaryOperation::AddPercentage,
+                        BinaryOperation::SubtractPercentage,
+                        BinaryOperation::Minimum,
+                        BinaryOperation::Maximum,
+                    ]),
+                    inner.clone(),
+                    inner.clone(),
+                )
+                    .prop_map(|(operation, lhs, rhs)| ExpressionKind::Binary {
+                        operation,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    }),
+                (
+                    inner.clone(),
+                    prop::collection::vec((comparison(), inner.clone()), 2..4),
+                )
+                    .prop_map(|(first, comparisons)| ExpressionKind::Comparisons {
+                        first: Box::new(first),
+                        comparisons,
+                    }),
+                (
+                    prop::sample::select(vec![
+                        UnaryOperation::Negation,
+                        UnaryOperation::Not,
+                        UnaryOperation::Factorial,
+                        UnaryOperation::Percent,
+                        UnaryOperation::SquareRoot,
+                    ]),
+                    inner.clone(),
+                )
+                    .prop_map(|(operation, operand)| ExpressionKind::Unary {
+                        operation,
+                        operand: Box::new(operand),
+                    }),
+                (
+                    prop::sample::select(vec!["x", "y"]),
+                    inner.clone(),
+                    any::<bool>()
+                )
+                    .prop_map(|(name, value, constant)| {
+                        ExpressionKind::Assignment {
+                            name: name.to_string(),
+                            value: Box::new(value),
+                            constant,
+                        }
+                    }),
+                (
+                    prop::sample::select(vec!["inv", "sqrt", "f"]),
+                    prop::collection::vec(inner, 0..3),
+                )
+                    .prop_map(|(name, arguments)| ExpressionKind::Call {
+                        name: name.to_string(),
+                        arguments,
+                    }),
+            ]
+            .prop_map(generated)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_display_round_trip(expr in expression()) {
+            let displayed = expr.to_string();
+            let reparsed = Parser::new(&displayed).complex(true).parse_expression();
+            prop_assert_eq!(Ok(expr), reparsed, "`{}` was parsed differently", displayed);
+        }
+    }
+
+    #[test]
+    fn test_signs() {
+        // `-` after an operator is a sign, and after an operand it's an operator.
+        assert_eq!("(3 - (-2))", parse("3 - -2").to_string());
+        assert_eq!("(3 - (-2))", parse("3--2").to_string());
+        assert_eq!("(3 + (-2))", parse("3 + -2").to_string());
+        assert_eq!("(3 * (-2))", parse("3 * -2").to_string());
+        assert_eq!("(3 / (-(-2)))", parse("3 / --2").to_string());
+        assert_eq!("((-3) - 2)", parse("-3 - 2").to_string());
+        // Exponentiation binds tighter than negation, on both sides.
+        assert_eq!("(-(3 ^ 2))", parse("-3 ^ 2").to_string());
+        assert_eq!("((-3) ^ 2)", parse("(-3) ^ 2").to_string());
+        assert_eq!("(2 ^ (-(3 ^ 2)))", parse("2 ^ -3 ^ 2").to_string());
+        assert_eq!("(-(2 ^ (-1)))", parse("-2^-1").to_string());
+        // `+` is only an operator, there is no positive sign.
+        assert_eq!(
+            Err(ParserError::BinaryOperatorAtStart(
+                OperationKind::Plus,
+                Span::from(4..5)
+            )),
+            Parser::new("3 + +2").parse()
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("((1 + 2) * 3)", parse("(1 + 2) * 3").to_string());
+        assert_eq!("(-(2 ^ 2))", parse("-2^2").to_string());
+        assert_eq!("(1 < 2 < 3)", parse("1 < 2 < 3").to_string());
+        assert_eq!("((1 < 2) < 3)", parse("(1 < 2) < 3").to_string());
+        assert_eq!("(200 + 10%)", parse("200 + 10%").to_string());
+        assert_eq!(r#"fmt(1, "%.2f")"#, parse(r#"fmt(1, "%.2f")"#).to_string());
+        assert_eq!("(x = inv(4, (5!)))", parse("x = inv(4, 5!)").to_string());
     }
 }
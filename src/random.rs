@@ -0,0 +1,102 @@
+//! Pseudorandom numbers for `rand`. The generator is seeded with `?seed`,
+//! so that the same inputs give the same numbers again.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The largest seed of `?seed`. Larger number literals aren't exact.
+pub const MAX_SEED: u64 = 1 << 53;
+
+/// A xorshift64* generator. It's small and good enough for simulations
+/// and sampling, but it's not suitable for cryptography.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    /// The state, which is never zero.
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator from a seed. The same seed always gives
+    /// the same numbers.
+    pub fn new(seed: u64) -> Self {
+        // Mix the seed with SplitMix64, so that close seeds give unrelated
+        // numbers, and the state isn't zero for a zero seed.
+        let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        state ^= state >> 31;
+        Self {
+            state: state.max(1),
+        }
+    }
+
+    /// The next number, from 0 to `u64::MAX`.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// The next number from 0 included to 1 excluded.
+    pub fn next_f64(&mut self) -> f64 {
+        // An `f64` has 53 bits of precision, so we keep the 53 highest bits
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Default for Rng {
+    /// Creates a generator seeded with the current time, so that every
+    /// session gives different numbers until `?seed` is used.
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        Self::new(nanos as u64)
+    }
+}
+
+/// Tests for pseudorandom numbers.
+#[cfg(test)]
+mod tests {
+    use crate::random::Rng;
+
+    #[test]
+    fn test_same_seed() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_different_seeds() {
+        let (mut a, mut b) = (Rng::new(1), Rng::new(2));
+        for _ in 0..100 {
+            assert_ne!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_sequence() {
+        // The numbers must stay the same, or seeded results would change.
+        let mut rng = Rng::new(42);
+        assert_eq!(0.1941059175341826, rng.next_f64());
+        assert_eq!(0.5626318272656207, rng.next_f64());
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        let mut rng = Rng::new(0);
+        assert_ne!(rng.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..10_000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x), "{x}");
+        }
+    }
+}
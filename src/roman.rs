@@ -0,0 +1,107 @@
+//! Roman numerals, like `XIV` or `MMXXIV`. When roman mode is on,
+//! identifiers that are made of Roman numeral letters are parsed as numbers.
+
+/// The largest number that can be written in Roman numerals,
+/// without the overline notation for thousands.
+pub const MAX_ROMAN: u32 = 3999;
+
+/// The value of every symbol, including the subtractive pairs,
+/// from largest to smallest.
+const SYMBOLS: [(&str, u32); 13] = [
+    ("M", 1000),
+    ("CM", 900),
+    ("D", 500),
+    ("CD", 400),
+    ("C", 100),
+    ("XC", 90),
+    ("L", 50),
+    ("XL", 40),
+    ("X", 10),
+    ("IX", 9),
+    ("V", 5),
+    ("IV", 4),
+    ("I", 1),
+];
+
+/// Whether the identifier only contains Roman numeral letters.
+/// Such identifiers are numbers in roman mode, even if they aren't valid numerals.
+pub fn is_roman_letters(identifier: &str) -> bool {
+    !identifier.is_empty() && identifier.chars().all(|c| "IVXLCDM".contains(c))
+}
+
+/// Converts a Roman numeral to an integer.
+/// Returns `None` if the numeral isn't written in the standard form,
+/// like `IIII` (instead of `IV`), `VV` (instead of `X`) or `IC` (instead of `XCIX`).
+pub fn roman_to_int(numeral: &str) -> Option<u32> {
+    // Read the symbols greedily, from largest to smallest.
+    let mut rest = numeral;
+    let mut value = 0;
+    for (symbol, symbol_value) in SYMBOLS {
+        while let Some(stripped) = rest.strip_prefix(symbol) {
+            rest = stripped;
+            value += symbol_value;
+        }
+    }
+    if !rest.is_empty() || value == 0 || value > MAX_ROMAN {
+        return None;
+    }
+
+    // Greedy reading accepts some non-standard numerals, like `IIII` or `VIV`.
+    // A numeral is only valid if it's the standard way of writing its value.
+    (int_to_roman(value)? == numeral).then_some(value)
+}
+
+/// Converts an integer to a Roman numeral.
+/// Returns `None` if the integer is zero or larger than [`MAX_ROMAN`].
+pub fn int_to_roman(mut value: u32) -> Option<String> {
+    if value == 0 || value > MAX_ROMAN {
+        return None;
+    }
+
+    let mut numeral = String::new();
+    for (symbol, symbol_value) in SYMBOLS {
+        while value >= symbol_value {
+            numeral.push_str(symbol);
+            value -= symbol_value;
+        }
+    }
+    Some(numeral)
+}
+
+/// Tests for Roman numerals.
+#[cfg(test)]
+mod tests {
+    use crate::roman::{int_to_roman, is_roman_letters, roman_to_int};
+
+    #[test]
+    fn test_roman_to_int() {
+        assert_eq!(Some(1), roman_to_int("I"));
+        assert_eq!(Some(4), roman_to_int("IV"));
+        assert_eq!(Some(14), roman_to_int("XIV"));
+        assert_eq!(Some(1994), roman_to_int("MCMXCIV"));
+        assert_eq!(Some(2024), roman_to_int("MMXXIV"));
+        assert_eq!(Some(3999), roman_to_int("MMMCMXCIX"));
+    }
+
+    #[test]
+    fn test_roman_to_int_invalid() {
+        for numeral in ["IIII", "VV", "IC", "VIV", "XM", "MMMM", "IIV", ""] {
+            assert_eq!(None, roman_to_int(numeral), "{numeral}");
+        }
+    }
+
+    #[test]
+    fn test_int_to_roman() {
+        assert_eq!(Some("XLII".to_string()), int_to_roman(42));
+        assert_eq!(None, int_to_roman(0));
+        assert_eq!(None, int_to_roman(4000));
+    }
+
+    #[test]
+    fn test_is_roman_letters() {
+        assert!(is_roman_letters("XIV"));
+        assert!(is_roman_letters("IIII"));
+        assert!(!is_roman_letters("x"));
+        assert!(!is_roman_letters("XIVa"));
+    }
+}
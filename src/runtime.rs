@@ -1,24 +1,3661 @@
-use crate::parser::{BinaryOperation, Expression, UnaryOperation};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[cfg(feature = "bigint")]
+use crate::bigint::BigInt;
+use crate::{
+    complex::Complex,
+    format::printf,
+    parser::{BinaryOperation, Expression, ExpressionKind, UnaryOperation},
+    random::Rng,
+    tokenizer::Span,
+};
+
+/// An error that occured during evaluation.
+#[derive(Debug, PartialEq)]
+pub enum RuntimeError {
+    /// The error occured because a variable was used before being assigned.
+    UnknownVariable(String, Span),
+    /// The error occured because the result is too large to be represented.
+    Overflow(Span),
+    /// The error occured because an operation only accepts non-negative
+    /// integers, like factorial, but got something else.
+    ExpectedNonNegativeInteger(Span),
+    /// The error occured because a function was called, but it doesn't exist,
+    /// and there is no variable with that name to multiply either.
+    UnknownFunction(String, Span),
+    /// The error occured because a function name, like `sqrt`, was used
+    /// without calling it, and there is no variable with that name.
+    ExpectedFunctionCall(String, Span),
+    /// The error occured because a function was called with the wrong number
+    /// of arguments. The first number is the expected count, the second one
+    /// is the actual count.
+    WrongArgumentCount(usize, usize, Span),
+    /// The error occured because a function was called with the wrong number
+    /// of arguments, like `rand(1)`, but it accepts two counts. The first two
+    /// numbers are the expected counts, the third one is the actual count.
+    WrongArgumentCountEither(usize, usize, usize, Span),
+    /// The error occured because a function divided by zero.
+    DivisionByZero(Span),
+    /// The error occured because an operation only accepts non-negative
+    /// numbers, like `√`, but got a negative number.
+    ExpectedNonNegative(Span),
+    /// The error occured because an operation only accepts positive
+    /// integers, like the largest denominator of `dec2frac`, but got something else.
+    ExpectedPositiveInteger(Span),
+    /// The error occured because a constant was assigned after being defined.
+    ConstantReassignment(String, Span),
+    /// The error occured because an operation only accepts real numbers,
+    /// like comparisons, but got a complex number.
+    ExpectedReal(Span),
+    /// The error occured because a template used a placeholder, like `$3`,
+    /// but was given fewer arguments.
+    MissingArgument(usize, Span),
+    /// The error occured because an operation only accepts numbers of at
+    /// least 1, like `acosh`, but got a smaller number.
+    ExpectedAtLeastOne(Span),
+    /// The error occured because an operation only accepts numbers strictly
+    /// between -1 and 1, like `atanh`, but got something else.
+    ExpectedBetweenMinusOneAndOne(Span),
+    /// The error occured because an operation only accepts numbers from -1
+    /// to 1, like `asin`, but got something else.
+    ExpectedFromMinusOneToOne(Span),
+    /// The error occured because `dice` was asked to roll more than
+    /// `MAX_DICE` dice, which would take too long.
+    TooManyDice(Span),
+    /// The error occured because integer mode is on, but an operation,
+    /// like `7 / 2`, gave a result that isn't an integer.
+    NonIntegerResult(Span),
+    /// The error occured because an argument had to be a variable name,
+    /// like the second argument of `table`, but was another expression.
+    ExpectedVariable(Span),
+    /// The error occured because a number had to be finite, like the
+    /// bounds of `table`, but was infinite or `NaN`.
+    ExpectedFinite(Span),
+    /// The error occured because the step of `table` was zero, or went away
+    /// from the end, so the end would never be reached.
+    InvalidStep(Span),
+    /// The error occured because `table` would have more than
+    /// `MAX_TABLE_ROWS` rows.
+    TooManyRows(Span),
+    /// The error occured because `table` was used inside an expression,
+    /// but it can only be the whole input.
+    NestedTable(Span),
+    /// The error occured because a number had to be an integer, like the
+    /// bounds of `sum` and `product`, but wasn't.
+    ExpectedInteger(Span),
+    /// The error occured because `sum` or `product` would have more than
+    /// `MAX_TERMS` terms.
+    TooManyTerms(Span),
+    /// The error occured because a result in big integer mode would have
+    /// more than `MAX_BIGINT_DIGITS` digits.
+    TooManyDigits(Span),
+    /// The error occured because `prev` asked for a result further back than
+    /// the history goes. The number is how many results there are.
+    NoSuchResult(usize, Span),
+    /// The error occured because an argument had to be the name of a function
+    /// of a number, like the first argument of `iterate`, but was something else.
+    ExpectedFunction(Span),
+    /// The error occured because `iterate` would apply its function more than
+    /// `MAX_ITERATIONS` times.
+    TooManyIterations(Span),
+    /// The error occured because text, like `"%.2f"`, was used where a
+    /// number was expected.
+    UnexpectedText(Span),
+    /// The error occured because an argument had to be text, like the format
+    /// of `fmt`, but was something else.
+    ExpectedText(Span),
+    /// The error occured because the format of `fmt` wasn't a valid
+    /// printf-style format, like `%.2f`.
+    InvalidFormat(Span),
+    /// The error occured because the operator of `reduce` wasn't `+`, `*`,
+    /// `min` or `max`.
+    UnknownOperator(Span),
+    /// The error occured because `reduce` was given an operator, but no
+    /// values to reduce.
+    NoValues(Span),
+    /// The error occured because `apply` was used, but no template was defined.
+    NoTemplate(Span),
+    /// The error occured because evaluating an expression took more
+    /// operations than the budget of `evaluate_with_budget`.
+    BudgetExceeded(Span),
+    /// The error occured because an `assert` failed: the first number, the
+    /// actual value, wasn't nearly equal to the second one, the expected value.
+    AssertionFailed(f64, f64, Span),
+}
+
+/// How divisions that aren't exact are handled in integer mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerDivision {
+    /// Divisions that aren't exact are errors, like every other
+    /// result that isn't an integer.
+    Exact,
+    /// Divisions are rounded down, so `7 / 2` is `3` and `-7 / 2` is `-4`.
+    Floor,
+}
+
+/// What happens when an operation overflows, giving an infinite result
+/// from finite operands, like `10^308 * 10`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The result is infinite, like in floating-point arithmetic.
+    #[default]
+    Allow,
+    /// The result is infinite, but the environment remembers the overflow,
+    /// so that a warning can be shown.
+    Warn,
+    /// The operation returns an overflow error.
+    Error,
+}
+
+/// The largest number whose factorial can be computed.
+/// `170!` is about `7.26e306`, the largest factorial that fits in an `f64`.
+/// `171!` would be infinite, so we return an overflow error instead.
+pub const MAX_FACTORIAL: f64 = 170.0;
+
+/// The largest power of two that an `f64` can't represent.
+/// Any exponentiation whose result would be at least `2^MAX_EXPONENT`
+/// returns an overflow error instead of computing an infinite value.
+pub const MAX_EXPONENT: f64 = 1024.0;
+
+/// The relative tolerance of `==` and `!=` in approximate mode.
+pub const APPROX_TOLERANCE: f64 = 1e-9;
+
+/// The largest number of dice that `dice` rolls at once.
+pub const MAX_DICE: f64 = 1_000_000.0;
+
+/// The largest number of rows of a table made by `table`.
+pub const MAX_TABLE_ROWS: usize = 1000;
+
+/// The largest number of terms of `sum` and `product`.
+pub const MAX_TERMS: f64 = 1_000_000.0;
+
+/// The largest number of times `iterate` applies its function.
+pub const MAX_ITERATIONS: f64 = 1_000_000.0;
+
+/// The number of results remembered for `?last` and `prev`. Older results are forgotten.
+pub const MAX_HISTORY: usize = 1000;
+
+/// The number of memory slots, recalled with `M1` to `M9` and set with `?store`.
+pub const MEMORY_SLOTS: usize = 9;
+
+/// The largest number of digits of a result in big integer mode.
+pub const MAX_BIGINT_DIGITS: usize = 10_000;
+
+/// The largest denominator of the fractions found by `dec2frac`,
+/// unless another one is given.
+pub const MAX_DENOMINATOR: f64 = 1_000_000.0;
+
+/// The names of the functions that can be called.
+pub const FUNCTIONS: [&str; 41] = [
+    "inv",
+    "reciprocal",
+    "sqrt",
+    "cbrt",
+    "degrees",
+    "radians",
+    "approx",
+    "dec2frac",
+    "hex",
+    "bin",
+    "oct",
+    "is_prime",
+    "factorial",
+    "choose",
+    "permute",
+    "sinh",
+    "cosh",
+    "tanh",
+    "asinh",
+    "acosh",
+    "atanh",
+    "asin",
+    "acos",
+    "atan",
+    "atan2",
+    "percent_of",
+    "add_pct",
+    "rand",
+    "dice",
+    "table",
+    "sum",
+    "product",
+    "assert",
+    "prev",
+    "round",
+    "iterate",
+    "nice",
+    "fmt",
+    "reduce",
+    "truncate_to",
+    "apply",
+];
+
+/// The environment in which expressions are evaluated. It remembers the
+/// values of variables and the previous results, and generates the numbers of `rand`.
+#[derive(Debug, Default, Clone)]
+pub struct Environment {
+    /// The variables, by name.
+    variables: HashMap<String, f64>,
+    /// The names of the variables that are constants.
+    constants: HashSet<String>,
+    /// The random number generator of `rand`. It's seeded with the
+    /// current time when it's first used, unless `seed` was called.
+    rng: Option<Rng>,
+    /// The most recent results, oldest first. This is what `?last` shows
+    /// and what `prev` reads.
+    history: VecDeque<f64>,
+    /// Whether an operation overflowed since the last `take_overflow`.
+    overflowed: bool,
+    /// The first division by zero since the last `take_division_by_zero`, if any.
+    divided_by_zero: Option<Span>,
+    /// The number of expression nodes evaluated since the last `take_evaluated_nodes`.
+    evaluated_nodes: usize,
+    /// The memory slots, from `M1` to `M9`. Like on a calculator, they start at 0.
+    memory: [f64; MEMORY_SLOTS],
+    /// The number of operations that can still be done, if there is a budget.
+    budget: Option<usize>,
+    /// The last expression with placeholders, like `$1 * $1 + 1`.
+    /// This is what `apply` evaluates.
+    template: Option<Expression>,
+}
+
+impl Environment {
+    /// Gets the value of a variable, if it exists. Memory slots like `M1`
+    /// always exist, unless a variable with the same name hides them.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.variables
+            .get(name)
+            .copied()
+            .or_else(|| memory_slot(name).map(|slot| self.memory[slot - 1]))
+    }
+
+    /// Remembers an expression with placeholders as the template of `apply`.
+    pub fn set_template(&mut self, template: Expression) {
+        self.template = Some(template);
+    }
+
+    /// Stores a value in a memory slot, numbered from 1.
+    pub fn store(&mut self, slot: usize, value: f64) {
+        self.memory[slot - 1] = value;
+    }
+
+    /// Sets the value of a variable, creating it if needed.
+    pub fn set(&mut self, name: String, value: f64) {
+        self.variables.insert(name, value);
+    }
+
+    /// Defines a constant, which can't be assigned again.
+    pub fn set_constant(&mut self, name: String, value: f64) {
+        self.constants.insert(name.clone());
+        self.set(name, value);
+    }
+
+    /// Seeds the random number generator, so that `rand` gives the same
+    /// numbers every time it's seeded with the same seed.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Some(Rng::new(seed));
+    }
+
+    /// The random number generator, seeded with the current time if needed.
+    fn rng(&mut self) -> &mut Rng {
+        self.rng.get_or_insert_with(Rng::default)
+    }
+
+    /// The most recent results, oldest first.
+    pub fn history(&self) -> &VecDeque<f64> {
+        &self.history
+    }
+
+    /// Adds a result to the history, forgetting the oldest one if it's full.
+    /// Returns the forgotten result, if any.
+    pub fn remember(&mut self, result: f64) -> Option<f64> {
+        let forgotten = match self.history.len() {
+            MAX_HISTORY => self.history.pop_front(),
+            _ => None,
+        };
+        self.history.push_back(result);
+        forgotten
+    }
+
+    /// A copy of the environment without the history, which can be much larger
+    /// than the rest and doesn't need to be copied as often.
+    pub fn without_history(&mut self) -> Environment {
+        let history = std::mem::take(&mut self.history);
+        let copy = self.clone();
+        self.history = history;
+        copy
+    }
+
+    /// Whether an operation overflowed with the `Warn` policy since the
+    /// last call, clearing the flag.
+    pub fn take_overflow(&mut self) -> bool {
+        std::mem::take(&mut self.overflowed)
+    }
+
+    /// The division by zero that happened first since the last call, if any,
+    /// clearing it. Dividing by zero is infinite or `NaN`, not an error.
+    pub fn take_division_by_zero(&mut self) -> Option<Span> {
+        self.divided_by_zero.take()
+    }
+
+    /// Handles an operation that overflowed with the given policy: it's
+    /// allowed, remembered for `take_overflow`, or an error.
+    fn overflow(&mut self, policy: OverflowPolicy, span: Span) -> Result<(), RuntimeError> {
+        match policy {
+            OverflowPolicy::Allow => Ok(()),
+            OverflowPolicy::Warn => {
+                self.overflowed = true;
+                Ok(())
+            }
+            OverflowPolicy::Error => Err(RuntimeError::Overflow(span)),
+        }
+    }
+
+    /// The number of expression nodes evaluated since the last call,
+    /// resetting the count. Nodes reused from the memoization cache aren't counted.
+    pub fn take_evaluated_nodes(&mut self) -> usize {
+        std::mem::take(&mut self.evaluated_nodes)
+    }
+
+    /// Spends one operation of the budget, if there is one.
+    fn spend(&mut self, span: Span) -> Result<(), RuntimeError> {
+        match &mut self.budget {
+            Some(0) => Err(RuntimeError::BudgetExceeded(span)),
+            Some(budget) => {
+                *budget -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Gives a variable bound by `table`, `sum` or `product` its previous value back,
+    /// or removes it if it didn't exist before.
+    fn restore(&mut self, name: &str, previous: Option<f64>) {
+        match previous {
+            Some(previous) => self.set(name.to_string(), previous),
+            None => {
+                self.variables.remove(name);
+            }
+        }
+    }
+
+    /// The variables sorted by name, with their values and whether they're constants.
+    pub fn variables(&self) -> Vec<(&str, f64, bool)> {
+        let mut variables = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value, self.is_constant(name)))
+            .collect::<Vec<_>>();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    /// Whether a variable is a constant.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.constants.contains(name)
+    }
+
+    /// Assigns a value to a variable, or defines a constant. Fails if the
+    /// variable is already a constant, before the value is even evaluated,
+    /// so that no assignment in the value happens either.
+    pub(crate) fn assign(
+        &mut self,
+        name: &str,
+        constant: bool,
+        span: Span,
+        value: impl FnOnce(&mut Self) -> Result<f64, RuntimeError>,
+    ) -> Result<f64, RuntimeError> {
+        if self.is_constant(name) {
+            return Err(RuntimeError::ConstantReassignment(name.to_string(), span));
+        }
+        let value = value(self)?;
+        if constant {
+            self.set_constant(name.to_string(), value);
+        } else {
+            self.set(name.to_string(), value);
+        }
+        Ok(value)
+    }
+}
+
+/// The number of the memory slot a name recalls, like 1 for `M1`.
+fn memory_slot(name: &str) -> Option<usize> {
+    match name.strip_prefix('M')?.as_bytes() {
+        [digit @ b'1'..=b'9'] => Some((digit - b'0') as usize),
+        _ => None,
+    }
+}
+
+/// A cache of already evaluated subexpressions, keyed by their structure.
+type Memo<'a> = HashMap<&'a Expression, f64>;
 
 /// Recursively evaluates an expression
-pub fn evaluate(expr: Expression) -> f64 {
-    match expr {
+pub fn evaluate(expr: Expression, env: &mut Environment) -> Result<f64, RuntimeError> {
+    expr.eval_in(env)
+}
+
+impl Expression {
+    /// Evaluates this expression in a new environment. Unlike `evaluate`, the
+    /// expression is borrowed, so it can be evaluated again without cloning it.
+    pub fn eval(&self) -> Result<f64, RuntimeError> {
+        self.eval_in(&mut Environment::default())
+    }
+
+    /// Evaluates this expression in the given environment.
+    pub fn eval_in(&self, env: &mut Environment) -> Result<f64, RuntimeError> {
+        evaluate_node(self, env, None, Modes::default(), &[])
+    }
+}
+
+/// Recursively evaluates an expression, but stops if it takes more than `budget`
+/// operations, so that untrusted input can't run for too long. Every evaluated
+/// expression node is an operation, and so is every application of the
+/// function of `iterate`.
+pub fn evaluate_with_budget(
+    expr: Expression,
+    env: &mut Environment,
+    budget: usize,
+) -> Result<f64, RuntimeError> {
+    let outer_budget = env.budget.replace(budget);
+    let result = expr.eval_in(env);
+    env.budget = outer_budget;
+    result
+}
+
+/// Recursively evaluates an expression, rounding the result of every operation
+/// and function call to the given number of decimals, like currency amounts.
+/// Unlike rounding the final result, this keeps errors from accumulating, so
+/// `0.1 + 0.2` is exactly `0.3` with 2 decimals.
+pub fn evaluate_fixed_point(
+    expr: Expression,
+    env: &mut Environment,
+    decimals: usize,
+) -> Result<f64, RuntimeError> {
+    evaluate_node(
+        &expr,
+        env,
+        None,
+        Modes {
+            decimals: Some(decimals),
+            ..Modes::default()
+        },
+        &[],
+    )
+}
+
+/// Recursively evaluates an expression in the calculator's modes: every operation
+/// is rounded to the modes' decimals if there are some, `==` and `!=` allow
+/// their relative tolerance if there is one, every value must be an integer
+/// if there is an integer division mode, and overflows are handled with
+/// their policy.
+pub fn evaluate_with(
+    expr: Expression,
+    env: &mut Environment,
+    modes: Modes,
+) -> Result<f64, RuntimeError> {
+    evaluate_node(&expr, env, None, modes, &[])
+}
+
+/// Recursively evaluates an expression, remembering the value of every
+/// subexpression so that identical subexpressions are only evaluated once.
+///
+/// Expressions containing assignments or random calls are evaluated without
+/// memoization, since the same subexpression could have different values
+/// before and after an assignment, or every time `rand` or `dice` is called.
+pub fn evaluate_memoized(expr: Expression, env: &mut Environment) -> Result<f64, RuntimeError> {
+    let mut memo = Memo::new();
+    let memo = (!has_side_effects(&expr)).then_some(&mut memo);
+    evaluate_node(&expr, env, memo, Modes::default(), &[])
+}
+
+/// Recursively evaluates a template, replacing every placeholder with its
+/// argument. `$1` is the first argument, `$2` the second one, and so on.
+pub fn evaluate_template(
+    expr: &Expression,
+    env: &mut Environment,
+    arguments: &[f64],
+) -> Result<f64, RuntimeError> {
+    evaluate_node(expr, env, None, Modes::default(), arguments)
+}
+
+/// Whether an expression contains an assignment or a `rand` or `dice` call.
+fn has_side_effects(expr: &Expression) -> bool {
+    match expr.kind() {
+        ExpressionKind::Binary { lhs, rhs, .. } => has_side_effects(lhs) || has_side_effects(rhs),
+        ExpressionKind::Comparisons { first, comparisons } => {
+            has_side_effects(first)
+                || comparisons
+                    .iter()
+                    .any(|(_, operand)| has_side_effects(operand))
+        }
+        ExpressionKind::Unary { operand, .. } => has_side_effects(operand),
+        ExpressionKind::Call { name, arguments } => {
+            // The template of `apply` may call `rand` too
+            matches!(name.as_str(), "rand" | "dice" | "apply")
+                || arguments.iter().any(has_side_effects)
+                // `iterate(rand, 0, 3)` calls `rand` too
+                || name == "iterate"
+                    && matches!(
+                        arguments.first().map(Expression::kind),
+                        Some(ExpressionKind::Variable(function)) if function == "rand" || function == "dice"
+                    )
+        }
+        ExpressionKind::Assignment { .. } => true,
+        ExpressionKind::Variable(_)
+        | ExpressionKind::Atom(_)
+        | ExpressionKind::Imaginary(_)
+        | ExpressionKind::Placeholder(_)
+        | ExpressionKind::Text(_) => false,
+    }
+}
+
+/// The calculator's modes, in which every operation is evaluated.
+/// The default modes are plain floating-point arithmetic.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Modes {
+    /// The number of decimals every operation is rounded to, if there are some.
+    pub decimals: Option<usize>,
+    /// The relative tolerance of `==` and `!=`, if there is one.
+    pub tolerance: Option<f64>,
+    /// The integer division mode, if every value must be an integer.
+    pub integer: Option<IntegerDivision>,
+    /// How operations that overflow are handled.
+    pub overflow: OverflowPolicy,
+}
+
+/// Recursively evaluates an expression in the given modes, using the memoization
+/// cache if there is one. Placeholders are replaced with their values, `$1` being the first one.
+fn evaluate_node<'a>(
+    expr: &'a Expression,
+    env: &mut Environment,
+    mut memo: Option<&mut Memo<'a>>,
+    modes: Modes,
+    placeholders: &[f64],
+) -> Result<f64, RuntimeError> {
+    let Modes {
+        decimals,
+        integer,
+        overflow,
+        ..
+    } = modes;
+    // Reuse the value of an identical subexpression if it was already evaluated
+    if let Some(value) = memo.as_ref().and_then(|memo| memo.get(expr)) {
+        return Ok(*value);
+    }
+    env.evaluated_nodes += 1;
+    env.spend(expr.span)?;
+
+    let value = match expr.kind() {
         // Binary expressions
-        Expression::Binary {
+        ExpressionKind::Binary {
             operation,
             lhs,
             rhs,
-        } => match operation {
-            BinaryOperation::Addition => evaluate(*lhs) + evaluate(*rhs),
-            BinaryOperation::Subtraction => evaluate(*lhs) - evaluate(*rhs),
-            BinaryOperation::Multiplication => evaluate(*lhs) * evaluate(*rhs),
-            BinaryOperation::Division => evaluate(*lhs) / evaluate(*rhs),
+        } => {
+            let lhs = evaluate_node(lhs, env, memo.as_deref_mut(), modes, placeholders)?;
+            let rhs = evaluate_node(rhs, env, memo.as_deref_mut(), modes, placeholders)?;
+            let value = match operation {
+                // There is no integer infinity
+                BinaryOperation::Division if integer.is_some() && rhs == 0.0 => {
+                    return Err(RuntimeError::DivisionByZero(expr.span));
+                }
+                BinaryOperation::Division if integer == Some(IntegerDivision::Floor) => {
+                    (lhs / rhs).floor()
+                }
+                _ => compare_or_binary(operation, lhs, rhs, modes, expr.span)?,
+            };
+            if *operation == BinaryOperation::Division && rhs == 0.0 {
+                env.divided_by_zero.get_or_insert(expr.span);
+            }
+            // Dividing by zero is infinite on purpose, it isn't an overflow
+            if overflowed(value, &[lhs, rhs])
+                && !(*operation == BinaryOperation::Division && rhs == 0.0)
+            {
+                env.overflow(overflow, expr.span)?;
+            }
+            round_to(value, decimals)
+        }
+        // Every operand of chained comparisons is evaluated once, even
+        // after a comparison is false
+        ExpressionKind::Comparisons { first, comparisons } => {
+            let mut lhs = evaluate_node(first, env, memo.as_deref_mut(), modes, placeholders)?;
+            let mut all_true = true;
+            for (operation, operand) in comparisons {
+                let rhs = evaluate_node(operand, env, memo.as_deref_mut(), modes, placeholders)?;
+                let compared = compare_or_binary(operation, lhs, rhs, modes, expr.span)?;
+                all_true &= to_bool(compared);
+                lhs = rhs;
+            }
+            from_bool(all_true)
+        }
+        // Unary expressions
+        ExpressionKind::Unary { operation, operand } => {
+            let operand = evaluate_node(operand, env, memo.as_deref_mut(), modes, placeholders)?;
+            let value = unary(operation, operand, overflow, expr.span)?;
+            if overflowed(value, &[operand]) {
+                env.overflow(overflow, expr.span)?;
+            }
+            round_to(value, decimals)
+        }
+        // Function calls. At the top level, the session makes tables
+        // instead, using `evaluate_table`.
+        ExpressionKind::Call { name, .. } if name == "table" => {
+            return Err(RuntimeError::NestedTable(expr.span));
+        }
+        // The expression of a sum or a product is evaluated once per term,
+        // so it isn't memoized
+        ExpressionKind::Call { name, arguments } if name == "sum" || name == "product" => {
+            let total = series(
+                name,
+                arguments,
+                env,
+                expr.span,
+                overflow,
+                |expression, env| evaluate_node(expression, env, None, modes, placeholders),
+            )?;
+            round_to(total, decimals)
+        }
+        // The format of `fmt` is text, not a value. At the top level, the
+        // session shows the formatted value instead, using `evaluate_format`.
+        ExpressionKind::Call { name, arguments } if name == "fmt" => {
+            let (value, _) = format_arguments(arguments, env, expr.span, |expression, env| {
+                evaluate_node(expression, env, None, modes, placeholders)
+            })?;
+            round_to(value, decimals)
+        }
+        // `apply` evaluates the template in the same modes
+        ExpressionKind::Call { name, arguments } if name == "apply" => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| {
+                    evaluate_node(argument, env, memo.as_deref_mut(), modes, placeholders)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            round_to(apply(&arguments, env, expr.span, modes)?, decimals)
+        }
+        // `prev` points at its argument if it isn't a positive integer
+        ExpressionKind::Call { name, arguments } if name == "prev" => {
+            let [argument] = arguments.as_slice() else {
+                return Err(RuntimeError::WrongArgumentCount(
+                    1,
+                    arguments.len(),
+                    expr.span,
+                ));
+            };
+            let n = evaluate_node(argument, env, memo.as_deref_mut(), modes, placeholders)?;
+            if n < 1.0 || n.fract() != 0.0 {
+                return Err(RuntimeError::ExpectedPositiveInteger(argument.span));
+            }
+            round_to(call_in(name, &[n], env, expr.span)?, decimals)
+        }
+        // The first argument of `reduce` is an operator, not a value
+        ExpressionKind::Call { name, arguments } if name == "reduce" => {
+            let value = reduce(arguments, env, expr.span, overflow, |expression, env| {
+                evaluate_node(expression, env, None, modes, placeholders)
+            })?;
+            round_to(value, decimals)
+        }
+        // The first argument of `iterate` is a function name, not a value
+        ExpressionKind::Call { name, arguments } if name == "iterate" => {
+            let value = iterate(arguments, env, expr.span, overflow, |expression, env| {
+                evaluate_node(expression, env, None, modes, placeholders)
+            })?;
+            round_to(value, decimals)
+        }
+        ExpressionKind::Call { name, arguments } => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| {
+                    evaluate_node(argument, env, memo.as_deref_mut(), modes, placeholders)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let value = call_in(name, &arguments, env, expr.span)?;
+            if overflowed(value, &arguments) {
+                env.overflow(overflow, expr.span)?;
+            }
+            round_to(value, decimals)
+        }
+        // Assignments evaluate to the assigned value
+        ExpressionKind::Assignment {
+            name,
+            value,
+            constant,
+        } => env.assign(name, *constant, expr.span, |env| {
+            evaluate_node(value, env, memo.as_deref_mut(), modes, placeholders)
+        })?,
+        // Variables
+        ExpressionKind::Variable(name) => match env.get(name) {
+            Some(value) => value,
+            None => return Err(unknown_variable(name, expr.span)),
         },
+        // Atoms
+        ExpressionKind::Atom(num) => num.0,
+        // Imaginary numbers only exist in complex mode
+        ExpressionKind::Imaginary(_) => return Err(RuntimeError::ExpectedReal(expr.span)),
+        // Placeholders are numbered from 1
+        ExpressionKind::Placeholder(index) => match placeholders.get(index - 1) {
+            Some(value) => *value,
+            None => return Err(RuntimeError::MissingArgument(*index, expr.span)),
+        },
+        ExpressionKind::Text(_) => return Err(RuntimeError::UnexpectedText(expr.span)),
+    };
+
+    // Checking every value, and not only the result, points to the
+    // operation that isn't an integer, and catches variables assigned
+    // before integer mode was turned on
+    if integer.is_some() && value.fract() != 0.0 {
+        return Err(RuntimeError::NonIntegerResult(expr.span));
+    }
+
+    if let Some(memo) = memo {
+        memo.insert(expr, value);
+    }
+
+    Ok(value)
+}
+
+/// Evaluates an expression exactly, in big integer mode. Only integer literals
+/// and variables up to `MAX_EXACT_FLOAT`, `+`, `-`, `*`, `^` with a non-negative
+/// exponent, `!` and negation are exact. Returns `None` if the expression uses
+/// anything else, so that it can be evaluated with floats instead.
+#[cfg(feature = "bigint")]
+pub fn evaluate_bigint(
+    expr: &Expression,
+    env: &Environment,
+) -> Option<Result<BigInt, RuntimeError>> {
+    evaluate_bigint_node(expr, env).transpose()
+}
+
+/// Recursively evaluates an expression to a big integer, or to `None` if
+/// it isn't exact on integers.
+#[cfg(feature = "bigint")]
+fn evaluate_bigint_node(
+    expr: &Expression,
+    env: &Environment,
+) -> Result<Option<BigInt>, RuntimeError> {
+    let value = match expr.kind() {
+        ExpressionKind::Binary {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let (Some(lhs), Some(rhs)) = (
+                evaluate_bigint_node(lhs, env)?,
+                evaluate_bigint_node(rhs, env)?,
+            ) else {
+                return Ok(None);
+            };
+            match operation {
+                BinaryOperation::Addition => Some(lhs + rhs),
+                BinaryOperation::Subtraction => Some(lhs - rhs),
+                BinaryOperation::Multiplication => Some(lhs * rhs),
+                // Squaring would take too long before the digits could be counted,
+                // so they're estimated first
+                BinaryOperation::Exponentiation => match rhs.to_u64() {
+                    Some(exponent) if lhs.log10() * exponent as f64 > MAX_BIGINT_DIGITS as f64 => {
+                        return Err(RuntimeError::TooManyDigits(expr.span));
+                    }
+                    Some(exponent) => Some(lhs.pow(exponent)),
+                    None => None,
+                },
+                _ => None,
+            }
+        }
+        ExpressionKind::Unary { operation, operand } => {
+            let Some(operand) = evaluate_bigint_node(operand, env)? else {
+                return Ok(None);
+            };
+            match operation {
+                UnaryOperation::Negation => Some(-operand),
+                UnaryOperation::Factorial => match operand.to_u64() {
+                    Some(n) => Some(bigint_factorial(n, expr.span)?),
+                    None => None,
+                },
+                _ => None,
+            }
+        }
+        ExpressionKind::Variable(name) => env.get(name).and_then(BigInt::from_f64),
+        ExpressionKind::Atom(num) => BigInt::from_f64(num.0),
+        _ => None,
+    };
+    match value {
+        Some(value) if value.digits() > MAX_BIGINT_DIGITS => {
+            Err(RuntimeError::TooManyDigits(expr.span))
+        }
+        value => Ok(value),
+    }
+}
+
+/// The exact factorial of `n`. The digits are counted after every
+/// multiplication, so that a large `n` fails before taking too long.
+#[cfg(feature = "bigint")]
+fn bigint_factorial(n: u64, span: Span) -> Result<BigInt, RuntimeError> {
+    (2..=n).try_fold(BigInt::from(1), |product, i| {
+        let product = product * BigInt::from(i);
+        if product.digits() > MAX_BIGINT_DIGITS {
+            return Err(RuntimeError::TooManyDigits(span));
+        }
+        Ok(product)
+    })
+}
+
+/// Recursively evaluates an expression in complex mode, where imaginary
+/// numbers are allowed. Variables can only hold real numbers.
+pub fn evaluate_complex(expr: Expression, env: &mut Environment) -> Result<Complex, RuntimeError> {
+    evaluate_complex_node(&expr, env)
+}
+
+/// Recursively evaluates an expression to a complex number.
+fn evaluate_complex_node(
+    expr: &Expression,
+    env: &mut Environment,
+) -> Result<Complex, RuntimeError> {
+    match expr.kind() {
+        // Binary expressions
+        ExpressionKind::Binary {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let lhs = evaluate_complex_node(lhs, env)?;
+            let rhs = evaluate_complex_node(rhs, env)?;
+            complex_binary(operation, lhs, rhs, expr.span)
+        }
+        // Chained comparisons
+        ExpressionKind::Comparisons { first, comparisons } => {
+            let mut lhs = evaluate_complex_node(first, env)?;
+            let mut all_true = true;
+            for (operation, operand) in comparisons {
+                let rhs = evaluate_complex_node(operand, env)?;
+                all_true &= complex_to_bool(complex_binary(operation, lhs, rhs, expr.span)?);
+                lhs = rhs;
+            }
+            Ok(from_bool(all_true).into())
+        }
         // Unary expressions
-        Expression::Unary { operation, operand } => match operation {
-            UnaryOperation::Negation => -evaluate(*operand),
+        ExpressionKind::Unary { operation, operand } => {
+            let operand = evaluate_complex_node(operand, env)?;
+            complex_unary(operation, operand, expr.span)
+        }
+        // Function calls
+        ExpressionKind::Call { name, arguments } => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| evaluate_complex_node(argument, env))
+                .collect::<Result<Vec<_>, _>>()?;
+            complex_call(name, &arguments, env, expr.span)
+        }
+        // Assignments evaluate to the assigned value, which must be real
+        ExpressionKind::Assignment {
+            name,
+            value,
+            constant,
+        } => env
+            .assign(name, *constant, expr.span, |env| {
+                let evaluated = evaluate_complex_node(value, env)?;
+                if !evaluated.is_real() {
+                    return Err(RuntimeError::ExpectedReal(value.span));
+                }
+                Ok(evaluated.re)
+            })
+            .map(Complex::from),
+        // Variables
+        ExpressionKind::Variable(name) => match env.get(name) {
+            Some(value) => Ok(value.into()),
+            None => Err(unknown_variable(name, expr.span)),
         },
         // Atoms
-        Expression::Atom(num) => num,
+        ExpressionKind::Atom(num) => Ok(num.0.into()),
+        ExpressionKind::Imaginary(num) => Ok(Complex::new(0.0, num.0)),
+        // Templates are only evaluated in real mode
+        ExpressionKind::Placeholder(index) => Err(RuntimeError::MissingArgument(*index, expr.span)),
+        ExpressionKind::Text(_) => Err(RuntimeError::UnexpectedText(expr.span)),
+    }
+}
+
+/// Applies a binary operation to already evaluated complex operands.
+fn complex_binary(
+    operation: &BinaryOperation,
+    lhs: Complex,
+    rhs: Complex,
+    span: Span,
+) -> Result<Complex, RuntimeError> {
+    // Real operands behave like in real mode, except that roots of negative
+    // numbers, like `(-4)^0.5`, are imaginary instead of `NaN`.
+    let negative_root = matches!(operation, BinaryOperation::Exponentiation)
+        && lhs.re < 0.0
+        && rhs.re.fract() != 0.0;
+    if lhs.is_real() && rhs.is_real() && !negative_root {
+        // Complex mode has no modes, so overflows are always errors, like in `powc`
+        return binary(operation, lhs.re, rhs.re, OverflowPolicy::Error, span).map(Complex::from);
+    }
+
+    let value = match operation {
+        BinaryOperation::Addition => lhs + rhs,
+        BinaryOperation::Subtraction => lhs - rhs,
+        BinaryOperation::Multiplication => lhs * rhs,
+        BinaryOperation::Division => lhs / rhs,
+        BinaryOperation::Exponentiation => {
+            let value = lhs.powc(rhs);
+            if !value.is_finite() {
+                return Err(RuntimeError::Overflow(span));
+            }
+            value
+        }
+        // Complex numbers can be equal or not, but they aren't ordered
+        BinaryOperation::Equal => from_bool(lhs == rhs).into(),
+        BinaryOperation::NotEqual => from_bool(lhs != rhs).into(),
+        BinaryOperation::Less
+        | BinaryOperation::LessOrEqual
+        | BinaryOperation::Greater
+        | BinaryOperation::GreaterOrEqual
+        | BinaryOperation::Minimum
+        | BinaryOperation::Maximum => return Err(RuntimeError::ExpectedReal(span)),
+        BinaryOperation::And => from_bool(complex_to_bool(lhs) & complex_to_bool(rhs)).into(),
+        BinaryOperation::Or => from_bool(complex_to_bool(lhs) | complex_to_bool(rhs)).into(),
+        // The percentage is relative to the left operand
+        BinaryOperation::AddPercentage => lhs + lhs * rhs / 100.0.into(),
+        BinaryOperation::SubtractPercentage => lhs - lhs * rhs / 100.0.into(),
+    };
+
+    Ok(value)
+}
+
+/// Applies a unary operation to an already evaluated complex operand.
+fn complex_unary(
+    operation: &UnaryOperation,
+    operand: Complex,
+    span: Span,
+) -> Result<Complex, RuntimeError> {
+    // Real operands behave like in real mode, except that the square root
+    // of a negative number is imaginary instead of an error.
+    if operand.is_real() && !matches!(operation, UnaryOperation::SquareRoot) {
+        return unary(operation, operand.re, OverflowPolicy::Error, span).map(Complex::from);
+    }
+
+    match operation {
+        UnaryOperation::Negation => Ok(-operand),
+        UnaryOperation::Not => Ok(from_bool(!complex_to_bool(operand)).into()),
+        UnaryOperation::Factorial => Err(RuntimeError::ExpectedNonNegativeInteger(span)),
+        UnaryOperation::Percent => Ok(operand / 100.0.into()),
+        UnaryOperation::SquareRoot => Ok(operand.sqrt()),
+    }
+}
+
+/// Calls the builtin function `name` with already evaluated complex arguments.
+fn complex_call(
+    name: &str,
+    arguments: &[Complex],
+    env: &mut Environment,
+    span: Span,
+) -> Result<Complex, RuntimeError> {
+    match name {
+        // The square root of a negative number is imaginary
+        "sqrt" => Ok(single_argument(arguments, span)?.sqrt()),
+        "inv" | "reciprocal" => {
+            let x = single_argument(arguments, span)?;
+            if x == Complex::default() {
+                return Err(RuntimeError::DivisionByZero(span));
+            }
+            Ok(Complex::from(1.0) / x)
+        }
+        // Other functions only accept real arguments
+        _ => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| argument.is_real().then_some(argument.re))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(RuntimeError::ExpectedReal(span))?;
+            call_in(name, &arguments, env, span).map(Complex::from)
+        }
+    }
+}
+
+/// Applies a binary operation to already evaluated operands in the given modes,
+/// comparing them for equality with the relative tolerance of `?approx` if there is one.
+fn compare_or_binary(
+    operation: &BinaryOperation,
+    lhs: f64,
+    rhs: f64,
+    modes: Modes,
+    span: Span,
+) -> Result<f64, RuntimeError> {
+    match (operation, modes.tolerance) {
+        (BinaryOperation::Equal, Some(tolerance)) => {
+            Ok(from_bool(nearly_equal(lhs, rhs, tolerance)))
+        }
+        (BinaryOperation::NotEqual, Some(tolerance)) => {
+            Ok(from_bool(!nearly_equal(lhs, rhs, tolerance)))
+        }
+        _ => binary(operation, lhs, rhs, modes.overflow, span),
+    }
+}
+
+/// The error of a variable that isn't assigned. If it's a function name,
+/// like `sqrt`, the function was most likely meant to be called.
+pub(crate) fn unknown_variable(name: &str, span: Span) -> RuntimeError {
+    if FUNCTIONS.contains(&name) {
+        RuntimeError::ExpectedFunctionCall(name.to_string(), span)
+    } else {
+        RuntimeError::UnknownVariable(name.to_string(), span)
+    }
+}
+
+/// Applies a binary operation to already evaluated operands.
+/// Only the overflow of `^` is handled here, since it can be detected before
+/// computing the power. The caller handles the others with `overflowed`.
+pub(crate) fn binary(
+    operation: &BinaryOperation,
+    lhs: f64,
+    rhs: f64,
+    overflow: OverflowPolicy,
+    span: Span,
+) -> Result<f64, RuntimeError> {
+    let value = match operation {
+        BinaryOperation::Addition => lhs + rhs,
+        BinaryOperation::Subtraction => lhs - rhs,
+        BinaryOperation::Multiplication => lhs * rhs,
+        BinaryOperation::Division => lhs / rhs,
+        BinaryOperation::Exponentiation => power(lhs, rhs, overflow, span)?,
+        // Comparisons and logical operations evaluate to `1` (true) or `0` (false).
+        BinaryOperation::Less => from_bool(lhs < rhs),
+        BinaryOperation::LessOrEqual => from_bool(lhs <= rhs),
+        BinaryOperation::Greater => from_bool(lhs > rhs),
+        BinaryOperation::GreaterOrEqual => from_bool(lhs >= rhs),
+        BinaryOperation::Equal => from_bool(lhs == rhs),
+        BinaryOperation::NotEqual => from_bool(lhs != rhs),
+        // Both operands were already evaluated, so `and` and `or` don't
+        // short-circuit, and an assignment in either operand always happens
+        BinaryOperation::And => from_bool(to_bool(lhs) & to_bool(rhs)),
+        BinaryOperation::Or => from_bool(to_bool(lhs) | to_bool(rhs)),
+        // The percentage is relative to the left operand
+        BinaryOperation::AddPercentage => lhs + lhs * rhs / 100.0,
+        BinaryOperation::SubtractPercentage => lhs - lhs * rhs / 100.0,
+        BinaryOperation::Minimum => lhs.min(rhs),
+        BinaryOperation::Maximum => lhs.max(rhs),
+    };
+
+    Ok(value)
+}
+
+/// Applies a unary operation to an already evaluated operand.
+/// Only factorials can overflow.
+pub(crate) fn unary(
+    operation: &UnaryOperation,
+    operand: f64,
+    overflow: OverflowPolicy,
+    span: Span,
+) -> Result<f64, RuntimeError> {
+    match operation {
+        UnaryOperation::Negation => Ok(-operand),
+        UnaryOperation::Not => Ok(from_bool(!to_bool(operand))),
+        UnaryOperation::Factorial => factorial(operand, overflow, span),
+        UnaryOperation::Percent => Ok(operand / 100.0),
+        UnaryOperation::SquareRoot => square_root(operand, span),
+    }
+}
+
+/// Calls the builtin function `name` with already evaluated arguments.
+pub(crate) fn call(name: &str, arguments: &[f64], span: Span) -> Result<f64, RuntimeError> {
+    match name {
+        "inv" | "reciprocal" => reciprocal(single_argument(arguments, span)?, span),
+        "sqrt" => Ok(single_argument(arguments, span)?.sqrt()),
+        // Unlike `x^(1/3)`, which is `NaN` for negative numbers since `1/3`
+        // isn't exactly a third, the cube root of a negative number is negative.
+        "cbrt" => Ok(single_argument(arguments, span)?.cbrt()),
+        // `round(x)` rounds to an integer, and `round(x, n)` to `n` decimals
+        "round" => match arguments {
+            [x] => Ok(x.round()),
+            [x, decimals] if decimals.fract() == 0.0 => Ok(round_decimals(*x, *decimals as i32)),
+            [_, _] => Err(RuntimeError::ExpectedInteger(span)),
+            _ => Err(RuntimeError::WrongArgumentCount(2, arguments.len(), span)),
+        },
+        // `truncate_to(x, n)` is like `round(x, n)`, but toward zero
+        "truncate_to" => match arguments {
+            [x, decimals] if decimals.fract() == 0.0 => Ok(truncate_decimals(*x, *decimals as i32)),
+            [_, _] => Err(RuntimeError::ExpectedInteger(span)),
+            _ => Err(RuntimeError::WrongArgumentCount(2, arguments.len(), span)),
+        },
+        "nice" => Ok(nice(single_argument(arguments, span)?)),
+        // Angle conversions, `degrees(x)` is `x * 180/π` and `radians(x)` is `x * π/180`
+        "degrees" => Ok(single_argument(arguments, span)?.to_degrees()),
+        "radians" => Ok(single_argument(arguments, span)?.to_radians()),
+        // Hyperbolic functions. The inverses are only defined on part of the
+        // real line, outside of which they would be `NaN`.
+        "sinh" => Ok(single_argument(arguments, span)?.sinh()),
+        "cosh" => Ok(single_argument(arguments, span)?.cosh()),
+        "tanh" => Ok(single_argument(arguments, span)?.tanh()),
+        "asinh" => Ok(single_argument(arguments, span)?.asinh()),
+        "acosh" => match single_argument(arguments, span)? {
+            x if x >= 1.0 => Ok(x.acosh()),
+            _ => Err(RuntimeError::ExpectedAtLeastOne(span)),
+        },
+        "atanh" => match single_argument(arguments, span)? {
+            x if x.abs() < 1.0 => Ok(x.atanh()),
+            _ => Err(RuntimeError::ExpectedBetweenMinusOneAndOne(span)),
+        },
+        // Inverse trigonometric functions, in radians like `degrees` expects.
+        // `asin` and `acos` are only defined from -1 to 1.
+        "asin" => match single_argument(arguments, span)? {
+            x if (-1.0..=1.0).contains(&x) => Ok(x.asin()),
+            _ => Err(RuntimeError::ExpectedFromMinusOneToOne(span)),
+        },
+        "acos" => match single_argument(arguments, span)? {
+            x if (-1.0..=1.0).contains(&x) => Ok(x.acos()),
+            _ => Err(RuntimeError::ExpectedFromMinusOneToOne(span)),
+        },
+        "atan" => Ok(single_argument(arguments, span)?.atan()),
+        // The angle of the point `(x, y)`, from `-π` to `π`
+        "atan2" => {
+            let [y, x] = exact_arguments(arguments, span)?;
+            Ok(y.atan2(x))
+        }
+        // Percentages for financial math, without the ambiguity of `%`.
+        // `percent_of(200, 10)` is 20, and `add_pct(200, 10)` is 220.
+        "percent_of" => {
+            let [whole, percent] = exact_arguments(arguments, span)?;
+            Ok(whole * percent / 100.0)
+        }
+        "add_pct" => {
+            let [base, percent] = exact_arguments(arguments, span)?;
+            Ok(base + base * percent / 100.0)
+        }
+        // For scripts that check their own results. Like `==` in approximate
+        // mode, small rounding errors are allowed.
+        "assert" => {
+            let [actual, expected] = exact_arguments(arguments, span)?;
+            if !nearly_equal(actual, expected, APPROX_TOLERANCE) {
+                return Err(RuntimeError::AssertionFailed(actual, expected, span));
+            }
+            Ok(1.0)
+        }
+        "approx" => {
+            let [a, b, epsilon] = exact_arguments(arguments, span)?;
+            approx(a, b, epsilon, span)
+        }
+        // The value of the fraction. At the top level, the session shows
+        // the fraction itself instead, using `evaluate_fraction`.
+        "dec2frac" => {
+            let (numerator, denominator) = fraction_arguments(arguments, span)?;
+            Ok(numerator / denominator)
+        }
+        // The value of the argument. At the top level, the session shows
+        // it in the base instead, using `evaluate_radix`.
+        "hex" | "bin" | "oct" => {
+            let x = single_argument(arguments, span)?;
+            radix_integer(x, span)?;
+            Ok(x)
+        }
+        "is_prime" => is_prime(single_argument(arguments, span)?, span),
+        // Like the `!` operator. As for every function, the caller decides
+        // what an infinite result from a finite argument means.
+        "factorial" => factorial(
+            single_argument(arguments, span)?,
+            OverflowPolicy::Allow,
+            span,
+        ),
+        "choose" => {
+            let [n, k] = exact_arguments(arguments, span)?;
+            choose(n, k, span)
+        }
+        "permute" => {
+            let [n, k] = exact_arguments(arguments, span)?;
+            permute(n, k, span)
+        }
+        _ => Err(RuntimeError::UnknownFunction(name.to_string(), span)),
+    }
+}
+
+/// Calls the builtin function `name` with already evaluated arguments, in
+/// the given environment. Unlike `call`, this can call `rand` and `dice`,
+/// which use the environment's random number generator.
+pub(crate) fn call_in(
+    name: &str,
+    arguments: &[f64],
+    env: &mut Environment,
+    span: Span,
+) -> Result<f64, RuntimeError> {
+    match (name, arguments) {
+        // `rand()` is from 0 to 1 and `rand(a, b)` from `a` to `b`, the end excluded
+        ("rand", []) => Ok(env.rng().next_f64()),
+        ("rand", [a, b]) => Ok(a + (b - a) * env.rng().next_f64()),
+        ("rand", _) => Err(RuntimeError::WrongArgumentCountEither(
+            0,
+            2,
+            arguments.len(),
+            span,
+        )),
+        ("dice", _) => {
+            let [count, sides] = exact_arguments(arguments, span)?;
+            dice(count, sides, env.rng(), span)
+        }
+        ("apply", _) => apply(arguments, env, span, Modes::default()),
+        // `prev(1)` is the last result, `prev(2)` the one before, and so on
+        ("prev", _) => {
+            let n = single_argument(arguments, span)?;
+            if n < 1.0 || n.fract() != 0.0 {
+                return Err(RuntimeError::ExpectedPositiveInteger(span));
+            }
+            let history = env.history();
+            match history.len().checked_sub(n as usize) {
+                Some(index) => Ok(history[index]),
+                None => Err(RuntimeError::NoSuchResult(history.len(), span)),
+            }
+        }
+        // Functions come first, so `x(2)` is only `x * 2` if `x` is a variable
+        // and not a function
+        (name, _) if !FUNCTIONS.contains(&name) => match env.get(name) {
+            Some(value) => Ok(value * single_argument(arguments, span)?),
+            None => call(name, arguments, span),
+        },
+        _ => call(name, arguments, span),
+    }
+}
+
+/// Rolls `count` dice with `sides` sides each, and sums them, like `dice(3, 6)` for 3d6.
+fn dice(count: f64, sides: f64, rng: &mut Rng, span: Span) -> Result<f64, RuntimeError> {
+    let is_positive_integer = |x: f64| x >= 1.0 && x.fract() == 0.0;
+    if !is_positive_integer(count) || !is_positive_integer(sides) {
+        return Err(RuntimeError::ExpectedPositiveInteger(span));
+    }
+    if count > MAX_DICE {
+        return Err(RuntimeError::TooManyDice(span));
+    }
+    // Every die is from 1 to `sides`, with the same chance for each side
+    Ok((0..count as u64)
+        .map(|_| (rng.next_f64() * sides).floor() + 1.0)
+        .sum())
+}
+
+/// Gets the argument of a function that takes exactly one argument.
+fn single_argument<T: Copy>(arguments: &[T], span: Span) -> Result<T, RuntimeError> {
+    let [argument] = exact_arguments(arguments, span)?;
+    Ok(argument)
+}
+
+/// Gets the arguments of a function that takes exactly `N` arguments.
+fn exact_arguments<const N: usize, T: Copy>(
+    arguments: &[T],
+    span: Span,
+) -> Result<[T; N], RuntimeError> {
+    arguments
+        .try_into()
+        .map_err(|_| RuntimeError::WrongArgumentCount(N, arguments.len(), span))
+}
+
+/// Evaluates a `dec2frac` call to the numerator and denominator of its fraction,
+/// instead of its value, with its arguments evaluated in the given modes.
+/// Returns `None` if the expression isn't a `dec2frac` call.
+pub fn evaluate_fraction(
+    expr: &Expression,
+    env: &mut Environment,
+    modes: Modes,
+) -> Option<Result<(f64, f64), RuntimeError>> {
+    let ExpressionKind::Call { name, arguments } = expr.kind() else {
+        return None;
+    };
+    if name != "dec2frac" {
+        return None;
+    }
+
+    let fraction = arguments
+        .iter()
+        .map(|argument| evaluate_node(argument, env, None, modes, &[]))
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|arguments| fraction_arguments(&arguments, expr.span));
+    Some(fraction)
+}
+
+/// Evaluates a `hex`, `bin` or `oct` call to its value and to its argument
+/// written in that base, like `0xff` for `hex(255)`, with its argument evaluated
+/// in the given modes. Returns `None` if the expression isn't one of those calls.
+pub fn evaluate_radix(
+    expr: &Expression,
+    env: &mut Environment,
+    modes: Modes,
+) -> Option<Result<(f64, String), RuntimeError>> {
+    let ExpressionKind::Call { name, arguments } = expr.kind() else {
+        return None;
+    };
+    let format: fn(u128) -> String = match name.as_str() {
+        "hex" => |integer| format!("{integer:#x}"),
+        "bin" => |integer| format!("{integer:#b}"),
+        "oct" => |integer| format!("{integer:#o}"),
+        _ => return None,
+    };
+
+    let integer = arguments
+        .iter()
+        .map(|argument| evaluate_node(argument, env, None, modes, &[]))
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|arguments| radix_integer(single_argument(&arguments, expr.span)?, expr.span));
+    Some(integer.map(|integer| (integer as f64, format(integer))))
+}
+
+/// Evaluates a `fmt` call to its value and to the value formatted with
+/// its format, like `3.14` for `fmt(3.14159, "%.2f")`, with the value evaluated
+/// in the given modes. Returns `None` if the expression isn't a `fmt` call.
+pub fn evaluate_format(
+    expr: &Expression,
+    env: &mut Environment,
+    modes: Modes,
+) -> Option<Result<(f64, String), RuntimeError>> {
+    let ExpressionKind::Call { name, arguments } = expr.kind() else {
+        return None;
+    };
+    if name != "fmt" {
+        return None;
+    }
+    Some(format_arguments(
+        arguments,
+        env,
+        expr.span,
+        |argument, env| evaluate_node(argument, env, None, modes, &[]),
+    ))
+}
+
+/// Evaluates the value of `fmt(x, format)` with `evaluate`, and formats it
+/// with `printf`.
+fn format_arguments(
+    arguments: &[Expression],
+    env: &mut Environment,
+    span: Span,
+    evaluate: impl FnOnce(&Expression, &mut Environment) -> Result<f64, RuntimeError>,
+) -> Result<(f64, String), RuntimeError> {
+    let [value, format] = arguments else {
+        return Err(RuntimeError::WrongArgumentCount(2, arguments.len(), span));
+    };
+    let ExpressionKind::Text(format_text) = format.kind() else {
+        return Err(RuntimeError::ExpectedText(format.span));
+    };
+    let value = evaluate(value, env)?;
+    match printf(value, format_text) {
+        Some(formatted) => Ok((value, formatted)),
+        None => Err(RuntimeError::InvalidFormat(format.span)),
+    }
+}
+
+/// A table made by `table`, with the values of an expression for a range
+/// of values of a variable.
+#[derive(Debug, PartialEq)]
+pub struct Table {
+    /// The name of the variable.
+    pub variable: String,
+    /// The expression evaluated for every value of the variable.
+    pub expression: Expression,
+    /// The values of the variable, with the value of the expression for each.
+    pub rows: Vec<(f64, f64)>,
+}
+
+/// Evaluates a `table(expr, var, start, stop, step)` call to its table: `var`
+/// goes from `start` to `stop` included, by `step`, and `expr` is evaluated
+/// for every value. Every argument is evaluated in the given modes. Returns
+/// `None` if the expression isn't a `table` call.
+///
+/// `var` is only assigned while the table is made. Afterwards, it has its
+/// previous value again, or doesn't exist if it didn't before.
+pub fn evaluate_table(
+    expr: &Expression,
+    env: &mut Environment,
+    modes: Modes,
+) -> Option<Result<Table, RuntimeError>> {
+    let ExpressionKind::Call { name, arguments } = expr.kind() else {
+        return None;
+    };
+    if name != "table" {
+        return None;
+    }
+    Some(table(arguments, env, expr.span, modes))
+}
+
+/// Makes the table of `table(expr, var, start, stop, step)`.
+fn table(
+    arguments: &[Expression],
+    env: &mut Environment,
+    span: Span,
+    modes: Modes,
+) -> Result<Table, RuntimeError> {
+    let [expression, variable, start, stop, step] = arguments else {
+        return Err(RuntimeError::WrongArgumentCount(5, arguments.len(), span));
+    };
+    let ExpressionKind::Variable(variable) = variable.kind() else {
+        return Err(RuntimeError::ExpectedVariable(variable.span));
+    };
+    if env.is_constant(variable) {
+        return Err(RuntimeError::ConstantReassignment(
+            variable.clone(),
+            arguments[1].span,
+        ));
+    }
+
+    let mut bound = |argument: &Expression| match evaluate_node(argument, env, None, modes, &[])? {
+        value if value.is_finite() => Ok(value),
+        _ => Err(RuntimeError::ExpectedFinite(argument.span)),
+    };
+    let (start_value, stop_value) = (bound(start)?, bound(stop)?);
+    let step_value = evaluate_node(step, env, None, modes, &[])?;
+    // The step must go towards the end, unless the range has a single value
+    let steps = (stop_value - start_value) / step_value;
+    let steps = match steps {
+        _ if start_value == stop_value => 0.0,
+        steps if steps.is_finite() && steps >= 0.0 => steps,
+        _ => return Err(RuntimeError::InvalidStep(step.span)),
+    };
+    // Rounding errors shouldn't drop the last row, like in `table(x, x, 0, 0.3, 0.1)`
+    let rows = (steps + 1e-9).floor() as usize + 1;
+    if rows > MAX_TABLE_ROWS {
+        return Err(RuntimeError::TooManyRows(span));
+    }
+
+    let previous = env.get(variable);
+    let rows = (0..rows)
+        .map(|i| {
+            // Multiplying instead of adding keeps rounding errors from accumulating
+            let x = start_value + i as f64 * step_value;
+            env.set(variable.clone(), x);
+            Ok((x, evaluate_node(expression, env, None, modes, &[])?))
+        })
+        .collect::<Result<Vec<_>, _>>();
+    env.restore(variable, previous);
+
+    Ok(Table {
+        variable: variable.clone(),
+        expression: expression.clone(),
+        rows: rows?,
+    })
+}
+
+/// Adds up `sum(expr, var, lo, hi)`, or multiplies `product(expr, var, lo, hi)`:
+/// the values of `expr` for every integer `var` from `lo` to `hi` included,
+/// evaluated with `evaluate`. Like in mathematics, an empty sum is 0 and an
+/// empty product is 1, when `hi` is less than `lo`.
+///
+/// Like with `table`, `var` has its previous value again afterwards.
+fn series(
+    name: &str,
+    arguments: &[Expression],
+    env: &mut Environment,
+    span: Span,
+    overflow: OverflowPolicy,
+    mut evaluate: impl FnMut(&Expression, &mut Environment) -> Result<f64, RuntimeError>,
+) -> Result<f64, RuntimeError> {
+    let [expression, variable, lo, hi] = arguments else {
+        return Err(RuntimeError::WrongArgumentCount(4, arguments.len(), span));
+    };
+    let ExpressionKind::Variable(variable) = variable.kind() else {
+        return Err(RuntimeError::ExpectedVariable(variable.span));
+    };
+    if env.is_constant(variable) {
+        return Err(RuntimeError::ConstantReassignment(
+            variable.clone(),
+            arguments[1].span,
+        ));
+    }
+
+    // Infinite and `NaN` bounds have no integer part either
+    let mut bound = |argument: &Expression| match evaluate(argument, env)? {
+        value if value.fract() == 0.0 => Ok(value),
+        _ => Err(RuntimeError::ExpectedInteger(argument.span)),
+    };
+    let (lo_value, hi_value) = (bound(lo)?, bound(hi)?);
+    let terms = (hi_value - lo_value + 1.0).max(0.0);
+    if terms > MAX_TERMS {
+        return Err(RuntimeError::TooManyTerms(span));
+    }
+
+    let (identity, combine): (f64, fn(f64, f64) -> f64) = match name {
+        "product" => (1.0, |a, b| a * b),
+        _ => (0.0, |a, b| a + b),
+    };
+    let previous = env.get(variable);
+    let total = (0..terms as u64).try_fold(identity, |total, i| {
+        env.set(variable.clone(), lo_value + i as f64);
+        let term = evaluate(expression, env)?;
+        let value = combine(total, term);
+        if overflowed(value, &[total, term]) {
+            env.overflow(overflow, span)?;
+        }
+        Ok(value)
+    });
+    env.restore(variable, previous);
+    total
+}
+
+/// Applies the function of `iterate(f, x0, n)` `n` times, starting from `x0`,
+/// so `iterate(sqrt, 16, 2)` is `sqrt(sqrt(16))`. The arguments are evaluated
+/// with `evaluate`. `x0` is applied `0` times when `n` is `0`.
+fn iterate(
+    arguments: &[Expression],
+    env: &mut Environment,
+    span: Span,
+    overflow: OverflowPolicy,
+    mut evaluate: impl FnMut(&Expression, &mut Environment) -> Result<f64, RuntimeError>,
+) -> Result<f64, RuntimeError> {
+    let [function, initial, count] = arguments else {
+        return Err(RuntimeError::WrongArgumentCount(3, arguments.len(), span));
+    };
+    // Functions of expressions can't be applied to a number
+    let function = match function.kind() {
+        ExpressionKind::Variable(name)
+            if FUNCTIONS.contains(&name.as_str())
+                && !matches!(
+                    name.as_str(),
+                    "table" | "sum" | "product" | "iterate" | "reduce" | "fmt"
+                ) =>
+        {
+            name
+        }
+        _ => return Err(RuntimeError::ExpectedFunction(function.span)),
+    };
+
+    let mut value = evaluate(initial, env)?;
+    let count_value = evaluate(count, env)?;
+    if count_value < 0.0 || count_value.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedNonNegativeInteger(count.span));
+    }
+    if count_value > MAX_ITERATIONS {
+        return Err(RuntimeError::TooManyIterations(count.span));
+    }
+    for _ in 0..count_value as usize {
+        env.spend(span)?;
+        let next = call_in(function, &[value], env, span)?;
+        if overflowed(next, &[value]) {
+            env.overflow(overflow, span)?;
+        }
+        value = next;
+    }
+    Ok(value)
+}
+
+/// Evaluates the template with the arguments of `apply` as its placeholders.
+/// The template is taken out of the environment while it's evaluated, so an
+/// `apply` inside the template fails instead of recursing forever.
+fn apply(
+    arguments: &[f64],
+    env: &mut Environment,
+    span: Span,
+    modes: Modes,
+) -> Result<f64, RuntimeError> {
+    let template = env.template.take().ok_or(RuntimeError::NoTemplate(span))?;
+    let value = evaluate_node(&template, env, None, modes, arguments);
+    env.template = Some(template);
+    value
+}
+
+/// Folds the values of `reduce(op, a, b, ...)` with its operator, from the
+/// left, so `reduce("+", 1, 2, 3)` is `1 + 2 + 3`. The values are evaluated
+/// with `evaluate`.
+fn reduce(
+    arguments: &[Expression],
+    env: &mut Environment,
+    span: Span,
+    overflow: OverflowPolicy,
+    mut evaluate: impl FnMut(&Expression, &mut Environment) -> Result<f64, RuntimeError>,
+) -> Result<f64, RuntimeError> {
+    let Some((operator, values)) = arguments.split_first() else {
+        return Err(RuntimeError::WrongArgumentCount(2, 0, span));
+    };
+    let ExpressionKind::Text(operator_name) = operator.kind() else {
+        return Err(RuntimeError::ExpectedText(operator.span));
+    };
+    // `min` and `max` ignore `NaN`, like `<?` and `>?`
+    let operation: fn(f64, f64) -> f64 = match operator_name.as_str() {
+        "+" => |a, b| a + b,
+        "*" => |a, b| a * b,
+        "min" => f64::min,
+        "max" => f64::max,
+        _ => return Err(RuntimeError::UnknownOperator(operator.span)),
+    };
+    let Some((first, rest)) = values.split_first() else {
+        return Err(RuntimeError::NoValues(span));
+    };
+    let mut value = evaluate(first, env)?;
+    for expression in rest {
+        let operand = evaluate(expression, env)?;
+        let next = operation(value, operand);
+        if overflowed(next, &[value, operand]) {
+            env.overflow(overflow, span)?;
+        }
+        value = next;
+    }
+    Ok(value)
+}
+
+/// Whether an operation overflowed, giving an infinite value from finite operands.
+fn overflowed(value: f64, operands: &[f64]) -> bool {
+    value.is_infinite() && operands.iter().all(|operand| operand.is_finite())
+}
+
+/// Converts the argument of `hex`, `bin` or `oct` to an integer.
+/// It must be a non-negative integer that fits in 128 bits.
+fn radix_integer(x: f64, span: Span) -> Result<u128, RuntimeError> {
+    if x < 0.0 || x.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedNonNegativeInteger(span));
+    }
+    if x >= 2f64.powi(128) {
+        return Err(RuntimeError::Overflow(span));
+    }
+    Ok(x as u128)
+}
+
+/// Finds the fraction of `dec2frac(x)` or `dec2frac(x, max_denominator)`.
+fn fraction_arguments(arguments: &[f64], span: Span) -> Result<(f64, f64), RuntimeError> {
+    let (x, max_denominator) = match arguments {
+        [x] => (*x, MAX_DENOMINATOR),
+        [x, max_denominator] => (*x, *max_denominator),
+        [] => return Err(RuntimeError::WrongArgumentCount(1, 0, span)),
+        _ => return Err(RuntimeError::WrongArgumentCount(2, arguments.len(), span)),
+    };
+    if max_denominator < 1.0 || max_denominator.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedPositiveInteger(span));
+    }
+
+    Ok(fraction(x, max_denominator))
+}
+
+/// Approximates `x` by the closest fraction whose denominator is at most
+/// `max_denominator`, using continued fractions. Returns the numerator and
+/// the denominator, which is always positive. So `0.75` is `3/4`.
+pub fn fraction(x: f64, max_denominator: f64) -> (f64, f64) {
+    // Infinite and `NaN` values can't be written as fractions
+    if !x.is_finite() {
+        return (x, 1.0);
+    }
+
+    // The last two convergents are `numerator / denominator` and
+    // `previous_numerator / previous_denominator`. They start as `1/0` and `0/1`.
+    let (mut numerator, mut previous_numerator) = (1.0, 0.0);
+    let (mut denominator, mut previous_denominator) = (0.0, 1.0);
+    let mut rest = x.abs();
+    // A float has a finite continued fraction, so this always ends.
+    // The limit only guards against rounding errors in `rest`.
+    for _ in 0..64 {
+        let term = rest.floor();
+        let next_denominator = term * denominator + previous_denominator;
+        if next_denominator > max_denominator {
+            // The next convergent's denominator is too large, but a smaller
+            // term (a semiconvergent) may still be closer than the last convergent.
+            let term = ((max_denominator - previous_denominator) / denominator).floor();
+            let semi_numerator = term * numerator + previous_numerator;
+            let semi_denominator = term * denominator + previous_denominator;
+            if (semi_numerator / semi_denominator - x.abs()).abs()
+                < (numerator / denominator - x.abs()).abs()
+            {
+                (numerator, denominator) = (semi_numerator, semi_denominator);
+            }
+            break;
+        }
+
+        (numerator, previous_numerator) = (term * numerator + previous_numerator, numerator);
+        (denominator, previous_denominator) = (next_denominator, denominator);
+        if numerator / denominator == x.abs() {
+            break;
+        }
+        rest = 1.0 / (rest - term);
+    }
+
+    (numerator.copysign(x), denominator)
+}
+
+/// Whether `a` and `b` are at most `epsilon` apart, as `1` (true) or `0` (false).
+/// Floating point results are rarely exact, so `0.1 + 0.2 == 0.3` is false,
+/// but `approx(0.1 + 0.2, 0.3, 10^-9)` is true.
+fn approx(a: f64, b: f64, epsilon: f64, span: Span) -> Result<f64, RuntimeError> {
+    // `NaN` isn't non-negative either
+    if epsilon < 0.0 || epsilon.is_nan() {
+        return Err(RuntimeError::ExpectedNonNegative(span));
+    }
+
+    Ok(from_bool((a - b).abs() <= epsilon))
+}
+
+/// Whether `a` and `b` are equal, up to a tolerance relative to the larger one.
+/// So with a tolerance of `10^-9`, `0.1 + 0.2` is nearly equal to `0.3`,
+/// but `10^-20` isn't nearly equal to `0`.
+fn nearly_equal(a: f64, b: f64, tolerance: f64) -> bool {
+    // Equal infinities are equal, even though their difference is `NaN`
+    a == b || (a - b).abs() <= tolerance * a.abs().max(b.abs())
+}
+
+/// Whether `n` is a prime number, as `1` (true) or `0` (false), using trial division.
+/// `0` and `1` aren't prime.
+fn is_prime(n: f64, span: Span) -> Result<f64, RuntimeError> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedNonNegativeInteger(span));
+    }
+    if n < 2.0 {
+        return Ok(from_bool(false));
+    }
+    // Every float above `2^53` is even, so this stops right away for them
+    if n % 2.0 == 0.0 {
+        return Ok(from_bool(n == 2.0));
+    }
+
+    let mut divisor = 3.0;
+    while divisor * divisor <= n {
+        if n % divisor == 0.0 {
+            return Ok(from_bool(false));
+        }
+        divisor += 2.0;
+    }
+    Ok(from_bool(true))
+}
+
+/// Computes `1 / x`. Unlike the `/` operator, which gives infinity,
+/// the reciprocal of zero is an error.
+fn reciprocal(x: f64, span: Span) -> Result<f64, RuntimeError> {
+    if x == 0.0 {
+        return Err(RuntimeError::DivisionByZero(span));
+    }
+
+    Ok(1.0 / x)
+}
+
+/// Computes the square root of a non-negative number.
+/// Unlike `sqrt(x)`, which gives `NaN`, the root of a negative number is an error.
+fn square_root(x: f64, span: Span) -> Result<f64, RuntimeError> {
+    if x < 0.0 {
+        return Err(RuntimeError::ExpectedNonNegative(span));
+    }
+
+    Ok(x.sqrt())
+}
+
+/// Raises `base` to the power of `exponent`, checking for overflow.
+///
+/// Powers of zero follow these conventions, instead of relying on `powf`:
+/// * `0^0` is `1`, like in IEEE 754.
+/// * `0^x` is `0` for any positive `x`, like `0^2` and `0^0.5`.
+/// * `0^x` for a negative `x`, like `0^-1`, is `1 / 0^-x`, so it's a division by zero.
+fn power(
+    base: f64,
+    exponent: f64,
+    overflow: OverflowPolicy,
+    span: Span,
+) -> Result<f64, RuntimeError> {
+    if base == 0.0 {
+        return match exponent {
+            0.0 => Ok(1.0),
+            exponent if exponent < 0.0 => Err(RuntimeError::DivisionByZero(span)),
+            _ => Ok(base.powf(exponent)),
+        };
+    }
+    // `|base|^exponent` is `2^(exponent * log2(|base|))`, so we can know
+    // whether the result overflows before computing it. Infinite operands
+    // give infinite results on purpose, so they don't overflow.
+    if overflow == OverflowPolicy::Error
+        && base.is_finite()
+        && exponent.is_finite()
+        && exponent * base.abs().log2() >= MAX_EXPONENT
+    {
+        return Err(RuntimeError::Overflow(span));
+    }
+
+    Ok(base.powf(exponent))
+}
+
+/// Computes the factorial of a non-negative integer. It's infinite if it
+/// overflows, unless the overflow policy makes that an error.
+fn factorial(n: f64, overflow: OverflowPolicy, span: Span) -> Result<f64, RuntimeError> {
+    // The factorial of infinity is infinite, without overflowing
+    if n == f64::INFINITY {
+        return Ok(n);
+    }
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedNonNegativeInteger(span));
+    }
+    // Check the threshold first, so huge factorials don't loop for a long time.
+    if n > MAX_FACTORIAL {
+        return match overflow {
+            OverflowPolicy::Error => Err(RuntimeError::Overflow(span)),
+            OverflowPolicy::Allow | OverflowPolicy::Warn => Ok(f64::INFINITY),
+        };
+    }
+
+    Ok((2..=n as u32).map(f64::from).product())
+}
+
+/// The number of ways to choose `k` items out of `n`, ignoring their order.
+/// This is `n! / (k! * (n - k)!)`, but it's computed one factor at a time,
+/// so `choose(200, 2)` doesn't overflow even though `200!` does.
+fn choose(n: f64, k: f64, span: Span) -> Result<f64, RuntimeError> {
+    if n < 0.0 || n.fract() != 0.0 || k < 0.0 || k.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedNonNegativeInteger(span));
+    }
+    // There is no way to choose more items than there are
+    if k > n {
+        return Ok(0.0);
+    }
+
+    // Choosing `k` items is the same as choosing the `n - k` others to leave out
+    let k = k.min(n - k);
+    let mut result = 1.0;
+    let mut i = 1.0;
+    while i <= k {
+        // After this step, `result` is `choose(n - k + i, i)`, which is an integer
+        result = result * (n - k + i) / i;
+        if !result.is_finite() {
+            return Err(RuntimeError::Overflow(span));
+        }
+        i += 1.0;
+    }
+    Ok(result)
+}
+
+/// The number of ways to arrange `k` items out of `n`, in order.
+/// This is `n! / (n - k)!`, computed one factor at a time like `choose`.
+fn permute(n: f64, k: f64, span: Span) -> Result<f64, RuntimeError> {
+    if n < 0.0 || n.fract() != 0.0 || k < 0.0 || k.fract() != 0.0 {
+        return Err(RuntimeError::ExpectedNonNegativeInteger(span));
+    }
+    if k > n {
+        return Ok(0.0);
+    }
+
+    let mut result = 1.0;
+    let mut i = 0.0;
+    while i < k {
+        result *= n - i;
+        if !result.is_finite() {
+            return Err(RuntimeError::Overflow(span));
+        }
+        i += 1.0;
+    }
+    Ok(result)
+}
+
+/// Rounds a value to the given number of decimals, if there are some.
+/// Halves are rounded away from zero, like `0.125` to `0.13`.
+fn round_to(value: f64, decimals: Option<usize>) -> f64 {
+    match decimals {
+        Some(decimals) => round_decimals(value, decimals.try_into().unwrap_or(i32::MAX)),
+        None => value,
+    }
+}
+
+/// Rounds a value to a nearby "nice" number, 1, 2 or 5 times a power of 10,
+/// like the labels of a chart axis. The value is written `m * 10^e` with `m`
+/// from 1 to 10, then `m` becomes 1 below 1.5, 2 below 3, 5 below 7, and 10
+/// from 7 on, like in Heckbert's nice numbers. So `nice(23)` is 20, `nice(0.047)`
+/// is 0.05 and `nice(8)` is 10. The sign is kept, and zero, infinities and
+/// `NaN` are left as they are.
+fn nice(x: f64) -> f64 {
+    if x == 0.0 || !x.is_finite() {
+        return x;
+    }
+    let exponent = x.abs().log10().floor() as i32;
+    // Dividing by an exact power of 10 keeps `0.05` from being `0.05000000000000001`
+    let scale = 10f64.powi(exponent.abs());
+    let mantissa = match exponent {
+        0.. => x.abs() / scale,
+        _ => x.abs() * scale,
+    };
+    let mantissa = match mantissa {
+        m if m < 1.5 => 1.0,
+        m if m < 3.0 => 2.0,
+        m if m < 7.0 => 5.0,
+        _ => 10.0,
+    };
+    let nice = match exponent {
+        0.. => mantissa * scale,
+        _ => mantissa / scale,
+    };
+    nice.copysign(x)
+}
+
+/// Rounds a value to the given number of decimals, or to tens, hundreds and
+/// so on if it's negative. Halves are rounded away from zero.
+fn round_decimals(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals.saturating_abs());
+    let rounded = if decimals >= 0 {
+        (value * scale).round() / scale
+    } else {
+        (value / scale).round() * scale
+    };
+    match rounded {
+        rounded if rounded.is_finite() => rounded,
+        // Values too large to be scaled already have no decimals
+        _ if decimals >= 0 => value,
+        // Every finite value is closer to zero than to the infinite scale
+        _ => 0.0 * value.signum(),
+    }
+}
+
+/// Truncates a value toward zero to the given number of decimals, or to tens,
+/// hundreds and so on if it's negative, like `round_decimals` but without
+/// rounding up: `truncate_to(1.99, 1)` is 1.9 where rounding would give 2.
+/// Zero, infinities and `NaN` are left as they are.
+fn truncate_decimals(value: f64, decimals: i32) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    // The digits of the shortest decimal form, like `2.3e0`, since scaling
+    // `2.3` by 100 gives `229.99999999999997`, which would truncate to `2.29`
+    let scientific = format!("{:e}", value.abs());
+    let Some((mantissa, exponent)) = scientific.split_once('e') else {
+        return value;
+    };
+    let Ok(exponent) = exponent.parse::<i32>() else {
+        return value;
+    };
+    // The first digit is the one of `10^exponent`, so the last decimal
+    // kept is digit number `exponent + 1 + decimals`
+    let digits = decimals.saturating_add(exponent + 1);
+    if digits <= 0 {
+        return 0.0 * value.signum();
+    }
+    let kept = mantissa
+        .chars()
+        .filter(char::is_ascii_digit)
+        .take(digits as usize)
+        .collect::<String>();
+    let exponent = exponent - (kept.len() as i32 - 1);
+    match format!("{kept}e{exponent}").parse::<f64>() {
+        Ok(truncated) => truncated.copysign(value),
+        Err(_) => value,
+    }
+}
+
+/// Converts a boolean to a number, `1` being true and `0` being false.
+pub(crate) fn from_bool(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Converts a number to a boolean. Any nonzero number is true.
+pub(crate) fn to_bool(value: f64) -> bool {
+    value != 0.0
+}
+
+/// Converts a complex number to a boolean. Any nonzero number is true.
+fn complex_to_bool(value: Complex) -> bool {
+    value != Complex::default()
+}
+
+/// Tests for the runtime.
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "bigint")]
+    use crate::runtime::evaluate_bigint;
+    use crate::{
+        parser::{ParseTree, Parser},
+        runtime::{
+            evaluate, evaluate_complex, evaluate_fixed_point, evaluate_format, evaluate_memoized,
+            evaluate_radix, evaluate_table, evaluate_template, evaluate_with, evaluate_with_budget,
+            fraction, Environment, IntegerDivision, Modes, OverflowPolicy, RuntimeError,
+            APPROX_TOLERANCE, FUNCTIONS, MAX_DENOMINATOR,
+        },
+        tokenizer::Span,
+    };
+
+    /// Parses and evaluates an input expression in the given environment.
+    fn eval_in(input: &str, env: &mut Environment) -> Result<f64, RuntimeError> {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => evaluate(expr, env),
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    /// Parses and evaluates an input expression in a new environment.
+    fn eval(input: &str) -> f64 {
+        eval_in(input, &mut Environment::default()).unwrap()
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert_eq!(1.0, eval("1 < 2"));
+        assert_eq!(0.0, eval("1 >= 2"));
+        assert_eq!(1.0, eval("2 == 2"));
+        assert_eq!(1.0, eval("2 != 3"));
+    }
+
+    #[test]
+    fn test_signs() {
+        assert_eq!(5.0, eval("3 - -2"));
+        assert_eq!(5.0, eval("3--2"));
+        assert_eq!(1.0, eval("3 + -2"));
+        assert_eq!(1.0, eval("3 - - - 2"));
+        assert_eq!(-6.0, eval("3 * -2"));
+        assert_eq!(-1.5, eval("3 / -2"));
+        assert_eq!(6.0, eval("-3 * -2"));
+        assert_eq!(-5.0, eval("-3 - 2"));
+        assert_eq!(3.0, eval("--3"));
+        // `-3^2` is `-(3^2)`, like in mathematics.
+        assert_eq!(-9.0, eval("-3^2"));
+        assert_eq!(9.0, eval("(-3)^2"));
+        assert_eq!(-8.0, eval("-2^3"));
+        assert_eq!(0.25, eval("2^-2"));
+        assert_eq!(-0.25, eval("-2^-2"));
+        assert_eq!(1.0 / 512.0, eval("2^-3^2"));
+        assert_eq!(-9.0, eval("0 - 3^2"));
+        assert_eq!(-6.0, eval("-3!"));
+    }
+
+    #[test]
+    fn test_comparison_precedence() {
+        assert_eq!(1.0, eval("1 + 1 == 2"));
+        assert_eq!(0.0, eval("2 * 3 < 5"));
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        assert_eq!(1.0, eval("1 < 2 < 3"));
+        assert_eq!(0.0, eval("3 > 2 > 5"));
+        assert_eq!(1.0, eval("1 < 2 <= 2 < 4"));
+    }
+
+    #[test]
+    fn test_chained_comparison_evaluates_operands_once() {
+        let mut env = Environment::default();
+        env.set("x".to_string(), 0.0);
+        assert_eq!(Ok(1.0), eval_in("0 < (x += 1) < 10", &mut env));
+        assert_eq!(Some(1.0), env.get("x"));
+        // Every operand is evaluated, even after a comparison is false.
+        assert_eq!(Ok(0.0), eval_in("2 < 1 < (x += 1)", &mut env));
+        assert_eq!(Some(2.0), env.get("x"));
+    }
+
+    #[test]
+    fn test_parenthesized_comparison_is_not_chained() {
+        // `(1 < 3)` is `1`, and `1 < 2`. Chaining would give `3 < 2` instead.
+        assert_eq!(1.0, eval("(1 < 3) < 2"));
+    }
+
+    #[test]
+    fn test_logical() {
+        assert_eq!(0.0, eval("1 and 0"));
+        assert_eq!(1.0, eval("0 or 1"));
+        assert_eq!(1.0, eval("not 0"));
+        assert_eq!(0.0, eval("not 5"));
+        // Any nonzero number is true.
+        assert_eq!(1.0, eval("-2 and 0.5"));
+    }
+
+    #[test]
+    fn test_logical_evaluates_both_operands() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(0.0), eval_in("0 and (x = 5)", &mut env));
+        assert_eq!(Some(5.0), env.get("x"));
+        assert_eq!(Ok(1.0), eval_in("1 or (y = 6)", &mut env));
+        assert_eq!(Some(6.0), env.get("y"));
+    }
+
+    #[test]
+    fn test_logical_precedence() {
+        // `and` binds tighter than `or`.
+        assert_eq!(1.0, eval("1 or 0 and 0"));
+        // Both bind looser than comparisons.
+        assert_eq!(1.0, eval("1 < 2 and 3 < 4"));
+        // `not` binds looser than comparisons too.
+        assert_eq!(1.0, eval("not 1 == 2"));
+    }
+
+    #[test]
+    fn test_assignment() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(3.0), eval_in("x = 3", &mut env));
+        assert_eq!(Ok(6.0), eval_in("x * 2", &mut env));
+        // Assignments are right-associative.
+        assert_eq!(Ok(1.0), eval_in("x = y = 1", &mut env));
+        assert_eq!(Ok(2.0), eval_in("x + y", &mut env));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let mut env = Environment::default();
+        eval_in("x = 3", &mut env).unwrap();
+        assert_eq!(Ok(12.0), eval_in("x *= 4", &mut env));
+        assert_eq!(Ok(12.0), eval_in("x", &mut env));
+        assert_eq!(Ok(14.0), eval_in("x += 2", &mut env));
+        assert_eq!(Ok(10.0), eval_in("x -= 4", &mut env));
+        assert_eq!(Ok(5.0), eval_in("x /= 2", &mut env));
+    }
+
+    #[test]
+    fn test_compound_assignment_undefined() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::UnknownVariable(
+                "y".to_string(),
+                (0..1).into()
+            )),
+            eval_in("y += 1", &mut env)
+        );
+        // The failed assignment must not create the variable.
+        assert!(env.get("y").is_none());
+    }
+
+    #[test]
+    fn test_constant() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(9.81), eval_in("const g = 9.81", &mut env));
+        assert_eq!(Ok(19.62), eval_in("2 * g", &mut env));
+        assert!(env.is_constant("g"));
+        assert_eq!(
+            Err(RuntimeError::ConstantReassignment(
+                "g".to_string(),
+                (0..6).into()
+            )),
+            eval_in("g = 10", &mut env)
+        );
+        assert!(matches!(
+            eval_in("g += 1", &mut env),
+            Err(RuntimeError::ConstantReassignment(_, _))
+        ));
+        assert!(matches!(
+            eval_in("const g = 1", &mut env),
+            Err(RuntimeError::ConstantReassignment(_, _))
+        ));
+        // The failed assignments leave the constant and the other variables untouched.
+        assert!(eval_in("g = (x = 1)", &mut env).is_err());
+        assert_eq!(Some(9.81), env.get("g"));
+        assert!(env.get("x").is_none());
+    }
+
+    #[test]
+    fn test_variable_reassignment() {
+        let mut env = Environment::default();
+        eval_in("v = 1", &mut env).unwrap();
+        assert_eq!(Ok(2.0), eval_in("v = 2", &mut env));
+        assert!(!env.is_constant("v"));
+        // A variable can become a constant.
+        eval_in("const v = 3", &mut env).unwrap();
+        assert!(env.is_constant("v"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut env = Environment::default();
+        // Slots that were never stored to are 0.
+        assert_eq!(Ok(0.0), eval_in("M9", &mut env));
+        env.store(1, 2.5);
+        env.store(9, 4.0);
+        assert_eq!(Ok(6.5), eval_in("M1 + M9", &mut env));
+        assert_eq!(Ok(2.0), eval_in("sqrt(M9)", &mut env));
+        assert!(matches!(
+            eval_in("M0 + M10", &mut env),
+            Err(RuntimeError::UnknownVariable(name, _)) if name == "M0"
+        ));
+        // A variable with the same name hides the slot.
+        assert_eq!(Ok(7.0), eval_in("M1 = 7", &mut env));
+        assert_eq!(Ok(7.0), eval_in("M1", &mut env));
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        assert!(Parser::new("1 + x = 3").parse().is_err());
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(3.0, eval("3 <? 5"));
+        assert_eq!(5.0, eval("3 >? 5"));
+        assert_eq!(-1.0, eval("-1 <? 2 <? 0"));
+        // Arithmetic binds tighter, and comparisons bind looser.
+        assert_eq!(4.0, eval("1 + 2 >? 4"));
+        assert_eq!(1.0, eval("3 <? 5 < 4"));
+    }
+
+    #[test]
+    fn test_exponentiation() {
+        assert_eq!(1024.0, eval("2^10"));
+        assert_eq!(0.5, eval("2^-1"));
+        // Exponentiation is right-associative.
+        assert_eq!(512.0, eval("2^3^2"));
+        // Exponentiation binds tighter than multiplication.
+        assert_eq!(18.0, eval("2 * 3^2"));
+    }
+
+    #[test]
+    fn test_exponentiation_overflow() {
+        use OverflowPolicy::{Error, Warn};
+        let mut env = Environment::default();
+        assert!(matches!(
+            eval_overflow("2^1000000", &mut env, Error),
+            Err(RuntimeError::Overflow(_))
+        ));
+        assert!(matches!(
+            eval_overflow("2^1024", &mut env, Error),
+            Err(RuntimeError::Overflow(_))
+        ));
+        assert_eq!(
+            Ok(2f64.powi(1023)),
+            eval_overflow("2^1023", &mut env, Error)
+        );
+        // Otherwise, the power is infinite.
+        assert_eq!(Ok(f64::INFINITY), eval_in("2^1024", &mut env));
+        assert!(!env.take_overflow());
+        assert_eq!(Ok(f64::INFINITY), eval_overflow("2^1024", &mut env, Warn));
+        assert!(env.take_overflow());
+        // Powers of infinity are infinite on purpose, they don't overflow.
+        assert_eq!(Ok(f64::INFINITY), eval_overflow("(1/0)^2", &mut env, Error));
+    }
+
+    #[test]
+    fn test_powers_of_zero() {
+        assert_eq!(1.0, eval("0^0"));
+        assert_eq!(0.0, eval("0^2"));
+        assert_eq!(0.0, eval("0^0.5"));
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::DivisionByZero((0..4).into())),
+            eval_in("0^-1", &mut env)
+        );
+        assert!(matches!(
+            eval_in("0^-0.5", &mut env),
+            Err(RuntimeError::DivisionByZero(_))
+        ));
+        // `NaN` stays `NaN`.
+        assert!(eval("0^(0/0)").is_nan());
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(1.0, eval("0!"));
+        assert_eq!(120.0, eval("5!"));
+        // Factorial binds tighter than negation.
+        assert_eq!(-6.0, eval("-3!"));
+        assert_eq!(64.0, eval("2^3!"));
+    }
+
+    #[test]
+    fn test_factorial_threshold() {
+        // `170!` is the largest finite factorial.
+        let largest = eval("170!");
+        assert!(largest.is_finite());
+        assert_eq!(largest, eval("169!") * 170.0);
+
+        use OverflowPolicy::{Error, Warn};
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::Overflow((0..4).into())),
+            eval_overflow("171!", &mut env, Error)
+        );
+        assert!(matches!(
+            eval_overflow("1000000!", &mut env, Error),
+            Err(RuntimeError::Overflow(_))
+        ));
+        // Otherwise, the factorial is infinite, without computing it.
+        assert_eq!(Ok(f64::INFINITY), eval_in("1000000!", &mut env));
+        assert!(!env.take_overflow());
+        assert_eq!(Ok(f64::INFINITY), eval_overflow("171!", &mut env, Warn));
+        assert!(env.take_overflow());
+        // The factorial of infinity is infinite on purpose, it doesn't overflow.
+        assert_eq!(Ok(f64::INFINITY), eval_overflow("(1/0)!", &mut env, Error));
+    }
+
+    #[test]
+    fn test_factorial_domain() {
+        let mut env = Environment::default();
+        assert!(matches!(
+            eval_in("2.5!", &mut env),
+            Err(RuntimeError::ExpectedNonNegativeInteger(_))
+        ));
+        assert!(matches!(
+            eval_in("(-1)!", &mut env),
+            Err(RuntimeError::ExpectedNonNegativeInteger(_))
+        ));
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(0.1, eval("10%"));
+        assert_eq!(220.0, eval("200 + 10%"));
+        assert_eq!(180.0, eval("200 - 10%"));
+        // Each percentage is relative to everything on its left.
+        assert_eq!(231.0, eval("200 + 10% + 5%"));
+        // Outside of additions and subtractions, it's just a division by 100.
+        assert_eq!(20.0, eval("200 * 10%"));
+        assert_eq!(2.5, eval("2 + 50% * 1"));
+        // Percent binds tighter than `+`, so the whole sum is the base.
+        assert_eq!(110.0, eval("50 + 50 + 10%"));
+    }
+
+    #[test]
+    fn test_reciprocal() {
+        assert_eq!(0.25, eval("inv(4)"));
+        assert_eq!(-2.0, eval("reciprocal(-0.5)"));
+        assert_eq!(5.0, eval("inv(inv(5))"));
+    }
+
+    #[test]
+    fn test_reciprocal_of_zero() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::DivisionByZero((0..6).into())),
+            eval_in("inv(0)", &mut env)
+        );
+        assert!(matches!(
+            eval_in("1 + inv(-0)", &mut env),
+            Err(RuntimeError::DivisionByZero(_))
+        ));
+    }
+
+    #[test]
+    fn test_functions_exist() {
+        for name in FUNCTIONS {
+            assert!(!matches!(
+                eval_in(&format!("{name}(1)"), &mut Environment::default()),
+                Err(RuntimeError::UnknownFunction(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_function_without_call() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::ExpectedFunctionCall(
+                "sqrt".to_string(),
+                (4..8).into()
+            )),
+            eval_in("max(sqrt, 1)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::UnknownVariable(
+                "foo".to_string(),
+                (0..3).into()
+            )),
+            eval_in("foo", &mut env)
+        );
+        // Function names can still be variables.
+        for (input, expected) in [("hex = 3", 3.0), ("sum = 3", 3.0), ("round = 2", 2.0)] {
+            assert_eq!(Ok(expected), eval_in(input, &mut env));
+        }
+        assert_eq!(Ok(8.0), eval_in("hex + sum + round", &mut env));
+        assert_eq!(Ok(4.0), eval_in("sqrt(16)", &mut env));
+    }
+
+    #[test]
+    fn test_nice() {
+        assert_eq!(20.0, eval("nice(23)"));
+        assert_eq!(0.05, eval("nice(0.047)"));
+        assert_eq!(1.0, eval("nice(1.49)"));
+        assert_eq!(2.0, eval("nice(1.5)"));
+        assert_eq!(5.0, eval("nice(3)"));
+        assert_eq!(10.0, eval("nice(7)"));
+        assert_eq!(1000.0, eval("nice(1000)"));
+        assert_eq!(200000.0, eval("nice(249999)"));
+        assert_eq!(0.001, eval("nice(0.0012)"));
+        assert_eq!(-500.0, eval("nice(-480)"));
+        assert_eq!(0.0, eval("nice(0)"));
+        assert_eq!(f64::INFINITY, eval("nice(1/0)"));
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!("3.14", eval("round(3.14159, 2)").to_string());
+        assert_eq!(12300.0, eval("round(12345, -2)"));
+        // Halves are rounded away from zero.
+        assert_eq!(3.0, eval("round(2.5, 0)"));
+        assert_eq!(-3.0, eval("round(-2.5)"));
+        assert_eq!(0.13, eval("round(0.125, 2)"));
+        // Scales too large for a float don't overflow.
+        assert_eq!(1e300, eval("round(10^300, 20)"));
+        assert_eq!(0.1, eval("round(0.1, 400)"));
+        assert_eq!(0.0, eval("round(12345, -400)"));
+        assert_eq!(
+            Err(RuntimeError::ExpectedInteger((0..13).into())),
+            eval_in("round(1, 0.5)", &mut Environment::default())
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 3, (0..14).into())),
+            eval_in("round(1, 2, 3)", &mut Environment::default())
+        );
+    }
+
+    #[test]
+    fn test_truncate_to() {
+        // Truncating never rounds up, unlike `round`.
+        assert_eq!(1.9, eval("truncate_to(1.99, 1)"));
+        assert_eq!(2.0, eval("round(1.99, 1)"));
+        assert_eq!(1.0, eval("truncate_to(1.99, 0)"));
+        assert_eq!(0.0045, eval("truncate_to(0.0045678, 4)"));
+        // Negative decimals truncate to tens, hundreds and so on.
+        assert_eq!(12300.0, eval("truncate_to(12399, -2)"));
+        assert_eq!(12400.0, eval("round(12399, -2)"));
+        assert_eq!(0.0, eval("truncate_to(99, -2)"));
+        // Negative values are truncated toward zero.
+        assert_eq!(-1.9, eval("truncate_to(-1.99, 1)"));
+        assert_eq!(-2.0, eval("round(-1.99, 1)"));
+        assert!(eval("truncate_to(-0.5, 0)").is_sign_negative());
+        // Decimals that aren't exact floats aren't truncated below themselves.
+        assert_eq!(2.3, eval("truncate_to(2.3, 2)"));
+        assert_eq!(0.29, eval("truncate_to(0.29, 2)"));
+        assert_eq!(1.5, eval("truncate_to(1.5, 20)"));
+        // Scales too large for a float don't overflow, like with `round`.
+        assert_eq!(0.1, eval("truncate_to(0.1, 400)"));
+        assert_eq!(0.0, eval("truncate_to(12345, -400)"));
+        assert_eq!(0.0, eval("truncate_to(0, 2)"));
+        assert!(eval("truncate_to(-0, 2)").is_sign_negative());
+        assert_eq!(f64::INFINITY, eval("truncate_to(1/0, 2)"));
+        assert_eq!(
+            Err(RuntimeError::ExpectedInteger((0..19).into())),
+            eval_in("truncate_to(1, 1.5)", &mut Environment::default())
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 1, (0..14).into())),
+            eval_in("truncate_to(1)", &mut Environment::default())
+        );
+    }
+
+    #[test]
+    fn test_variable_call() {
+        let mut env = Environment::default();
+        env.set("x".to_string(), 3.0);
+        // A variable followed by parentheses is a multiplication.
+        assert_eq!(Ok(6.0), eval_in("x(2)", &mut env));
+        assert_eq!(Ok(21.0), eval_in("x(2 + 5)", &mut env));
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(1, 2, (0..7).into())),
+            eval_in("x(1, 2)", &mut env)
+        );
+        // A function is called, even if there is a variable with its name.
+        env.set("inv".to_string(), 5.0);
+        assert_eq!(Ok(0.5), eval_in("inv(2)", &mut env));
+        // A name that is neither is an error.
+        assert_eq!(
+            Err(RuntimeError::UnknownFunction(
+                "y".to_string(),
+                (0..4).into()
+            )),
+            eval_in("y(2)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_combinatorics() {
+        assert_eq!(10.0, eval("choose(5, 2)"));
+        assert_eq!(20.0, eval("permute(5, 2)"));
+        assert_eq!(1.0, eval("choose(0, 0)"));
+        assert_eq!(1.0, eval("permute(0, 0)"));
+        assert_eq!(0.0, eval("choose(2, 5)"));
+        assert_eq!(0.0, eval("permute(2, 5)"));
+        assert_eq!(120.0, eval("factorial(5)"));
+        assert_eq!(eval("5!"), eval("factorial(5)"));
+        assert_eq!(eval("5! / 2!"), eval("permute(5, 3)"));
+        // The intermediate factorials would overflow, but the result doesn't.
+        assert_eq!(19900.0, eval("choose(200, 2)"));
+        assert_eq!(19900.0, eval("choose(200, 198)"));
+        assert_eq!(39800.0, eval("permute(200, 2)"));
+    }
+
+    #[test]
+    fn test_combinatorics_errors() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegativeInteger((0..14).into())),
+            eval_in("choose(5.5, 2)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegativeInteger((0..14).into())),
+            eval_in("permute(5, -1)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::Overflow((0..19).into())),
+            eval_in("permute(2000, 1000)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::Overflow((0..18).into())),
+            eval_in("choose(2000, 1000)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 1, (0..9).into())),
+            eval_in("choose(5)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::Overflow((0..14).into())),
+            eval_overflow("factorial(171)", &mut env, OverflowPolicy::Error)
+        );
+    }
+
+    #[test]
+    fn test_is_prime() {
+        for prime in [2, 3, 5, 7, 11, 13, 17, 97, 7919] {
+            assert_eq!(1.0, eval(&format!("is_prime({prime})")));
+        }
+        for composite in [4, 6, 9, 15, 18, 25, 91, 7917] {
+            assert_eq!(0.0, eval(&format!("is_prime({composite})")));
+        }
+        assert_eq!(0.0, eval("is_prime(0)"));
+        assert_eq!(0.0, eval("is_prime(1)"));
+        // `2^61 - 1` is a Mersenne prime, but it isn't exact as a float.
+        assert_eq!(0.0, eval("is_prime(2^61 - 1)"));
+        assert_eq!(1.0, eval("is_prime(2^31 - 1)"));
+
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegativeInteger((0..13).into())),
+            eval_in("is_prime(2.5)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegativeInteger((0..12).into())),
+            eval_in("is_prime(-7)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(1, 2, (0..9).into())),
+            eval_in("inv(1, 2)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(1, 0, (0..5).into())),
+            eval_in("inv()", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::UnknownFunction(
+                "foo".to_string(),
+                (0..6).into()
+            )),
+            eval_in("foo(1)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(3.0, eval("sqrt(9)"));
+        assert!(eval("sqrt(-1)").is_nan());
+    }
+
+    #[test]
+    fn test_cbrt() {
+        assert_eq!(3.0, eval("cbrt(27)"));
+        assert_eq!(-2.0, eval("cbrt(-8)"));
+        assert_eq!(0.5, eval("cbrt(0.125)"));
+        // The `^` approach doesn't work for negative numbers.
+        assert!(eval("(-8)^(1/3)").is_nan());
+        assert_eq!(eval("27^(1/3)"), eval("cbrt(27)"));
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        // The sign of zero is kept, so it's observable in the bits.
+        assert_eq!((-0.0f64).to_bits(), eval("-0").to_bits());
+        assert_eq!((-0.0f64).to_bits(), eval("0 * -1").to_bits());
+        assert_eq!(0.0f64.to_bits(), eval("-0 + 0").to_bits());
+        assert_eq!(f64::NEG_INFINITY, eval("1 / -0"));
+        // But it's still equal to zero.
+        assert_eq!(1.0, eval("-0 == 0"));
+        assert_eq!(0.0, eval("-0 < 0"));
+    }
+
+    #[test]
+    fn test_hyperbolic_functions() {
+        assert_eq!(0.0, eval("sinh(0)"));
+        assert_eq!(1.0, eval("cosh(0)"));
+        assert_eq!(0.0, eval("tanh(0)"));
+        assert_eq!(0.0, eval("acosh(1)"));
+        assert!((eval("sinh(1)") - 1.1752011936438014).abs() < 1e-15);
+        assert!((eval("tanh(20)") - 1.0).abs() < 1e-15);
+        // The inverses undo the functions, up to rounding.
+        for x in [-2.0, -0.5, 0.25, 1.5] {
+            assert!((eval(&format!("asinh(sinh({x}))")) - x).abs() < 1e-12);
+            assert!((eval(&format!("atanh(tanh({x}))")) - x).abs() < 1e-12);
+            let x = f64::abs(x);
+            assert!((eval(&format!("acosh(cosh({x}))")) - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_hyperbolic_domain_errors() {
+        assert_eq!(
+            Err(RuntimeError::ExpectedAtLeastOne((0..11).into())),
+            eval_in("acosh(0.99)", &mut Environment::default())
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedBetweenMinusOneAndOne((0..8).into())),
+            eval_in("atanh(1)", &mut Environment::default())
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedBetweenMinusOneAndOne((0..9).into())),
+            eval_in("atanh(-1)", &mut Environment::default())
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedBetweenMinusOneAndOne((0..10).into())),
+            eval_in("atanh(1/0)", &mut Environment::default())
+        );
+    }
+
+    #[test]
+    fn test_inverse_trigonometric_functions() {
+        use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+        assert_eq!(FRAC_PI_2, eval("asin(1)"));
+        assert_eq!(-FRAC_PI_2, eval("asin(-1)"));
+        assert_eq!(PI, eval("acos(-1)"));
+        assert_eq!(0.0, eval("acos(1)"));
+        assert_eq!(FRAC_PI_4, eval("atan(1)"));
+        assert_eq!(FRAC_PI_4, eval("atan2(1, 1)"));
+        // Unlike `atan(y / x)`, `atan2` knows which quadrant the point is in.
+        assert_eq!(-3.0 * FRAC_PI_4, eval("atan2(-1, -1)"));
+        assert_eq!(FRAC_PI_2, eval("atan2(1, 0)"));
+        assert!((eval("degrees(asin(0.5))") - 30.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_inverse_trigonometric_errors() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::ExpectedFromMinusOneToOne((0..7).into())),
+            eval_in("asin(2)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedFromMinusOneToOne((0..12).into())),
+            eval_in("acos(-1.001)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 1, (0..8).into())),
+            eval_in("atan2(1)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_percentages() {
+        assert_eq!(20.0, eval("percent_of(200, 10)"));
+        assert_eq!(220.0, eval("add_pct(200, 10)"));
+        assert_eq!(300.0, eval("percent_of(200, 150)"));
+        assert_eq!(0.0, eval("add_pct(0, 10)"));
+        // Negative rates are discounts.
+        assert_eq!(-20.0, eval("percent_of(200, -10)"));
+        assert_eq!(180.0, eval("add_pct(200, -10)"));
+        assert_eq!(0.0, eval("add_pct(200, -100)"));
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 1, (0..12).into())),
+            eval_in("add_pct(200)", &mut Environment::default())
+        );
+    }
+
+    /// Parses an input and makes its table in the given environment.
+    fn table_in(input: &str, env: &mut Environment) -> Result<Vec<(f64, f64)>, RuntimeError> {
+        let Ok(ParseTree::Expression(expr)) = Parser::new(input).parse() else {
+            panic!("`{input}` is not a valid expression");
+        };
+        let table = evaluate_table(&expr, env, Modes::default()).expect("not a table");
+        table.map(|table| table.rows)
+    }
+
+    #[test]
+    fn test_table() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Ok(vec![
+                (0.0, 1.0),
+                (1.0, 2.0),
+                (2.0, 5.0),
+                (3.0, 10.0),
+                (4.0, 17.0),
+                (5.0, 26.0)
+            ]),
+            table_in("table(x * x + 1, x, 0, 5, 1)", &mut env)
+        );
+        // Steps can go down, and the end is only included if it's reached.
+        assert_eq!(
+            Ok(vec![(1.0, -1.0), (0.5, -0.5), (0.0, -0.0)]),
+            table_in("table(-t, t, 1, -0.2, -0.5)", &mut env)
+        );
+        assert_eq!(
+            Ok(vec![(2.0, 4.0)]),
+            table_in("table(x + 2, x, 2, 2, 0)", &mut env)
+        );
+        // Rounding errors don't drop the last row.
+        assert_eq!(
+            4,
+            table_in("table(x, x, 0, 0.3, 0.1)", &mut env)
+                .unwrap()
+                .len()
+        );
+        // The variable gets its value back afterwards.
+        env.set("x".to_string(), 7.0);
+        table_in("table(x, x, 0, 1, 1)", &mut env).unwrap();
+        assert_eq!(Some(7.0), env.get("x"));
+        assert_eq!(None, env.get("t"));
+    }
+
+    #[test]
+    fn test_table_errors() {
+        let mut env = Environment::default();
+        env.set_constant("pi".to_string(), std::f64::consts::PI);
+        let errors = [
+            (
+                "table(x, x, 0, 5, 0)",
+                RuntimeError::InvalidStep((18..19).into()),
+            ),
+            (
+                "table(x, x, 0, 5, -1)",
+                RuntimeError::InvalidStep((18..20).into()),
+            ),
+            (
+                "table(x, 2, 0, 5, 1)",
+                RuntimeError::ExpectedVariable((9..10).into()),
+            ),
+            (
+                "table(x, x, 0, 1/0, 1)",
+                RuntimeError::ExpectedFinite((15..18).into()),
+            ),
+            (
+                "table(x, x, 0, 1000, 1)",
+                RuntimeError::TooManyRows((0..23).into()),
+            ),
+            (
+                "table(pi, pi, 0, 5, 1)",
+                RuntimeError::ConstantReassignment("pi".to_string(), (10..12).into()),
+            ),
+            (
+                "table(x, x, 0, 5)",
+                RuntimeError::WrongArgumentCount(5, 4, (0..17).into()),
+            ),
+            (
+                "table(y, x, 0, 5, 1)",
+                RuntimeError::UnknownVariable("y".to_string(), (6..7).into()),
+            ),
+        ];
+        for (input, error) in errors {
+            assert_eq!(Err(error), table_in(input, &mut env), "{input}");
+        }
+        // The variable is removed even after an error.
+        assert_eq!(None, env.get("x"));
+        assert_eq!(
+            Err(RuntimeError::NestedTable((4..24).into())),
+            eval_in("2 * table(x, x, 0, 5, 1)", &mut env)
+        );
+    }
+
+    /// Parses and evaluates an input expression exactly, in a new environment.
+    #[cfg(feature = "bigint")]
+    fn eval_bigint(input: &str) -> Option<Result<String, RuntimeError>> {
+        let expr = Parser::new(input).parse_expression().unwrap();
+        evaluate_bigint(&expr, &Environment::default())
+            .map(|result| result.map(|value| value.to_string()))
+    }
+
+    #[test]
+    #[cfg(feature = "bigint")]
+    fn test_bigint() {
+        assert_eq!(
+            Some(Ok(
+                "93326215443944152681699238856266700490715968264381621468592963895217599993229915608941463976156518286253697920827223758251185210916864000000000000000000000000"
+                    .to_string()
+            )),
+            eval_bigint("100!")
+        );
+        assert_eq!(
+            Some(Ok("340282366920938463463374607431768211456".to_string())),
+            eval_bigint("2^128")
+        );
+        assert_eq!(
+            Some(Ok("-340282366920938463463374607431768211455".to_string())),
+            eval_bigint("1 - 2^128")
+        );
+        assert_eq!(Some(Ok("-8".to_string())), eval_bigint("(-2)^3"));
+        // Operations that aren't exact on integers aren't evaluated.
+        assert_eq!(None, eval_bigint("2^128 / 2"));
+        assert_eq!(None, eval_bigint("2^-1"));
+        assert_eq!(None, eval_bigint("sqrt(4)"));
+        assert_eq!(None, eval_bigint("1.5 * 2"));
+        assert_eq!(None, eval_bigint("x + 1"));
+    }
+
+    #[test]
+    #[cfg(feature = "bigint")]
+    fn test_bigint_limits() {
+        assert_eq!(
+            Some(Err(RuntimeError::TooManyDigits((0..8).into()))),
+            eval_bigint("2^100000")
+        );
+        assert_eq!(
+            Some(Err(RuntimeError::TooManyDigits((0..6).into()))),
+            eval_bigint("10000!")
+        );
+        assert_eq!(Some(Ok("1".to_string())), eval_bigint("1^1000000000"));
+    }
+
+    #[test]
+    fn test_sum() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(5050.0), eval_in("sum(i, i, 1, 100)", &mut env));
+        assert_eq!(Ok(14.0), eval_in("sum(i*i, i, 1, 3)", &mut env));
+        assert_eq!(Ok(0.0), eval_in("sum(i, i, 1, 0)", &mut env));
+        assert_eq!(Ok(-2.0), eval_in("sum(k, k, -2, 1)", &mut env));
+        // Sums can be nested, and used inside expressions.
+        assert_eq!(
+            Ok(11.0),
+            eval_in("1 + sum(sum(j, j, 1, i), i, 1, 3)", &mut env)
+        );
+        // The variable gets its value back afterwards.
+        env.set("i".to_string(), 7.0);
+        assert_eq!(Ok(6.0 + 7.0), eval_in("sum(i, i, 1, 3) + i", &mut env));
+        assert_eq!(Some(7.0), env.get("i"));
+        assert_eq!(None, env.get("k"));
+    }
+
+    #[test]
+    fn test_product() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(120.0), eval_in("product(i, i, 1, 5)", &mut env));
+        assert_eq!(Ok(1.0), eval_in("product(i, i, 1, 0)", &mut env));
+        assert_eq!(Ok(0.0), eval_in("product(k, k, -2, 1)", &mut env));
+        assert_eq!(Ok(1.0), eval_in("product(i, i, 1, 5) / 5!", &mut env));
+        assert_eq!(
+            Ok(6.0),
+            eval_in("sum(product(j, j, 1, i), i, 1, 3) - 3", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedInteger((17..20).into())),
+            eval_in("product(i, i, 1, 0.5)", &mut env)
+        );
+        assert_eq!(None, env.get("i"));
+    }
+
+    #[test]
+    fn test_iterate() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(2.0), eval_in("iterate(sqrt, 16, 2)", &mut env));
+        assert_eq!(Ok(16.0), eval_in("iterate(sqrt, 16, 0)", &mut env));
+        assert_eq!(Ok(0.5), eval_in("iterate(inv, 2, 3)", &mut env));
+        assert_eq!(
+            Ok(738.0),
+            eval_in("iterate(factorial, 3, 2) + 18", &mut env)
+        );
+        // Square roots converge to 1.
+        let converged = eval_in("iterate(sqrt, 1000, 60)", &mut env).unwrap();
+        assert!((converged - 1.0).abs() < 1e-15);
+        // The fixed point of `atan` is 0, reached slowly.
+        let converged = eval_in("iterate(atan, 1, 10000)", &mut env).unwrap();
+        assert!(converged.abs() < 0.02);
+    }
+
+    #[test]
+    fn test_iterate_errors() {
+        let mut env = Environment::default();
+        env.set("x".to_string(), 2.0);
+        let errors = [
+            (
+                "iterate(x, 1, 2)",
+                RuntimeError::ExpectedFunction((8..9).into()),
+            ),
+            (
+                "iterate(sum, 1, 2)",
+                RuntimeError::ExpectedFunction((8..11).into()),
+            ),
+            (
+                "iterate(fmt, 1, 2)",
+                RuntimeError::ExpectedFunction((8..11).into()),
+            ),
+            (
+                "iterate(2 + 2, 1, 2)",
+                RuntimeError::ExpectedFunction((8..13).into()),
+            ),
+            (
+                "iterate(sqrt, 1, 1.5)",
+                RuntimeError::ExpectedNonNegativeInteger((17..20).into()),
+            ),
+            (
+                "iterate(sqrt, 1, -1)",
+                RuntimeError::ExpectedNonNegativeInteger((17..19).into()),
+            ),
+            (
+                "iterate(sqrt, 1, 10^7)",
+                RuntimeError::TooManyIterations((17..21).into()),
+            ),
+            (
+                "iterate(sqrt, 1)",
+                RuntimeError::WrongArgumentCount(3, 2, (0..16).into()),
+            ),
+            (
+                "iterate(atan2, 1, 2)",
+                RuntimeError::WrongArgumentCount(2, 1, (0..20).into()),
+            ),
+        ];
+        for (input, error) in errors {
+            assert_eq!(Err(error), eval_in(input, &mut env), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_sum_errors() {
+        let mut env = Environment::default();
+        env.set_constant("pi".to_string(), std::f64::consts::PI);
+        let errors = [
+            (
+                "sum(i, i, 1, 2.5)",
+                RuntimeError::ExpectedInteger((13..16).into()),
+            ),
+            (
+                "sum(i, i, 1/0, 2)",
+                RuntimeError::ExpectedInteger((10..13).into()),
+            ),
+            (
+                "sum(i, 2, 1, 3)",
+                RuntimeError::ExpectedVariable((7..8).into()),
+            ),
+            (
+                "sum(i, i, 1, 2000000)",
+                RuntimeError::TooManyTerms((0..21).into()),
+            ),
+            (
+                "sum(pi, pi, 1, 3)",
+                RuntimeError::ConstantReassignment("pi".to_string(), (8..10).into()),
+            ),
+            (
+                "sum(i, i, 1)",
+                RuntimeError::WrongArgumentCount(4, 3, (0..12).into()),
+            ),
+            (
+                "sum(y, i, 1, 3)",
+                RuntimeError::UnknownVariable("y".to_string(), (4..5).into()),
+            ),
+        ];
+        for (input, error) in errors {
+            assert_eq!(Err(error), eval_in(input, &mut env), "{input}");
+        }
+        // The variable is removed even after an error.
+        assert_eq!(None, env.get("i"));
+    }
+
+    #[test]
+    fn test_rand() {
+        let mut env = Environment::default();
+        // The generator is only seeded with the time when it's first used.
+        assert!(env.rng.is_none());
+        eval_in("rand()", &mut env).unwrap();
+        assert!(env.rng.is_some());
+        env.seed(7);
+        for _ in 0..1000 {
+            let x = eval_in("rand()", &mut env).unwrap();
+            assert!((0.0..1.0).contains(&x));
+            let x = eval_in("rand(-5, 5)", &mut env).unwrap();
+            assert!((-5.0..5.0).contains(&x));
+        }
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCountEither(
+                0,
+                2,
+                1,
+                (0..7).into()
+            )),
+            eval_in("rand(1)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_dice() {
+        let mut env = Environment::default();
+        env.seed(42);
+        let rolls = ["dice(3, 6)", "dice(3, 6)", "dice(1, 20)", "dice(10, 2)"]
+            .map(|input| eval_in(input, &mut env));
+        assert_eq!([Ok(9.0), Ok(11.0), Ok(7.0), Ok(15.0)], rolls);
+        // One-sided dice always roll 1.
+        assert_eq!(Ok(100.0), eval_in("dice(100, 1)", &mut env));
+        for _ in 0..1000 {
+            let total = eval_in("dice(2, 6)", &mut env).unwrap();
+            assert!((2.0..=12.0).contains(&total));
+        }
+    }
+
+    #[test]
+    fn test_dice_errors() {
+        let mut env = Environment::default();
+        for (input, end) in [
+            ("dice(0, 6)", 10),
+            ("dice(3, 0)", 10),
+            ("dice(2.5, 6)", 12),
+            ("dice(3, -6)", 11),
+            ("dice(3, 1/0)", 12),
+        ] {
+            assert_eq!(
+                Err(RuntimeError::ExpectedPositiveInteger((0..end).into())),
+                eval_in(input, &mut env),
+                "{input}"
+            );
+        }
+        assert_eq!(
+            Err(RuntimeError::TooManyDice((0..13).into())),
+            eval_in("dice(10^9, 6)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 1, (0..7).into())),
+            eval_in("dice(3)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_rand_not_memoized() {
+        let mut env = Environment::default();
+        env.seed(7);
+        // Every call gives a new number, so they can't be memoized.
+        let Ok(ParseTree::Expression(expr)) = Parser::new("rand() - rand()").parse() else {
+            panic!("not a valid expression");
+        };
+        assert_ne!(Ok(0.0), evaluate_memoized(expr, &mut env));
+    }
+
+    #[test]
+    fn test_assert() {
+        assert_eq!(1.0, eval("assert(2 + 2, 4)"));
+        assert_eq!(1.0, eval("assert(0.1 + 0.2, 0.3)"));
+        assert_eq!(1.0, eval("assert(1/0, 1/0)"));
+        assert_eq!(
+            Err(RuntimeError::AssertionFailed(5.0, 4.0, (0..16).into())),
+            eval_in("assert(2 + 3, 4)", &mut Environment::default())
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 1, (0..9).into())),
+            eval_in("assert(1)", &mut Environment::default())
+        );
+    }
+
+    #[test]
+    fn test_pipe() {
+        assert_eq!(4.0, eval("16 |> sqrt"));
+        assert_eq!(0.25, eval("16 |> sqrt |> inv"));
+        assert_eq!(2.0, eval("1 + 3 |> sqrt"));
+    }
+
+    #[test]
+    fn test_angle_conversions() {
+        let mut env = Environment::default();
+        env.set("pi".to_string(), std::f64::consts::PI);
+        assert_eq!(Ok(180.0), eval_in("degrees(pi)", &mut env));
+        assert_eq!(Ok(std::f64::consts::PI), eval_in("radians(180)", &mut env));
+        assert_eq!(
+            Ok(std::f64::consts::FRAC_PI_2),
+            eval_in("radians(90)", &mut env)
+        );
+        assert_eq!(0.0, eval("degrees(0)"));
+        assert_eq!(-90.0, eval("degrees(radians(-90))"));
+        // Round trips are exact up to rounding.
+        for angle in [90.0, 45.0, 30.0, 1.0, 359.9] {
+            let round_trip = eval(&format!("degrees(radians({angle}))"));
+            assert!((round_trip - angle).abs() <= f64::EPSILON * angle);
+        }
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(1, 2, (0..13).into())),
+            eval_in("degrees(1, 2)", &mut env)
+        );
+    }
+
+    #[test]
+    fn test_sqrt_prefix() {
+        assert_eq!(4.0, eval("√16"));
+        assert_eq!(4.0, eval("√(9+7)"));
+        // Like negation, `√` binds looser than `^`.
+        assert_eq!(3.0, eval("√3^2"));
+        assert_eq!(5.0, eval("1 + √16"));
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegative((0..5).into())),
+            eval_in("√-1", &mut Environment::default())
+        );
+    }
+
+    #[test]
+    fn test_approx() {
+        assert_eq!(0.0, eval("0.1 + 0.2 == 0.3"));
+        assert_eq!(1.0, eval("approx(0.1 + 0.2, 0.3, 10^-9)"));
+        // The error is about `5.5e-17`, which is more than `10^-17`.
+        assert_eq!(0.0, eval("approx(0.1 + 0.2, 0.3, 10^-17)"));
+        assert_eq!(1.0, eval("approx(1, 1, 0)"));
+        assert_eq!(1.0, eval("approx(1, 1.5, 0.5)"));
+    }
+
+    #[test]
+    fn test_approx_errors() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(3, 2, (0..12).into())),
+            eval_in("approx(1, 2)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegative((0..16).into())),
+            eval_in("approx(1, 2, -1)", &mut env)
+        );
+        assert!(matches!(
+            eval_in("approx(1, 2, 0/0)", &mut env),
+            Err(RuntimeError::ExpectedNonNegative(_))
+        ));
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!((1.0, 2.0), fraction(0.5, MAX_DENOMINATOR));
+        assert_eq!((3.0, 4.0), fraction(0.75, MAX_DENOMINATOR));
+        assert_eq!((-3.0, 4.0), fraction(-0.75, MAX_DENOMINATOR));
+        assert_eq!((1.0, 3.0), fraction(1.0 / 3.0, MAX_DENOMINATOR));
+        assert_eq!((5.0, 1.0), fraction(5.0, MAX_DENOMINATOR));
+        assert_eq!((0.0, 1.0), fraction(0.0, MAX_DENOMINATOR));
+        // With a small denominator, the closest fraction is only an approximation.
+        assert_eq!((1.0, 3.0), fraction(0.333, 10.0));
+        assert_eq!((22.0, 7.0), fraction(std::f64::consts::PI, 10.0));
+        assert_eq!((355.0, 113.0), fraction(std::f64::consts::PI, 1000.0));
+        // `311/99` is a semiconvergent, closer than the convergent `22/7`.
+        assert_eq!((311.0, 99.0), fraction(std::f64::consts::PI, 100.0));
+    }
+
+    #[test]
+    fn test_dec2frac() {
+        // Inside an expression, the fraction is a number like any other.
+        assert_eq!(0.75, eval("dec2frac(0.75)"));
+        assert_eq!(1.0 / 3.0, eval("dec2frac(0.333, 10)"));
+
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::ExpectedPositiveInteger((0..16).into())),
+            eval_in("dec2frac(0.5, 0)", &mut env)
+        );
+        assert_eq!(
+            Err(RuntimeError::WrongArgumentCount(2, 3, (0..17).into())),
+            eval_in("dec2frac(1, 2, 3)", &mut env)
+        );
+    }
+
+    /// Parses an input expression and evaluates it with `evaluate_radix`.
+    fn eval_radix(input: &str) -> Option<Result<(f64, String), RuntimeError>> {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                evaluate_radix(&expr, &mut Environment::default(), Modes::default())
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    #[test]
+    fn test_radix() {
+        assert_eq!(
+            Some(Ok((255.0, "0xff".to_string()))),
+            eval_radix("hex(255)")
+        );
+        assert_eq!(Some(Ok((5.0, "0b101".to_string()))), eval_radix("bin(5)"));
+        assert_eq!(Some(Ok((8.0, "0o10".to_string()))), eval_radix("oct(8)"));
+        assert_eq!(Some(Ok((0.0, "0x0".to_string()))), eval_radix("hex(0)"));
+        assert_eq!(None, eval_radix("inv(255)"));
+        assert_eq!(None, eval_radix("hex(255) + 1"));
+        // Inside an expression, the argument is a number like any other.
+        assert_eq!(256.0, eval("hex(255) + 1"));
+    }
+
+    /// Parses an input expression and evaluates it with `evaluate_format`.
+    fn eval_format(input: &str) -> Option<Result<(f64, String), RuntimeError>> {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                evaluate_format(&expr, &mut Environment::default(), Modes::default())
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            Some(Ok((0.75, "0.75".to_string()))),
+            eval_format(r#"fmt(3/4, "%.2f")"#)
+        );
+        assert_eq!(
+            Some(Ok((1500.0, "1.5e+03".to_string()))),
+            eval_format(r#"fmt(1500, "%.1e")"#)
+        );
+        assert_eq!(None, eval_format(r#"fmt(1, "%f") + 1"#));
+        // Inside an expression, the value isn't formatted.
+        assert_eq!(1.125, eval(r#"fmt(1/8, "%.2f") + 1"#));
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let mut env = Environment::default();
+        let errors = [
+            (
+                r#"fmt(1, "%d")"#,
+                RuntimeError::InvalidFormat((7..11).into()),
+            ),
+            (
+                r#"fmt(1, "%f%f")"#,
+                RuntimeError::InvalidFormat((7..13).into()),
+            ),
+            (r#"fmt(1, 2)"#, RuntimeError::ExpectedText((7..8).into())),
+            (
+                r#"fmt(1)"#,
+                RuntimeError::WrongArgumentCount(2, 1, (0..6).into()),
+            ),
+            (r#"1 + "%f""#, RuntimeError::UnexpectedText((4..8).into())),
+            (r#"sqrt("4")"#, RuntimeError::UnexpectedText((5..8).into())),
+        ];
+        for (input, error) in errors {
+            assert_eq!(Err(error), eval_in(input, &mut env), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_reduce() {
+        assert_eq!(6.0, eval(r#"reduce("+", 1, 2, 3)"#));
+        assert_eq!(24.0, eval(r#"reduce("*", 1, 2, 3, 4)"#));
+        assert_eq!(-1.0, eval(r#"reduce("min", 4, -1, 9)"#));
+        assert_eq!(9.0, eval(r#"reduce("max", 4, 1, 9)"#));
+        // A single value is reduced to itself.
+        assert_eq!(5.0, eval(r#"reduce("*", 5)"#));
+        assert_eq!(12.0, eval(r#"reduce("+", 2 * 3, sqrt(9), 3) + 0"#));
+    }
+
+    #[test]
+    fn test_reduce_errors() {
+        let mut env = Environment::default();
+        let errors = [
+            (
+                r#"reduce("-", 1, 2)"#,
+                RuntimeError::UnknownOperator((7..10).into()),
+            ),
+            (r#"reduce(1, 2)"#, RuntimeError::ExpectedText((7..8).into())),
+            (r#"reduce("max")"#, RuntimeError::NoValues((0..13).into())),
+            (
+                r#"reduce()"#,
+                RuntimeError::WrongArgumentCount(2, 0, (0..8).into()),
+            ),
+            (
+                r#"reduce("+", 1, y)"#,
+                RuntimeError::UnknownVariable("y".to_string(), (15..16).into()),
+            ),
+        ];
+        for (input, error) in errors {
+            assert_eq!(Err(error), eval_in(input, &mut env), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_radix_errors() {
+        assert_eq!(
+            Some(Err(RuntimeError::ExpectedNonNegativeInteger((0..8).into()))),
+            eval_radix("hex(1.5)")
+        );
+        assert_eq!(
+            Some(Err(RuntimeError::ExpectedNonNegativeInteger((0..7).into()))),
+            eval_radix("bin(-1)")
+        );
+        assert_eq!(
+            Some(Err(RuntimeError::Overflow((0..10).into()))),
+            eval_radix("oct(2^128)")
+        );
+        assert_eq!(
+            Some(Err(RuntimeError::WrongArgumentCount(1, 2, (0..9).into()))),
+            eval_radix("hex(1, 2)")
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedNonNegativeInteger((4..12).into())),
+            eval_in("1 + hex(0.5)", &mut Environment::default())
+        );
+    }
+
+    /// Parses and evaluates an input expression in complex mode, and formats the result.
+    fn eval_complex(input: &str) -> Result<String, RuntimeError> {
+        match Parser::new(input).complex(true).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                evaluate_complex(expr, &mut Environment::default()).map(|z| z.to_string())
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    #[test]
+    fn test_complex_arithmetic() {
+        assert_eq!(Ok("5 + 5i".to_string()), eval_complex("(1 + 2i) * (3 - i)"));
+        assert_eq!(Ok("-1".to_string()), eval_complex("i^2"));
+        assert_eq!(
+            Ok("0.1 + 0.7i".to_string()),
+            eval_complex("(1 + 2i) / (3 - i)")
+        );
+        assert_eq!(Ok("1".to_string()), eval_complex("2i == 2 * i"));
+        // Real expressions are unaffected.
+        assert_eq!(Ok("7".to_string()), eval_complex("1 + 2 * 3"));
+    }
+
+    #[test]
+    fn test_complex_sqrt() {
+        assert_eq!(Ok("i".to_string()), eval_complex("sqrt(-1)"));
+        assert_eq!(Ok("2i".to_string()), eval_complex("sqrt(-4)"));
+        assert_eq!(Ok("2i".to_string()), eval_complex("(-4)^0.5"));
+        assert_eq!(Ok("3".to_string()), eval_complex("sqrt(9)"));
+        assert_eq!(Ok("-2".to_string()), eval_complex("cbrt(-8)"));
+        assert_eq!(Ok("i".to_string()), eval_complex("√-1"));
+    }
+
+    #[test]
+    fn test_complex_errors() {
+        assert_eq!(
+            Err(RuntimeError::ExpectedReal((0..6).into())),
+            eval_complex("i < 2i")
+        );
+        assert_eq!(
+            Err(RuntimeError::ExpectedReal((4..6).into())),
+            eval_complex("x = 2i")
+        );
+        assert_eq!(
+            Err(RuntimeError::DivisionByZero((0..6).into())),
+            eval_complex("inv(0)")
+        );
+    }
+
+    /// Parses and evaluates an input expression in fixed-point mode.
+    fn eval_fixed_point(input: &str, decimals: usize) -> f64 {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                evaluate_fixed_point(expr, &mut Environment::default(), decimals).unwrap()
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    /// Parses and evaluates an input expression in a new environment,
+    /// with approximate equality.
+    fn eval_approx(input: &str) -> f64 {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                let modes = Modes {
+                    tolerance: Some(APPROX_TOLERANCE),
+                    ..Modes::default()
+                };
+                evaluate_with(expr, &mut Environment::default(), modes).unwrap()
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    /// Parses and evaluates an input expression in a new environment,
+    /// in integer mode.
+    fn eval_integer(input: &str, division: IntegerDivision) -> Result<f64, RuntimeError> {
+        match Parser::new(input).integer(true).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                let modes = Modes {
+                    integer: Some(division),
+                    ..Modes::default()
+                };
+                evaluate_with(expr, &mut Environment::default(), modes)
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    /// Parses and evaluates an input expression in the given environment,
+    /// handling overflows with the given policy.
+    fn eval_overflow(
+        input: &str,
+        env: &mut Environment,
+        overflow: OverflowPolicy,
+    ) -> Result<f64, RuntimeError> {
+        match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => {
+                let modes = Modes {
+                    overflow,
+                    ..Modes::default()
+                };
+                evaluate_with(expr, env, modes)
+            }
+            _ => panic!("`{input}` is not a valid expression"),
+        }
+    }
+
+    #[test]
+    fn test_evaluated_nodes() {
+        let mut env = Environment::default();
+        eval_in("2 + 3 * 4", &mut env).unwrap();
+        assert_eq!(5, env.take_evaluated_nodes());
+        assert_eq!(0, env.take_evaluated_nodes());
+        eval_in("sqrt((1 + 2) * (3 + 4))", &mut env).unwrap();
+        assert_eq!(8, env.take_evaluated_nodes());
+        // The body of a sum is evaluated once per term.
+        eval_in("sum(k * 2, k, 1, 3)", &mut env).unwrap();
+        assert_eq!(12, env.take_evaluated_nodes());
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut env = Environment::default();
+        let parse = |input: &str| match Parser::new(input).parse() {
+            Ok(ParseTree::Expression(expr)) => expr,
+            _ => panic!("`{input}` is not a valid expression"),
+        };
+        // `2 + 3 * 4` has 5 nodes, and `4` is the last one evaluated.
+        assert_eq!(
+            Ok(14.0),
+            evaluate_with_budget(parse("2 + 3 * 4"), &mut env, 5)
+        );
+        assert_eq!(
+            Err(RuntimeError::BudgetExceeded((8..9).into())),
+            evaluate_with_budget(parse("2 + 3 * 4"), &mut env, 4)
+        );
+        // Every term of a sum and every application of `iterate` counts.
+        assert!(evaluate_with_budget(parse("sum(k, k, 1, 100)"), &mut env, 100).is_err());
+        assert_eq!(
+            Err(RuntimeError::BudgetExceeded((0..25).into())),
+            evaluate_with_budget(parse("iterate(sqrt, 2, 1000000)"), &mut env, 100)
+        );
+        // The budget is only for that evaluation.
+        assert_eq!(Ok(5050.0), eval_in("sum(k, k, 1, 100)", &mut env));
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let mut env = Environment::default();
+        assert_eq!(
+            Ok(f64::INFINITY),
+            eval_overflow("10^308 * 10", &mut env, OverflowPolicy::Allow)
+        );
+        assert!(!env.take_overflow());
+
+        assert_eq!(
+            Ok(f64::INFINITY),
+            eval_overflow("10^308 * 10", &mut env, OverflowPolicy::Warn)
+        );
+        assert!(env.take_overflow());
+        assert!(!env.take_overflow());
+
+        assert_eq!(
+            Err(RuntimeError::Overflow((4..15).into())),
+            eval_overflow("1 + 10^308 * 10", &mut env, OverflowPolicy::Error)
+        );
+        assert!(!env.take_overflow());
+    }
+
+    #[test]
+    fn test_overflow_exceptions() {
+        use OverflowPolicy::Error;
+        let mut env = Environment::default();
+        // Dividing by zero and operations on infinities aren't overflows.
+        assert_eq!(Ok(f64::INFINITY), eval_overflow("1 / 0", &mut env, Error));
+        assert_eq!(
+            Ok(f64::INFINITY),
+            eval_overflow("1 / 0 * 2", &mut env, Error)
+        );
+        assert_eq!(Ok(1e308), eval_overflow("10^307 * 10", &mut env, Error));
+        // Neither are sums of terms that divided by zero.
+        assert_eq!(
+            Ok(f64::INFINITY),
+            eval_overflow("sum(1 / i, i, 0, 2)", &mut env, Error)
+        );
+    }
+
+    #[test]
+    fn test_overflow_outside_operators() {
+        use OverflowPolicy::{Error, Warn};
+        let mut env = Environment::default();
+        for (input, span) in [
+            ("sum(10^308, i, 1, 2)", 0..20),
+            ("product(10^200, i, 1, 2)", 0..24),
+            (r#"reduce("*", 10^200, 10^200)"#, 0..27),
+            ("iterate(sinh, 10, 2)", 0..20),
+            ("1 + cosh(1000)", 4..14),
+        ] {
+            assert_eq!(
+                Err(RuntimeError::Overflow(span.into())),
+                eval_overflow(input, &mut env, Error)
+            );
+            assert_eq!(Ok(f64::INFINITY), eval_overflow(input, &mut env, Warn));
+            assert!(env.take_overflow());
+        }
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let mut env = Environment::default();
+        eval_in("1 / 2", &mut env).unwrap();
+        assert_eq!(None, env.take_division_by_zero());
+        // The first division by zero is remembered, even if the result is finite.
+        // Its span includes the parentheses.
+        assert_eq!(Ok(2.0), eval_in("1 / (1 / 0) + 2 / 1", &mut env));
+        assert_eq!(Some((4..11).into()), env.take_division_by_zero());
+        assert_eq!(None, env.take_division_by_zero());
+    }
+
+    #[test]
+    fn test_integer_mode() {
+        use IntegerDivision::{Exact, Floor};
+        assert_eq!(Ok(4.0), eval_integer("8 / 2", Exact));
+        assert_eq!(
+            Err(RuntimeError::NonIntegerResult((0..5).into())),
+            eval_integer("7 / 2", Exact)
+        );
+        assert_eq!(Ok(3.0), eval_integer("7 / 2", Floor));
+        assert_eq!(Ok(-4.0), eval_integer("-7 / 2", Floor));
+        assert_eq!(Ok(7.0), eval_integer("7 / 2 * 2 + 1", Floor));
+        // The error points to the operation that isn't an integer.
+        assert_eq!(
+            Err(RuntimeError::NonIntegerResult((4..11).into())),
+            eval_integer("1 + sqrt(2)", Floor)
+        );
+        assert_eq!(
+            Err(RuntimeError::NonIntegerResult((0..4).into())),
+            eval_integer("2^-1", Floor)
+        );
+        assert_eq!(
+            Err(RuntimeError::DivisionByZero((0..5).into())),
+            eval_integer("1 / 0", Floor)
+        );
+    }
+
+    #[test]
+    fn test_approx_equality() {
+        assert_eq!(0.0, eval("0.1 + 0.2 == 0.3"));
+        assert_eq!(1.0, eval_approx("0.1 + 0.2 == 0.3"));
+        assert_eq!(0.0, eval_approx("0.1 + 0.2 != 0.3"));
+        assert_eq!(1.0, eval_approx("0.3 == 0.1 + 0.2 == 0.3"));
+        assert_eq!(0.0, eval_approx("1 == 1.000001"));
+        assert_eq!(1.0, eval_approx("0 == 0"));
+        assert_eq!(1.0, eval_approx("1 / 0 == 2 / 0"));
+        // Other comparisons are still exact.
+        assert_eq!(1.0, eval_approx("0.1 + 0.2 > 0.3"));
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        assert_eq!(0.30000000000000004, eval("0.1 + 0.2"));
+        assert_eq!(0.3, eval_fixed_point("0.1 + 0.2", 2));
+        // Every step is rounded, not just the result.
+        assert_eq!(1.0, eval("1 / 3 * 3"));
+        assert_eq!(0.99, eval_fixed_point("1 / 3 * 3", 2));
+        assert_eq!(0.13, eval_fixed_point("0.125 * 1", 2));
+        assert_eq!(3.0, eval_fixed_point("2.6 + 0.4", 0));
+        // Huge values are left alone.
+        assert_eq!(2f64.powi(1000), eval_fixed_point("2^1000", 17));
+    }
+
+    #[test]
+    fn test_eval_by_reference() {
+        let Ok(ParseTree::Expression(expr)) = Parser::new("2 * 3 + 1").parse() else {
+            panic!("not a valid expression");
+        };
+        assert_eq!(Ok(7.0), expr.eval());
+        assert_eq!(Ok(7.0), expr.eval());
+
+        // The same tree gives different results in different environments.
+        let Ok(ParseTree::Expression(expr)) = Parser::new("x += 1").parse() else {
+            panic!("not a valid expression");
+        };
+        let mut env = Environment::default();
+        env.set("x".to_string(), 1.0);
+        assert_eq!(Ok(2.0), expr.eval_in(&mut env));
+        assert_eq!(Ok(3.0), expr.eval_in(&mut env));
+        assert!(matches!(
+            expr.eval(),
+            Err(RuntimeError::UnknownVariable(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_template() {
+        let Ok(ParseTree::Expression(template)) = Parser::new("$1 * $1 + $2").parse() else {
+            panic!("not a valid expression");
+        };
+        let mut env = Environment::default();
+        assert_eq!(
+            Ok(10.0),
+            evaluate_template(&template, &mut env, &[3.0, 1.0])
+        );
+        assert_eq!(Ok(6.0), evaluate_template(&template, &mut env, &[2.0, 2.0]));
+        // Extra arguments are ignored.
+        assert_eq!(
+            Ok(1.0),
+            evaluate_template(&template, &mut env, &[0.0, 1.0, 5.0])
+        );
+        // `apply` evaluates the template inside expressions.
+        assert_eq!(
+            Err(RuntimeError::NoTemplate((4..15).into())),
+            eval_in("1 + apply(3, 1)", &mut env)
+        );
+        env.set_template(template);
+        assert_eq!(Ok(11.0), eval_in("1 + apply(3, 1)", &mut env));
+        assert_eq!(Ok(20.0), eval_in("apply(3, 1) * apply(1, 1)", &mut env));
+    }
+
+    #[test]
+    fn test_template_missing_argument() {
+        let Ok(ParseTree::Expression(template)) = Parser::new("$1 * $1 + $2").parse() else {
+            panic!("not a valid expression");
+        };
+        let mut env = Environment::default();
+        assert_eq!(
+            Err(RuntimeError::MissingArgument(2, Span::from(10..12))),
+            evaluate_template(&template, &mut env, &[3.0])
+        );
+        // Placeholders can't be evaluated without a template.
+        assert_eq!(
+            Err(RuntimeError::MissingArgument(1, Span::from(0..2))),
+            template.eval()
+        );
+    }
+
+    #[test]
+    fn test_memoized_same_results() {
+        let inputs = [
+            "1 + 2 * 3",
+            "(2^3 + 2^3) * (2^3 + 2^3)",
+            "5! / 5! + 5!",
+            "1 < 2 < 3",
+            "-(4 - 4) - (4 - 4)",
+        ];
+        for input in inputs {
+            let Ok(ParseTree::Expression(expr)) = Parser::new(input).parse() else {
+                panic!("`{input}` is not a valid expression");
+            };
+            let mut env = Environment::default();
+            assert_eq!(
+                evaluate(expr.clone(), &mut env),
+                evaluate_memoized(expr, &mut env),
+                "`{input}` evaluated differently with memoization"
+            );
+        }
+    }
+
+    #[test]
+    fn test_memoized_with_assignments() {
+        // `x` has different values in the same expression, so it can't be memoized.
+        let Ok(ParseTree::Expression(expr)) = Parser::new("(x = 1) + x + (x = 2) + x").parse()
+        else {
+            panic!("not a valid expression");
+        };
+        assert_eq!(
+            Ok(6.0),
+            evaluate_memoized(expr, &mut Environment::default())
+        );
     }
 }
@@ -0,0 +1,1727 @@
+//! A calculator session. The session holds everything that must be
+//! remembered from one input to the next, like variables and the last result.
+//! Both the interactive prompt and batch mode use it.
+
+use std::{borrow::Cow, collections::VecDeque};
+
+#[cfg(feature = "bigint")]
+use crate::{bigint::MAX_EXACT_FLOAT, runtime::evaluate_bigint};
+use crate::{
+    explain::explain,
+    format::{sparkline, ulp, FloatBits, Format, MAX_DECIMALS},
+    parser::{Expression, ParseTree, Parser, ParserError},
+    runtime::{
+        evaluate_complex, evaluate_format, evaluate_fraction, evaluate_radix, evaluate_table,
+        evaluate_with, Environment, IntegerDivision, Modes, OverflowPolicy, RuntimeError, Table,
+        APPROX_TOLERANCE,
+    },
+    tokenizer::Span,
+};
+
+/// What the calculator should do after executing an input.
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    /// Show the result of an evaluated expression.
+    Result(f64),
+    /// Show the result of an evaluated expression, rounded to the given
+    /// number of decimals instead of using the session's format.
+    Rounded(f64, usize),
+    /// Show the result of an evaluated expression in big integer mode,
+    /// with all the digits of the exact integer, since the float isn't exact.
+    Digits(f64, String),
+    /// Show the result of an evaluated expression as it's written by its
+    /// function, like `0xff` for `hex(255)`, instead of using the session's format.
+    Formatted(f64, String),
+    /// Show some text.
+    Text(String),
+    /// Copy some text to the clipboard.
+    Copy(String),
+    /// Quit the calculator.
+    Quit,
+    /// Nothing to show.
+    Nothing,
+}
+
+/// An error that occured while executing an input.
+#[derive(Debug, PartialEq)]
+pub enum SessionError {
+    /// The input could not be parsed.
+    Parser(ParserError),
+    /// The expression could not be evaluated.
+    Runtime(RuntimeError),
+    /// The command needs a previous result, but there isn't one.
+    NoPreviousResult,
+    /// `?settings undo` was used, but the settings were never changed.
+    NothingToUndo,
+    /// `?settings redo` was used, but no change of the settings was undone.
+    NothingToRedo,
+    /// A command needs a Cargo feature that the calculator was built without,
+    /// like `?bigint on` without the `bigint` feature.
+    MissingFeature(&'static str),
+}
+
+impl From<ParserError> for SessionError {
+    fn from(value: ParserError) -> Self {
+        SessionError::Parser(value)
+    }
+}
+
+impl From<RuntimeError> for SessionError {
+    fn from(value: RuntimeError) -> Self {
+        SessionError::Runtime(value)
+    }
+}
+
+/// The state of a calculator session.
+#[derive(Default)]
+pub struct Session {
+    /// The variables defined by the user.
+    environment: Environment,
+    /// The last evaluated result, if any.
+    last_result: Option<f64>,
+    /// The sum of the results since the running total was turned on,
+    /// or `None` if it's off.
+    total: Option<f64>,
+    /// The last evaluated expression, if any, with the environment it was
+    /// evaluated in. This is what `?explain` explains. The environment has
+    /// no history, which `?explain` gets back from the current one.
+    last_expression: Option<(Expression, Environment)>,
+    /// The result the history forgot when it remembered the last one, if it was full.
+    forgotten: Option<f64>,
+    /// How results are formatted.
+    format: Format,
+    /// Whether to show how long each input took to execute.
+    timing: bool,
+    /// Whether Roman numerals are parsed as numbers.
+    roman: bool,
+    /// Whether expressions are evaluated to complex numbers.
+    complex: bool,
+    /// The number of decimals every operation is rounded to, if any.
+    /// Complex mode doesn't round.
+    fixed_point: Option<usize>,
+    /// Whether `==` and `!=` allow a small relative tolerance, so that
+    /// `0.1 + 0.2 == 0.3` is true. Complex mode compares exactly.
+    approx: bool,
+    /// How divisions are handled in integer mode, where every literal and
+    /// result must be an integer, or `None` if it's off. Complex mode
+    /// doesn't check for integers.
+    integer: Option<IntegerDivision>,
+    /// Whether parentheses left open at the end of the input are closed.
+    autoclose: bool,
+    /// The number of parentheses closed at the end of the last input.
+    closed_parentheses: usize,
+    /// Whether integer operations are exact on integers of any size.
+    bigint: bool,
+    /// Whether the last input was evaluated with floats in big integer mode.
+    bigint_fallback: bool,
+    /// How operations that overflow are handled.
+    overflow: OverflowPolicy,
+    /// Whether an operation overflowed in the last input, with the `Warn` policy.
+    overflowed: bool,
+    /// The first division by zero of the last result, if any.
+    division_by_zero: Option<Span>,
+    /// Whether the evaluated expression nodes are counted.
+    profile: bool,
+    /// The number of expression nodes evaluated for the last result, if it's counted.
+    evaluated_nodes: Option<usize>,
+    /// The value results are compared to with their relative error, if any.
+    reference: Option<f64>,
+    /// The settings before each of their changes, oldest first.
+    /// This is what `?settings undo` goes back to.
+    undo_settings: Vec<Settings>,
+    /// The settings before each undo, most recently undone last.
+    /// This is what `?settings redo` goes back to.
+    redo_settings: Vec<Settings>,
+}
+
+/// The settings of a session, which `?settings undo` and `?settings redo`
+/// go back and forth between. Variables and results aren't settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Settings {
+    format: Format,
+    timing: bool,
+    roman: bool,
+    complex: bool,
+    fixed_point: Option<usize>,
+    approx: bool,
+    integer: Option<IntegerDivision>,
+    autoclose: bool,
+    bigint: bool,
+    overflow: OverflowPolicy,
+    profile: bool,
+    reference: Option<f64>,
+}
+
+impl Session {
+    /// Creates a new session.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Formats a result according to the session's settings.
+    pub fn format_result(&self, value: f64) -> String {
+        self.format.format(value)
+    }
+
+    /// Whether the time taken by each input should be shown.
+    pub fn timing(&self) -> bool {
+        self.timing
+    }
+
+    /// The value results are compared to with `?relerr`, if any.
+    pub fn reference(&self) -> Option<f64> {
+        self.reference
+    }
+
+    /// The running total, if it's on.
+    pub fn total(&self) -> Option<f64> {
+        self.total
+    }
+
+    /// Whether parentheses left open at the end of the input are closed.
+    pub fn autoclose(&self) -> bool {
+        self.autoclose
+    }
+
+    /// The number of parentheses that were closed at the end of the last
+    /// input, because they were left open while `?autoclose` is on.
+    pub fn closed_parentheses(&self) -> usize {
+        self.closed_parentheses
+    }
+
+    /// Evaluates an expression exactly, in big integer mode, or returns `None`
+    /// if it isn't exact on integers. Results too large to be exact floats
+    /// come with all their digits. Results too large to be floats at all
+    /// are handled like overflows in the history and the total.
+    #[cfg(feature = "bigint")]
+    fn evaluate_exact(
+        &mut self,
+        expr: &Expression,
+    ) -> Result<Option<(f64, Option<String>)>, SessionError> {
+        let Some(exact) = evaluate_bigint(expr, &self.environment).transpose()? else {
+            return Ok(None);
+        };
+        let value = exact.to_f64();
+        if value.is_infinite() {
+            match self.overflow {
+                OverflowPolicy::Allow => {}
+                OverflowPolicy::Warn => self.overflowed = true,
+                OverflowPolicy::Error => return Err(RuntimeError::Overflow(expr.span).into()),
+            }
+        }
+        let digits = (value.abs() > MAX_EXACT_FLOAT).then(|| exact.to_string());
+        Ok(Some((value, digits)))
+    }
+
+    /// Without the `bigint` feature, nothing is evaluated exactly.
+    #[cfg(not(feature = "bigint"))]
+    fn evaluate_exact(
+        &mut self,
+        _expr: &Expression,
+    ) -> Result<Option<(f64, Option<String>)>, SessionError> {
+        Ok(None)
+    }
+
+    /// The modes in which expressions are evaluated with floats.
+    fn modes(&self) -> Modes {
+        Modes {
+            decimals: self.fixed_point,
+            tolerance: self.approx.then_some(APPROX_TOLERANCE),
+            integer: self.integer,
+            overflow: self.overflow,
+        }
+    }
+
+    /// The current settings.
+    fn settings(&self) -> Settings {
+        Settings {
+            format: self.format,
+            timing: self.timing,
+            roman: self.roman,
+            complex: self.complex,
+            fixed_point: self.fixed_point,
+            approx: self.approx,
+            integer: self.integer,
+            autoclose: self.autoclose,
+            bigint: self.bigint,
+            overflow: self.overflow,
+            profile: self.profile,
+            reference: self.reference,
+        }
+    }
+
+    /// Changes all the settings at once.
+    fn set_settings(&mut self, settings: Settings) {
+        self.format = settings.format;
+        self.timing = settings.timing;
+        self.roman = settings.roman;
+        self.complex = settings.complex;
+        self.fixed_point = settings.fixed_point;
+        self.approx = settings.approx;
+        self.integer = settings.integer;
+        self.autoclose = settings.autoclose;
+        self.bigint = settings.bigint;
+        self.overflow = settings.overflow;
+        self.profile = settings.profile;
+        self.reference = settings.reference;
+    }
+
+    /// Whether the last input was evaluated with floats, although big integer
+    /// mode is on, because it used an operation that isn't exact on integers.
+    pub fn bigint_fallback(&self) -> bool {
+        self.bigint_fallback
+    }
+
+    /// Whether an operation overflowed in the last input, while overflows
+    /// are handled with the `Warn` policy.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Where the last result first divided by zero, if it did. Dividing by
+    /// zero gives an infinite or `NaN` result instead of an error.
+    pub fn division_by_zero(&self) -> Option<Span> {
+        self.division_by_zero
+    }
+
+    /// The number of expression nodes evaluated for the last result, if
+    /// `?profile` is on and the result was evaluated with floats.
+    pub fn evaluated_nodes(&self) -> Option<usize> {
+        self.evaluated_nodes
+    }
+
+    /// Parses and executes a single input.
+    /// An expression can be prefixed with `~N` to round its result to `N` decimals,
+    /// like `~2 1/3`. The prefix is ignored for special commands.
+    pub fn execute(&mut self, input: &str) -> Result<Output, SessionError> {
+        let parsed = rounding_prefix(input).and_then(|(decimals, input)| {
+            let parser = Parser::new(&input)
+                .roman(self.roman)
+                .complex(self.complex)
+                .autoclose(self.autoclose)
+                .integer(self.integer.is_some());
+            let (parse_tree, closed) = parser.parse_with_closed_parentheses()?;
+            Ok((decimals, parse_tree, closed))
+        });
+        self.closed_parentheses = match &parsed {
+            Ok((_, _, closed)) => *closed,
+            Err(_) => 0,
+        };
+        self.bigint_fallback = false;
+        self.overflowed = false;
+        self.division_by_zero = None;
+        self.environment.take_division_by_zero();
+        self.evaluated_nodes = None;
+        let (decimals, parse_tree, _) = parsed?;
+        let settings = self.settings();
+        let output = match parse_tree {
+            // In complex mode, results that aren't real are shown as text.
+            // Only real results are remembered as the last result.
+            ParseTree::Expression(expr) if self.complex => {
+                let last_expression = (expr.clone(), self.environment.without_history());
+                let evaluated = evaluate_complex(expr, &mut self.environment)?;
+                if !evaluated.is_real() {
+                    self.last_result = None;
+                    self.last_expression = None;
+                    return Ok(Output::Text(evaluated.to_string()));
+                }
+                self.last_result = Some(evaluated.re);
+                self.last_expression = Some(last_expression);
+                match decimals {
+                    Some(decimals) => Output::Rounded(evaluated.re, decimals),
+                    None => Output::Result(evaluated.re),
+                }
+            }
+            // Expressions with placeholders are remembered as the template
+            ParseTree::Expression(expr) if expr.placeholder_count() > 0 => {
+                let count = expr.placeholder_count();
+                self.environment.set_template(expr);
+                let plural = if count == 1 { "" } else { "s" };
+                Output::Text(format!(
+                    "template with {count} argument{plural}, evaluate it with `apply(...)`"
+                ))
+            }
+            // Evaluate the expression
+            ParseTree::Expression(expr) => {
+                let last_expression = (expr.clone(), self.environment.without_history());
+                let modes = self.modes();
+                // `dec2frac` shows its result as a fraction, like `3/4`, `hex`, `bin`
+                // and `oct` show their result in that base, like `0xff`, and `fmt`
+                // shows its value formatted, like `3.14`. They are results like any
+                // other, only written differently.
+                let formatted = evaluate_fraction(&expr, &mut self.environment, modes)
+                    .map(|fraction| {
+                        fraction.map(|(numerator, denominator)| {
+                            let text = format_fraction(numerator, denominator);
+                            (numerator / denominator, text)
+                        })
+                    })
+                    .or_else(|| evaluate_radix(&expr, &mut self.environment, modes))
+                    .or_else(|| evaluate_format(&expr, &mut self.environment, modes));
+                if let Some(formatted) = formatted {
+                    let (value, text) = formatted?;
+                    self.last_result = Some(value);
+                    self.last_expression = Some(last_expression);
+                    Output::Formatted(value, text)
+                }
+                // `table` shows a value of the expression per row
+                else if let Some(table) = evaluate_table(&expr, &mut self.environment, modes) {
+                    self.last_expression = None;
+                    return Ok(Output::Text(format_table(&table?, &self.format)));
+                } else {
+                    let exact = match self.bigint {
+                        true => self.evaluate_exact(&expr)?,
+                        false => None,
+                    };
+                    self.bigint_fallback = self.bigint && exact.is_none();
+                    let (evaluated, digits) = match exact {
+                        Some(exact) => exact,
+                        None => {
+                            // Other evaluations, like the one of `table`, count nodes too
+                            self.environment.take_evaluated_nodes();
+                            let evaluated = evaluate_with(expr, &mut self.environment, modes);
+                            self.overflowed = self.environment.take_overflow();
+                            let evaluated_nodes = self.environment.take_evaluated_nodes();
+                            self.evaluated_nodes = self.profile.then_some(evaluated_nodes);
+                            (evaluated?, None)
+                        }
+                    };
+                    self.last_result = Some(evaluated);
+                    self.last_expression = Some(last_expression);
+                    match (digits, decimals) {
+                        // An integer is already rounded to any number of decimals
+                        (Some(digits), _) => Output::Digits(evaluated, digits),
+                        (None, Some(decimals)) => Output::Rounded(evaluated, decimals),
+                        (None, None) => Output::Result(evaluated),
+                    }
+                }
+            }
+            // Show the bits of the last result
+            ParseTree::Bits => {
+                let last_result = self.last_result.ok_or(SessionError::NoPreviousResult)?;
+                Output::Text(FloatBits::new(last_result).to_string())
+            }
+            // Show the gap between the last result and the next float
+            ParseTree::Ulp => {
+                let last_result = self.last_result.ok_or(SessionError::NoPreviousResult)?;
+                Output::Text(format!("ulp {}", ulp(last_result)))
+            }
+            // Compare two numbers, which may be written in different bases
+            ParseTree::Eq(a, b) if a == b => {
+                Output::Text(format!("equal, both are {}", self.format_result(a)))
+            }
+            ParseTree::Eq(a, b) => Output::Text(format!(
+                "not equal, {} and {}",
+                self.format_result(a),
+                self.format_result(b)
+            )),
+            // Show the limits of floating-point numbers
+            ParseTree::Eps => Output::Text(format_limits()),
+            // Show the variables, so they can be entered again in another session
+            ParseTree::Export if self.environment.variables().is_empty() => Output::Nothing,
+            ParseTree::Export => Output::Text(format_export(&self.environment)),
+            // Explain the last expression. The explanation evaluates it again,
+            // so it gets a copy of the environment to leave the real one untouched.
+            // The copy gets the history back as it was before the expression's
+            // result was remembered, so `prev` has the same value.
+            ParseTree::Explain => {
+                let (expr, env) = self
+                    .last_expression
+                    .as_ref()
+                    .ok_or(SessionError::NoPreviousResult)?;
+                let mut env = env.clone();
+                let history = self.environment.history();
+                let before = history.iter().take(history.len().saturating_sub(1));
+                for &result in self.forgotten.iter().chain(before) {
+                    env.remember(result);
+                }
+                Output::Text(explain(expr, &mut env)?.join("\n"))
+            }
+            // Copy the last result, as it's shown
+            ParseTree::Copy => {
+                let last_result = self.last_result.ok_or(SessionError::NoPreviousResult)?;
+                Output::Copy(self.format_result(last_result))
+            }
+            // Change the format settings
+            ParseTree::Scientific(scientific) => {
+                self.format.scientific = scientific;
+                Output::Nothing
+            }
+            ParseTree::Digits(digits) => {
+                self.format.digits = Some(digits);
+                Output::Nothing
+            }
+            ParseTree::Money(money) => {
+                self.format.money = money;
+                Output::Nothing
+            }
+            // Go back to the default format, which is the shortest exact representation
+            // The separator isn't a format of its own, so it's kept
+            ParseTree::PrecisionAuto => {
+                // The marks are the user's locale, not a precision
+                self.format = Format {
+                    separator: self.format.separator,
+                    decimal_mark: self.format.decimal_mark,
+                    grouped: self.format.grouped,
+                    ..Format::default()
+                };
+                Output::Nothing
+            }
+            ParseTree::Time(timing) => {
+                self.timing = timing;
+                Output::Nothing
+            }
+            ParseTree::Profile(profile) => {
+                self.profile = profile;
+                Output::Nothing
+            }
+            ParseTree::Roman(roman) => {
+                self.roman = roman;
+                Output::Nothing
+            }
+            ParseTree::Complex(complex) => {
+                self.complex = complex;
+                Output::Nothing
+            }
+            // Turning the total on again keeps adding to it
+            ParseTree::Total(true) => {
+                self.total.get_or_insert(0.0);
+                Output::Nothing
+            }
+            ParseTree::Total(false) => {
+                self.total = None;
+                Output::Nothing
+            }
+            ParseTree::TotalReset => {
+                if let Some(total) = &mut self.total {
+                    *total = 0.0;
+                }
+                Output::Nothing
+            }
+            ParseTree::Approx(approx) => {
+                self.approx = approx;
+                Output::Nothing
+            }
+            ParseTree::BigInt(true) if cfg!(not(feature = "bigint")) => {
+                return Err(SessionError::MissingFeature("bigint"));
+            }
+            ParseTree::BigInt(bigint) => {
+                self.bigint = bigint;
+                Output::Nothing
+            }
+            ParseTree::Overflow(overflow) => {
+                self.overflow = overflow;
+                Output::Nothing
+            }
+            ParseTree::AutoClose(autoclose) => {
+                self.autoclose = autoclose;
+                Output::Nothing
+            }
+            ParseTree::Integer(integer) => {
+                self.integer = integer;
+                Output::Nothing
+            }
+            // Like with `?format_marks`, the marks can't be the same
+            ParseTree::Separator(separator, span) if separator == self.format.decimal_mark => {
+                return Err(ParserError::SameMarks(span).into());
+            }
+            // A separator is only seen if the digits are grouped, so asking
+            // for one groups them, like `?format_marks`
+            ParseTree::Separator(separator, _) => {
+                self.format.separator = separator;
+                self.format.grouped = true;
+                Output::Nothing
+            }
+            ParseTree::FormatMarks(Some((separator, decimal_mark))) => {
+                self.format.separator = separator;
+                self.format.decimal_mark = decimal_mark;
+                self.format.grouped = true;
+                Output::Nothing
+            }
+            // Both marks go back to the default, so they can't end up the same
+            ParseTree::FormatMarks(None) => {
+                self.format.separator = Format::default().separator;
+                self.format.decimal_mark = Format::default().decimal_mark;
+                self.format.grouped = false;
+                Output::Nothing
+            }
+            // The reference is evaluated like any other expression
+            ParseTree::RelativeError(reference) => {
+                let modes = self.modes();
+                self.reference = match reference {
+                    Some(reference) => {
+                        Some(evaluate_with(reference, &mut self.environment, modes)?)
+                    }
+                    None => None,
+                };
+                Output::Nothing
+            }
+            ParseTree::Store(slot) => {
+                let last_result = self.last_result.ok_or(SessionError::NoPreviousResult)?;
+                self.environment.store(slot, last_result);
+                Output::Nothing
+            }
+            ParseTree::Seed(seed) => {
+                self.environment.seed(seed);
+                Output::Nothing
+            }
+            ParseTree::FixedPoint(decimals) => {
+                self.fixed_point = decimals;
+                Output::Nothing
+            }
+            // Undoing and redoing aren't changes of their own, so they return
+            // before the change is remembered
+            ParseTree::SettingsUndo => {
+                let previous = self
+                    .undo_settings
+                    .pop()
+                    .ok_or(SessionError::NothingToUndo)?;
+                self.redo_settings.push(settings);
+                self.set_settings(previous);
+                return Ok(Output::Nothing);
+            }
+            ParseTree::SettingsRedo => {
+                let next = self
+                    .redo_settings
+                    .pop()
+                    .ok_or(SessionError::NothingToRedo)?;
+                self.undo_settings.push(settings);
+                self.set_settings(next);
+                return Ok(Output::Nothing);
+            }
+            // Show the most recent results, oldest first
+            ParseTree::Last(count) => {
+                if self.environment.history().is_empty() {
+                    return Err(SessionError::NoPreviousResult);
+                }
+                let results = last_results(self.environment.history(), count)
+                    .map(|result| self.format_result(result))
+                    .collect::<Vec<_>>();
+                Output::Text(results.join("\n"))
+            }
+            // Show both results and how far apart they are, evaluated like
+            // any other expression
+            ParseTree::Diff(first, second) => {
+                let modes = self.modes();
+                let a = evaluate_with(first, &mut self.environment, modes)?;
+                let b = evaluate_with(second, &mut self.environment, modes)?;
+                let (absolute, relative) = difference(a, b);
+                let relative = match relative {
+                    Some(relative) => self.format_result(relative),
+                    None => "undefined, since b is 0".to_string(),
+                };
+                Output::Text(format!(
+                    "a = {}\nb = {}\n|a - b| = {}\n|a - b| / |b| = {relative}",
+                    self.format_result(a),
+                    self.format_result(b),
+                    self.format_result(absolute),
+                ))
+            }
+            ParseTree::Quit => Output::Quit,
+            ParseTree::Empty => Output::Nothing,
+        };
+
+        // Remember the settings before a change, so it can be undone
+        if self.settings() != settings {
+            self.undo_settings.push(settings);
+            self.redo_settings.clear();
+        }
+
+        // Remember the new result, if there is one
+        self.division_by_zero = self.environment.take_division_by_zero();
+        if let Output::Result(result)
+        | Output::Rounded(result, _)
+        | Output::Digits(result, _)
+        | Output::Formatted(result, _) = output
+        {
+            self.forgotten = self.environment.remember(result);
+            if let Some(total) = &mut self.total {
+                *total += result;
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+/// Formats a table in two aligned columns, with the variable and the
+/// expression as headers, like `x | (x * x)`. Tables of more than one row
+/// end with a sparkline of the values, under the values.
+fn format_table(table: &Table, format: &Format) -> String {
+    let header = (table.variable.clone(), table.expression.to_string());
+    let rows = std::iter::once(header)
+        .chain(
+            table
+                .rows
+                .iter()
+                .map(|(x, value)| (format.format(*x), format.format(*value))),
+        )
+        .collect::<Vec<_>>();
+    let width = rows
+        .iter()
+        .map(|(x, _)| x.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut lines = rows
+        .iter()
+        .map(|(x, value)| format!("{x:<width$} | {value}"))
+        .collect::<Vec<_>>();
+    if table.rows.len() > 1 {
+        let values = table
+            .rows
+            .iter()
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        lines.push(format!("{:<width$} | {}", "", sparkline(&values)));
+    }
+    lines.join("\n")
+}
+
+/// Formats the variables as assignments, one per line, like `x = 2` or
+/// `const g = 9.81`, so that entering the lines defines them again.
+fn format_export(env: &Environment) -> String {
+    env.variables()
+        .iter()
+        .map(|&(name, value, constant)| {
+            // There are no literals for infinity and `NaN`, so they're
+            // written as the divisions that give them
+            let value = match value {
+                value if value.is_nan() => "0/0".to_string(),
+                f64::INFINITY => "1/0".to_string(),
+                f64::NEG_INFINITY => "-1/0".to_string(),
+                // The shortest representation reads back to the same number
+                value => value.to_string(),
+            };
+            let keyword = if constant { "const " } else { "" };
+            format!("{keyword}{name} = {value}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats the machine epsilon and the limits of floating-point numbers,
+/// one per line. Epsilon is the gap between `1` and the next number.
+fn format_limits() -> String {
+    [
+        ("epsilon", f64::EPSILON),
+        ("min positive", f64::MIN_POSITIVE),
+        ("max", f64::MAX),
+        ("min", f64::MIN),
+    ]
+    .iter()
+    .map(|(name, value)| format!("{name:<12} {value:e}"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// The absolute difference between two numbers, and the relative difference
+/// to the second one. The relative difference is undefined if `b` is 0.
+fn difference(a: f64, b: f64) -> (f64, Option<f64>) {
+    ((a - b).abs(), relative_error(a, b))
+}
+
+/// The relative error of a value to a reference, `|value - reference| / |reference|`.
+/// It's undefined if the reference is 0.
+pub fn relative_error(value: f64, reference: f64) -> Option<f64> {
+    (reference != 0.0).then(|| (value - reference).abs() / reference.abs())
+}
+
+/// The last `count` results of a history, or the whole history if it's shorter.
+fn last_results(history: &VecDeque<f64>, count: usize) -> impl Iterator<Item = f64> + '_ {
+    history
+        .iter()
+        .skip(history.len().saturating_sub(count))
+        .copied()
+}
+
+/// Formats a fraction like `3/4`. Integers are shown without a denominator.
+fn format_fraction(numerator: f64, denominator: f64) -> String {
+    if denominator == 1.0 {
+        numerator.to_string()
+    } else {
+        format!("{numerator}/{denominator}")
+    }
+}
+
+/// Splits the `~N` rounding prefix from an input, like in `~2 1/3`.
+/// The prefix is replaced by spaces in the returned input, so that the
+/// spans of parser and runtime errors still point to the right place.
+fn rounding_prefix(input: &str) -> Result<(Option<usize>, Cow<'_, str>), ParserError> {
+    let start = input.len() - input.trim_start().len();
+    let Some(rest) = input[start..].strip_prefix('~') else {
+        return Ok((None, Cow::Borrowed(input)));
+    };
+
+    // The number of decimals goes until the next whitespace
+    let argument = rest.split(char::is_whitespace).next().unwrap_or_default();
+    let range = 0..=MAX_DECIMALS;
+    let decimals = argument
+        .parse::<usize>()
+        .ok()
+        .filter(|decimals| range.contains(decimals))
+        .ok_or_else(|| {
+            // Point to the `~` if there is no argument at all
+            let span = if argument.is_empty() {
+                Span::from(start..(start + 1))
+            } else {
+                Span::from((start + 1)..(start + 1 + argument.len()))
+            };
+            ParserError::ExpectedIntegerInRange(Some(span), range.clone())
+        })?;
+
+    // The prefix is ASCII, so it's as many bytes as spaces
+    let prefix_len = start + 1 + argument.len();
+    let masked = format!("{}{}", " ".repeat(prefix_len), &input[prefix_len..]);
+    Ok((Some(decimals), Cow::Owned(masked)))
+}
+
+/// Tests for sessions.
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::{
+        parser::ParserError,
+        runtime::{OverflowPolicy, RuntimeError, MAX_HISTORY},
+        session::{
+            difference, last_results, relative_error, rounding_prefix, Output, Session,
+            SessionError,
+        },
+        tokenizer::Span,
+    };
+
+    #[test]
+    fn test_rounding_prefix() {
+        let (decimals, input) = rounding_prefix("~2 1/3").unwrap();
+        assert_eq!(Some(2), decimals);
+        assert_eq!("   1/3", input);
+
+        let (decimals, input) = rounding_prefix(" ~10\t1").unwrap();
+        assert_eq!(Some(10), decimals);
+        assert_eq!("    \t1", input);
+    }
+
+    #[test]
+    fn test_no_rounding_prefix() {
+        let (decimals, input) = rounding_prefix("1/3").unwrap();
+        assert_eq!(None, decimals);
+        assert_eq!("1/3", input);
+    }
+
+    #[test]
+    fn test_invalid_rounding_prefix() {
+        assert_eq!(
+            Err(ParserError::ExpectedIntegerInRange(
+                Some(Span::from(1..3)),
+                0..=17
+            )),
+            rounding_prefix("~99 1").map(|_| ())
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedIntegerInRange(
+                Some(Span::from(1..2)),
+                0..=17
+            )),
+            rounding_prefix("~x 1").map(|_| ())
+        );
+        assert_eq!(
+            Err(ParserError::ExpectedIntegerInRange(
+                Some(Span::from(0..1)),
+                0..=17
+            )),
+            rounding_prefix("~ 1").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_rounding_keeps_session_format() {
+        let mut session = Session::new();
+        assert!(matches!(
+            session.execute("~2 1/3"),
+            Ok(Output::Rounded(_, 2))
+        ));
+        // Only that result was rounded.
+        assert!(matches!(session.execute("1/3"), Ok(Output::Result(_))));
+        assert_eq!("0.3333333333333333", session.format_result(1.0 / 3.0));
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut session = Session::new();
+        assert!(matches!(
+            session.execute("?copy"),
+            Err(SessionError::NoPreviousResult)
+        ));
+        session.execute("1 / 4").unwrap();
+        assert_eq!(
+            Ok(Output::Copy("0.25".to_string())),
+            session.execute("?copy")
+        );
+        // The result is copied as it's shown.
+        session.execute("?sci on").unwrap();
+        assert_eq!(
+            Ok(Output::Copy("2.5e-1".to_string())),
+            session.execute("?copy")
+        );
+    }
+
+    #[test]
+    fn test_rounding_error_spans() {
+        // Spans still point into the original input.
+        let mut session = Session::new();
+        assert!(matches!(
+            session.execute("~2 1 + x"),
+            Err(SessionError::Runtime(RuntimeError::UnknownVariable(_, span)))
+                if span == Span::from(7..8)
+        ));
+    }
+
+    #[test]
+    fn test_complex_mode() {
+        let mut session = Session::new();
+        assert!(matches!(
+            session.execute("sqrt(-4)"),
+            Ok(Output::Result(result)) if result.is_nan()
+        ));
+        session.execute("?complex on").unwrap();
+        assert_eq!(
+            Ok(Output::Text("2i".to_string())),
+            session.execute("sqrt(-4)")
+        );
+        assert_eq!(
+            Ok(Output::Text("5 + 5i".to_string())),
+            session.execute("(1 + 2i) * (3 - i)")
+        );
+        // Real results are remembered as usual.
+        assert_eq!(Ok(Output::Result(-1.0)), session.execute("i * i"));
+        assert_eq!(Ok(Output::Copy("-1".to_string())), session.execute("?copy"));
+        session.execute("?complex off").unwrap();
+        assert!(matches!(
+            session.execute("2i"),
+            Err(SessionError::Parser(
+                ParserError::ImaginaryOutsideComplexMode(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_fixed_point_mode() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Result(0.30000000000000004)),
+            session.execute("0.1 + 0.2")
+        );
+        session.execute("?fixedpoint 2").unwrap();
+        assert_eq!(Ok(Output::Result(0.3)), session.execute("0.1 + 0.2"));
+        session.execute("?fixedpoint off").unwrap();
+        assert_eq!(
+            Ok(Output::Result(0.30000000000000004)),
+            session.execute("0.1 + 0.2")
+        );
+    }
+
+    #[test]
+    fn test_seed() {
+        let mut session = Session::new();
+        session.execute("?seed 42").unwrap();
+        let sequence =
+            ["rand()", "rand()", "rand(1, 7)"].map(|input| session.execute(input).unwrap());
+        assert_eq!(
+            [
+                Output::Result(0.1941059175341826),
+                Output::Result(0.5626318272656207),
+                Output::Result(3.916636826260313),
+            ],
+            sequence
+        );
+        // Seeding again starts the same sequence again.
+        session.execute("?seed 42").unwrap();
+        assert_eq!(
+            Ok(Output::Result(0.1941059175341826)),
+            session.execute("rand()")
+        );
+        // Every call gives a new number, even in the same expression.
+        assert_ne!(Ok(Output::Result(0.0)), session.execute("rand() - rand()"));
+    }
+
+    #[test]
+    fn test_approx_mode() {
+        let mut session = Session::new();
+        assert_eq!(Ok(Output::Result(0.0)), session.execute("0.1 + 0.2 == 0.3"));
+        assert_eq!(Ok(Output::Result(1.0)), session.execute("0.1 + 0.2 != 0.3"));
+        session.execute("?approx on").unwrap();
+        assert_eq!(Ok(Output::Result(1.0)), session.execute("0.1 + 0.2 == 0.3"));
+        assert_eq!(Ok(Output::Result(0.0)), session.execute("0.1 + 0.2 != 0.3"));
+        // The tolerance is relative, so it doesn't hide real differences.
+        assert_eq!(Ok(Output::Result(0.0)), session.execute("1.001 == 1"));
+        assert_eq!(Ok(Output::Result(0.0)), session.execute("10^-20 == 0"));
+        session.execute("?approx off").unwrap();
+        assert_eq!(Ok(Output::Result(0.0)), session.execute("0.1 + 0.2 == 0.3"));
+    }
+
+    #[test]
+    fn test_autoclose() {
+        let mut session = Session::new();
+        assert_eq!(
+            Err(SessionError::Parser(ParserError::UnclosedParenthesis(None))),
+            session.execute("(1 + (2 * 3")
+        );
+        session.execute("?autoclose on").unwrap();
+        assert_eq!(Ok(Output::Result(7.0)), session.execute("(1 + (2 * 3"));
+        assert_eq!(2, session.closed_parentheses());
+        assert_eq!(Ok(Output::Result(7.0)), session.execute("(1 + (2 * 3))"));
+        assert_eq!(0, session.closed_parentheses());
+        // The rounding prefix isn't part of the expression.
+        assert_eq!(
+            Ok(Output::Rounded(1.0 / 3.0, 2)),
+            session.execute("~2 (1 / 3")
+        );
+        assert_eq!(1, session.closed_parentheses());
+        // Extra closing parentheses can't be fixed at the end.
+        assert!(session.execute("1 + 2)").is_err());
+        session.execute("?autoclose off").unwrap();
+        assert!(session.execute("(1 + 2").is_err());
+        assert_eq!(0, session.closed_parentheses());
+    }
+
+    #[test]
+    fn test_table() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Text(
+                ["x  | (x * x)", "0  | 0", "5  | 25", "10 | 100", "   | ▁▃█"].join("\n")
+            )),
+            session.execute("table(x * x, x, 0, 10, 5)")
+        );
+        // The variable doesn't exist afterwards.
+        assert!(session.execute("x").is_err());
+        // A single row has no sparkline.
+        assert_eq!(
+            Ok(Output::Text(["y | (y + 1)", "2 | 3"].join("\n"))),
+            session.execute("table(y + 1, y, 2, 2, 1)")
+        );
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NestedTable(
+                Span::from(4..28)
+            ))),
+            session.execute("1 + table(x * x, x, 0, 5, 1)")
+        );
+    }
+
+    #[test]
+    fn test_table_modes() {
+        let mut session = Session::new();
+        // Every row is evaluated in the calculator's modes, like any expression.
+        session.execute("?fixedpoint 2").unwrap();
+        assert_eq!(
+            Ok(Output::Text(
+                ["x | (x / 3)", "0 | 0", "1 | 0.33", "2 | 0.67", "  | ▁▄█"].join("\n")
+            )),
+            session.execute("table(x / 3, x, 0, 2, 1)")
+        );
+        session.execute("?fixedpoint off").unwrap();
+        session.execute("?int on").unwrap();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NonIntegerResult(
+                Span::from(6..11)
+            ))),
+            session.execute("table(x / 2, x, 0, 2, 1)")
+        );
+    }
+
+    #[test]
+    fn test_radix() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Formatted(255.0, "0xff".to_string())),
+            session.execute("hex(255)")
+        );
+        assert_eq!(
+            Ok(Output::Formatted(3.0, "0b11".to_string())),
+            session.execute("bin(1 + 2)")
+        );
+        // The value is the last result.
+        assert_eq!(Ok(Output::Copy("3".to_string())), session.execute("?copy"));
+        assert_eq!(
+            Err(SessionError::Runtime(
+                RuntimeError::ExpectedNonNegativeInteger(Span::from(0..9))
+            )),
+            session.execute("oct(-0.5)")
+        );
+    }
+
+    #[test]
+    fn test_radix_modes() {
+        let mut session = Session::new();
+        // The argument is evaluated in the calculator's modes, like any expression.
+        session.execute("?fixedpoint 0").unwrap();
+        assert_eq!(
+            Ok(Output::Formatted(4.0, "0x4".to_string())),
+            session.execute("hex(7 / 2)")
+        );
+        session.execute("?fixedpoint off").unwrap();
+        session.execute("?int on").unwrap();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NonIntegerResult(
+                Span::from(4..9)
+            ))),
+            session.execute("hex(7 / 2)")
+        );
+        session.execute("?int off").unwrap();
+
+        // The results are remembered and added to the total, like any result.
+        session.execute("?total on").unwrap();
+        session.execute("oct(8)").unwrap();
+        session.execute("dec2frac(0.5)").unwrap();
+        assert_eq!(Some(8.5), session.total());
+        assert_eq!(Some(&0.5), session.environment.history().back());
+    }
+
+    #[test]
+    fn test_format() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Formatted(3.14259, "  3.14".to_string())),
+            session.execute(r#"fmt(3.14259, "%6.2f")"#)
+        );
+        // The value is the last result, not the formatted text.
+        assert_eq!(
+            Ok(Output::Copy("3.14259".to_string())),
+            session.execute("?copy")
+        );
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::InvalidFormat(
+                Span::from(7..13)
+            ))),
+            session.execute(r#"fmt(1, "%.2x")"#)
+        );
+
+        // The value is evaluated in the calculator's modes, and the result
+        // is remembered like any other.
+        session.execute("?fixedpoint 2").unwrap();
+        session.execute("?total on").unwrap();
+        assert_eq!(
+            Ok(Output::Formatted(0.33, "0.33000".to_string())),
+            session.execute(r#"fmt(1 / 3, "%.5f")"#)
+        );
+        assert_eq!(Some(0.33), session.total());
+        assert_eq!(Some(&0.33), session.environment.history().back());
+    }
+
+    #[test]
+    fn test_dec2frac() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Formatted(0.75, "3/4".to_string())),
+            session.execute("dec2frac(0.75)")
+        );
+        assert_eq!(
+            Ok(Output::Formatted(0.5, "1/2".to_string())),
+            session.execute("dec2frac(0.5)")
+        );
+        assert_eq!(
+            Ok(Output::Formatted(1.0 / 3.0, "1/3".to_string())),
+            session.execute("dec2frac(0.333, 10)")
+        );
+        assert_eq!(
+            Ok(Output::Formatted(-2.0, "-2".to_string())),
+            session.execute("dec2frac(-2)")
+        );
+        // The value of the fraction is the last result.
+        assert_eq!(Ok(Output::Copy("-2".to_string())), session.execute("?copy"));
+    }
+
+    #[test]
+    fn test_last_results() {
+        let history = VecDeque::from([1.0, 2.0, 3.0]);
+        let last = |count| last_results(&history, count).collect::<Vec<_>>();
+        assert_eq!(vec![2.0, 3.0], last(2));
+        assert_eq!(vec![1.0, 2.0, 3.0], last(3));
+        // Asking for more results than there are gives all of them.
+        assert_eq!(vec![1.0, 2.0, 3.0], last(10));
+        assert_eq!(0, last_results(&VecDeque::new(), 10).count());
+    }
+
+    #[test]
+    fn test_last() {
+        let mut session = Session::new();
+        assert!(matches!(
+            session.execute("?last"),
+            Err(SessionError::NoPreviousResult)
+        ));
+        for input in ["1 + 1", "?sci off", "~1 1/3", "x = 10"] {
+            session.execute(input).unwrap();
+        }
+        assert_eq!(
+            Ok(Output::Text("0.3333333333333333\n10".to_string())),
+            session.execute("?last 2")
+        );
+        assert_eq!(
+            Ok(Output::Text("2\n0.3333333333333333\n10".to_string())),
+            session.execute("?last")
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut session = Session::new();
+        assert_eq!(
+            Err(SessionError::NoPreviousResult),
+            session.execute("?store 1")
+        );
+        session.execute("6 * 7").unwrap();
+        assert_eq!(Ok(Output::Nothing), session.execute("?store 1"));
+        session.execute("1 / 4").unwrap();
+        session.execute("?store 2").unwrap();
+        assert_eq!(Ok(Output::Result(42.25)), session.execute("M1 + M2"));
+        // Storing again replaces the slot.
+        session.execute("?store 1").unwrap();
+        assert_eq!(Ok(Output::Result(42.25)), session.execute("M1"));
+        assert_eq!(Ok(Output::Result(0.0)), session.execute("M3"));
+    }
+
+    #[test]
+    fn test_prev() {
+        let mut session = Session::new();
+        for input in ["1 + 1", "?sci off", "~1 1/3", "x = 10"] {
+            session.execute(input).unwrap();
+        }
+        assert_eq!(Ok(Output::Result(10.0)), session.execute("prev(1)"));
+        assert_eq!(Ok(Output::Result(2.0)), session.execute("prev(4)"));
+        // Results of `prev` are results too, so `prev(1)` is now 2.
+        assert_eq!(
+            Ok(Output::Result(12.0)),
+            session.execute("prev(1) + prev(3)")
+        );
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NoSuchResult(
+                6,
+                Span::from(0..7)
+            ))),
+            session.execute("prev(7)")
+        );
+        assert_eq!(
+            Err(SessionError::Runtime(
+                RuntimeError::ExpectedPositiveInteger(Span::from(5..8))
+            )),
+            session.execute("prev(1/2)")
+        );
+    }
+
+    #[test]
+    fn test_explain_prev() {
+        let mut session = Session::new();
+        for result in 1..=MAX_HISTORY {
+            session.execute(&result.to_string()).unwrap();
+        }
+        // The history is full, so the oldest result, 1, is forgotten. It's
+        // still the one `?explain` sees, like the expression did.
+        assert_eq!(
+            Ok(Output::Result(1.0)),
+            session.execute(&format!("prev({MAX_HISTORY})"))
+        );
+        assert_eq!(
+            Ok(Output::Text(format!("prev({MAX_HISTORY}) = 1"))),
+            session.execute("?explain")
+        );
+    }
+
+    #[test]
+    fn test_template() {
+        let mut session = Session::new();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NoTemplate(Span::from(
+                0..8
+            )))),
+            session.execute("apply(3)")
+        );
+        assert_eq!(
+            Ok(Output::Text(
+                "template with 1 argument, evaluate it with `apply(...)`".to_string()
+            )),
+            session.execute("$1 * $1 + 1")
+        );
+        assert_eq!(Ok(Output::Result(10.0)), session.execute("apply(3)"));
+        session.execute("x = 2").unwrap();
+        assert_eq!(Ok(Output::Result(26.0)), session.execute("apply(x + 3)"));
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::MissingArgument(
+                1,
+                Span::from(0..2)
+            ))),
+            session.execute("apply()")
+        );
+        // `apply` is a call like any other.
+        assert_eq!(Ok(Output::Result(11.0)), session.execute("1 + apply(3)"));
+        assert_eq!(
+            Ok(Output::Result(26.0)),
+            session.execute("iterate(apply, 1, 3)")
+        );
+        assert_eq!(
+            Ok(Output::Text("apply(1) = 2".to_string())),
+            session
+                .execute("apply(1)")
+                .and_then(|_| session.execute("?explain"))
+        );
+        // A template can't apply itself.
+        session.execute("apply($1) + 1").unwrap();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NoTemplate(Span::from(
+                0..9
+            )))),
+            session.execute("apply(2)")
+        );
+    }
+
+    #[test]
+    fn test_total() {
+        let mut session = Session::new();
+        session.execute("5").unwrap();
+        assert_eq!(None, session.total());
+
+        session.execute("?total on").unwrap();
+        assert_eq!(Some(0.0), session.total());
+        let totals = ["5", "3", "x = 2", "?bits", "1 / y", "-10"]
+            .into_iter()
+            .map(|input| {
+                let _ = session.execute(input);
+                session.total()
+            })
+            .collect::<Vec<_>>();
+        // Commands and errors don't change the total.
+        assert_eq!(
+            vec![
+                Some(5.0),
+                Some(8.0),
+                Some(10.0),
+                Some(10.0),
+                Some(10.0),
+                Some(0.0)
+            ],
+            totals
+        );
+
+        session.execute("7").unwrap();
+        session.execute("?total on").unwrap();
+        assert_eq!(Some(7.0), session.total());
+        session.execute("?total reset").unwrap();
+        assert_eq!(Some(0.0), session.total());
+        session.execute("?total off").unwrap();
+        assert_eq!(None, session.total());
+        // Resetting doesn't turn the total on.
+        session.execute("?total reset").unwrap();
+        assert_eq!(None, session.total());
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Text("equal, both are 255".to_string())),
+            session.execute("?eq 0xff 255")
+        );
+        assert_eq!(
+            Ok(Output::Text("not equal, 2 and 3".to_string())),
+            session.execute("?eq 0b10 3")
+        );
+        assert_eq!(
+            Ok(Output::Text("equal, both are 8".to_string())),
+            session.execute("?eq 0o10 0x8")
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let mut session = Session::new();
+        assert_eq!(Ok(Output::Nothing), session.execute("?export"));
+        for input in [
+            "x = 1/3",
+            "const g = 9.81",
+            "big = 2^1000",
+            "tiny = 2^-1000",
+            "negative = -0.5",
+            "infinite = 1/0",
+            "nan = 0/0",
+        ] {
+            session.execute(input).unwrap();
+        }
+        let Ok(Output::Text(exported)) = session.execute("?export") else {
+            panic!("`?export` should show the variables");
+        };
+        assert!(exported.starts_with("big = 1071508607186267"));
+        assert!(exported.contains("\nconst g = 9.81\n"));
+        assert!(exported.contains("\ninfinite = 1/0\n"));
+        assert!(exported.ends_with("\nx = 0.3333333333333333"));
+
+        // Entering the lines in a new session gives the same variables.
+        let mut copy = Session::new();
+        for line in exported.lines() {
+            copy.execute(line).unwrap();
+        }
+        assert_eq!(Ok(Output::Text(exported.clone())), copy.execute("?export"));
+        assert_eq!(
+            format!("{:?}", session.environment.variables()),
+            format!("{:?}", copy.environment.variables())
+        );
+    }
+
+    #[test]
+    fn test_eps() {
+        assert_eq!(
+            Ok(Output::Text(
+                "epsilon      2.220446049250313e-16\n\
+                 min positive 2.2250738585072014e-308\n\
+                 max          1.7976931348623157e308\n\
+                 min          -1.7976931348623157e308"
+                    .to_string()
+            )),
+            Session::new().execute("?eps")
+        );
+    }
+
+    #[test]
+    fn test_difference() {
+        assert_eq!((1.0, Some(0.5)), difference(3.0, 2.0));
+        assert_eq!((1.0, Some(0.5)), difference(-3.0, -2.0));
+        assert_eq!((0.0, Some(0.0)), difference(4.0, 4.0));
+        // The relative difference to zero is undefined, instead of infinite.
+        assert_eq!((2.0, None), difference(2.0, 0.0));
+        assert_eq!((0.0, None), difference(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_relative_error() {
+        assert_eq!(Some(0.5), relative_error(3.0, 2.0));
+        assert_eq!(Some(0.5), relative_error(-1.0, -2.0));
+        assert_eq!(Some(0.0), relative_error(7.0, 7.0));
+        assert_eq!(Some(2.0), relative_error(-1.0, 1.0));
+        // A reference of zero would divide by zero.
+        assert_eq!(None, relative_error(1.0, 0.0));
+        assert_eq!(None, relative_error(0.0, -0.0));
+    }
+
+    #[test]
+    fn test_relerr() {
+        let mut session = Session::new();
+        assert_eq!(None, session.reference());
+        assert_eq!(Ok(Output::Nothing), session.execute("?relerr 2 * 3"));
+        assert_eq!(Some(6.0), session.reference());
+        // Setting the reference isn't a result.
+        assert_eq!(
+            Err(SessionError::NoPreviousResult),
+            session.execute("?copy")
+        );
+        session.execute("?relerr off").unwrap();
+        assert_eq!(None, session.reference());
+        session.execute("?settings undo").unwrap();
+        assert_eq!(Some(6.0), session.reference());
+        // The reference is evaluated in the session's modes.
+        session.execute("?fixedpoint 1").unwrap();
+        session.execute("?relerr 1 / 3").unwrap();
+        assert_eq!(Some(0.3), session.reference());
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Text(
+                "a = 3\nb = 2\n|a - b| = 1\n|a - b| / |b| = 0.5".to_string()
+            )),
+            session.execute("?diff 1 + 2 ; 4 / 2")
+        );
+        assert_eq!(
+            Ok(Output::Text(
+                "a = 1\nb = 0\n|a - b| = 1\n|a - b| / |b| = undefined, since b is 0".to_string()
+            )),
+            session.execute("?diff 1 ; 0")
+        );
+        // Both expressions are evaluated in the session's modes.
+        session.execute("?fixedpoint 0").unwrap();
+        assert_eq!(
+            Ok(Output::Text(
+                "a = 3\nb = 2\n|a - b| = 1\n|a - b| / |b| = 0.5".to_string()
+            )),
+            session.execute("?diff 10 / 4 ; 2")
+        );
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        let mut session = Session::new();
+        let Ok(Output::Result(result)) = session.execute("0 * -1") else {
+            panic!("`0 * -1` has no result");
+        };
+        assert!(result.is_sign_negative());
+        assert_eq!("-0", session.format_result(result));
+        assert_eq!(
+            Ok(Output::Text("sign 1 | exponent 00000000000 | mantissa 0000000000000000000000000000000000000000000000000000".to_string())),
+            session.execute("?bits")
+        );
+        // Rounding every operation keeps the sign too.
+        session.execute("?fixedpoint 2").unwrap();
+        let Ok(Output::Result(result)) = session.execute("-0") else {
+            panic!("`-0` has no result");
+        };
+        assert!(result.is_sign_negative());
+    }
+
+    #[test]
+    fn test_money_format() {
+        let mut session = Session::new();
+        session.execute("?money on").unwrap();
+        assert_eq!("2.68", session.format_result(2.675));
+        assert_eq!("1,234.50", session.format_result(1234.5));
+        // The result itself isn't rounded, only how it's shown.
+        assert_eq!(Ok(Output::Result(2.675)), session.execute("2.675"));
+        session.execute("?money off").unwrap();
+        assert_eq!("2.675", session.format_result(2.675));
+    }
+
+    #[test]
+    fn test_integer_mode() {
+        let mut session = Session::new();
+        assert_eq!(Ok(Output::Result(3.5)), session.execute("7 / 2"));
+        session.execute("?int on").unwrap();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::NonIntegerResult(
+                Span::from(0..5)
+            ))),
+            session.execute("7 / 2")
+        );
+        assert_eq!(
+            Err(SessionError::Parser(ParserError::NonIntegerLiteral(
+                Span::from(0..3)
+            ))),
+            session.execute("3.5")
+        );
+        assert_eq!(Ok(Output::Result(4.0)), session.execute("8 / 2"));
+        session.execute("?int floor").unwrap();
+        assert_eq!(Ok(Output::Result(3.0)), session.execute("7 / 2"));
+        session.execute("?int off").unwrap();
+        assert_eq!(Ok(Output::Result(3.5)), session.execute("3.5"));
+    }
+
+    #[test]
+    fn test_settings_undo() {
+        let mut session = Session::new();
+        assert_eq!(
+            Err(SessionError::NothingToUndo),
+            session.execute("?settings undo")
+        );
+        session.execute("?sci on").unwrap();
+        session.execute("?digits 3").unwrap();
+        session.execute("?complex on").unwrap();
+        // Inputs that don't change the settings aren't remembered.
+        session.execute("x = 2").unwrap();
+        session.execute("?complex on").unwrap();
+        assert_eq!("1.23e3", session.format_result(1234.0));
+
+        session.execute("?settings undo").unwrap();
+        assert_eq!(Ok(Output::Result(4.0)), session.execute("x * 2"));
+        assert!(!session.complex);
+        session.execute("?settings undo").unwrap();
+        assert_eq!("1.234e3", session.format_result(1234.0));
+        session.execute("?settings redo").unwrap();
+        assert_eq!("1.23e3", session.format_result(1234.0));
+        session.execute("?settings undo").unwrap();
+        session.execute("?settings undo").unwrap();
+        assert_eq!("1234", session.format_result(1234.0));
+        assert_eq!(
+            Err(SessionError::NothingToUndo),
+            session.execute("?settings undo")
+        );
+
+        // A new change forgets what was undone.
+        session.execute("?settings redo").unwrap();
+        assert!(session.format.scientific);
+        session.execute("?approx on").unwrap();
+        assert_eq!(
+            Err(SessionError::NothingToRedo),
+            session.execute("?settings redo")
+        );
+        session.execute("?settings undo").unwrap();
+        assert!(!session.approx);
+        assert!(session.format.scientific);
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut session = Session::new();
+        session.execute("2 + 3 * 4").unwrap();
+        assert_eq!(None, session.evaluated_nodes());
+        session.execute("?profile on").unwrap();
+        assert_eq!(Ok(Output::Result(14.0)), session.execute("2 + 3 * 4"));
+        assert_eq!(Some(5), session.evaluated_nodes());
+        session.execute("x = sqrt(1 + 3) ^ (3 - 1)").unwrap();
+        assert_eq!(Some(9), session.evaluated_nodes());
+        // Commands don't evaluate anything.
+        session.execute("?last").unwrap();
+        assert_eq!(None, session.evaluated_nodes());
+        session.execute("?profile off").unwrap();
+        session.execute("2 + 3 * 4").unwrap();
+        assert_eq!(None, session.evaluated_nodes());
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let mut session = Session::new();
+        session.execute("~2 1 + 1 / 0").unwrap();
+        assert_eq!(Some(Span::from(7..12)), session.division_by_zero());
+        session.execute("1 / 4").unwrap();
+        assert_eq!(None, session.division_by_zero());
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        let mut session = Session::new();
+        assert_eq!(
+            Ok(Output::Result(f64::INFINITY)),
+            session.execute("10^308 * 10")
+        );
+        assert!(!session.overflowed());
+        session.execute("?overflow warn").unwrap();
+        assert_eq!(
+            Ok(Output::Result(f64::INFINITY)),
+            session.execute("10^308 * 10")
+        );
+        assert!(session.overflowed());
+        assert_eq!(Ok(Output::Result(2.0)), session.execute("1 + 1"));
+        assert!(!session.overflowed());
+        session.execute("?overflow error").unwrap();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::Overflow(Span::from(
+                0..11
+            )))),
+            session.execute("10^308 * 10")
+        );
+        assert!(!session.overflowed());
+        session.execute("?settings undo").unwrap();
+        assert_eq!(OverflowPolicy::Warn, session.overflow);
+    }
+
+    #[test]
+    #[cfg(feature = "bigint")]
+    fn test_bigint_mode() {
+        let mut session = Session::new();
+        assert_eq!(Ok(Output::Result(2f64.powi(128))), session.execute("2^128"));
+        session.execute("?bigint on").unwrap();
+        assert_eq!(
+            Ok(Output::Digits(
+                2f64.powi(128),
+                "340282366920938463463374607431768211456".to_string()
+            )),
+            session.execute("2^128")
+        );
+        assert_eq!(Some(2f64.powi(128)), session.last_result);
+        assert!(matches!(
+            session.execute("200!"),
+            Ok(Output::Digits(_, digits)) if digits.len() == 375
+        ));
+        assert!(!session.bigint_fallback());
+        // Small results are results like any other.
+        assert_eq!(Ok(Output::Result(120.0)), session.execute("5!"));
+        // Operations that aren't exact fall back to floats.
+        assert_eq!(Ok(Output::Result(0.5)), session.execute("1 / 2"));
+        assert!(session.bigint_fallback());
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::TooManyDigits(
+                Span::from(0..8)
+            ))),
+            session.execute("2^100000")
+        );
+        assert!(!session.bigint_fallback());
+        session.execute("?bigint off").unwrap();
+        assert_eq!(Ok(Output::Result(2f64.powi(128))), session.execute("2^128"));
+    }
+
+    #[test]
+    #[cfg(feature = "bigint")]
+    fn test_bigint_results_are_remembered() {
+        let mut session = Session::new();
+        session.execute("?bigint on").unwrap();
+        session.execute("?total on").unwrap();
+        // Like other results, big results are in the history and the total,
+        // and a rounding prefix doesn't change an integer.
+        assert_eq!(
+            Ok(Output::Digits(
+                2f64.powi(64),
+                "18446744073709551616".to_string()
+            )),
+            session.execute("~2 2^64")
+        );
+        assert_eq!(Some(&2f64.powi(64)), session.environment.history().back());
+        assert_eq!(Some(2f64.powi(64)), session.total());
+        // Results too large for floats follow the overflow policy.
+        session.execute("?overflow error").unwrap();
+        assert_eq!(
+            Err(SessionError::Runtime(RuntimeError::Overflow(Span::from(
+                0..7
+            )))),
+            session.execute("2^10000")
+        );
+        session.execute("?overflow warn").unwrap();
+        assert!(matches!(
+            session.execute("2^10000"),
+            Ok(Output::Digits(value, _)) if value.is_infinite()
+        ));
+        assert!(session.overflowed());
+    }
+
+    #[test]
+    #[cfg(not(feature = "bigint"))]
+    fn test_bigint_mode_without_feature() {
+        assert_eq!(
+            Err(SessionError::MissingFeature("bigint")),
+            Session::new().execute("?bigint on")
+        );
+    }
+
+    #[test]
+    fn test_format_marks() {
+        let mut session = Session::new();
+        session.execute("?format_marks , .").unwrap();
+        assert_eq!("1,234,567.89", session.format_result(1234567.89));
+        session.execute("?format_marks . ,").unwrap();
+        assert_eq!("1.234.567,89", session.format_result(1234567.89));
+        assert_eq!("-1.234.567", session.format_result(-1234567.0));
+        // `?sep` can't make the separator the decimal mark either.
+        assert_eq!(
+            Err(SessionError::Parser(ParserError::SameMarks(Span::from(
+                5..6
+            )))),
+            session.execute("?sep ,")
+        );
+        assert_eq!("1.234.567,89", session.format_result(1234567.89));
+        session.execute("?money on").unwrap();
+        assert_eq!("1.234.567,89", session.format_result(1234567.89));
+        // The marks stay when going back to the default format.
+        session.execute("?precision auto").unwrap();
+        assert_eq!("0,5", session.format_result(0.5));
+        session.execute("?format_marks off").unwrap();
+        assert_eq!("1234567.89", session.format_result(1234567.89));
+        session.execute("?money on").unwrap();
+        assert_eq!("1,234,567.89", session.format_result(1234567.89));
+    }
+
+    #[test]
+    fn test_separator() {
+        let mut session = Session::new();
+        assert_eq!("1000000", session.format_result(1e6));
+        // The separator groups the digits of every result.
+        session.execute("?sep _").unwrap();
+        assert_eq!("1_000_000", session.format_result(1e6));
+        session.execute("?format_marks off").unwrap();
+        assert_eq!("1000000", session.format_result(1e6));
+        session.execute("?money on").unwrap();
+        assert_eq!("1,000,000.00", session.format_result(1e6));
+        session.execute("?sep _").unwrap();
+        assert_eq!("1_000_000.00", session.format_result(1e6));
+        // Going back to the default format keeps the separator.
+        session.execute("?precision auto").unwrap();
+        session.execute("?money on").unwrap();
+        assert_eq!("1_000_000.00", session.format_result(1e6));
+        session.execute("?sep ,").unwrap();
+        assert_eq!("1,000,000.00", session.format_result(1e6));
+    }
+}
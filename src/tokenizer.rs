@@ -1,7 +1,9 @@
 //! The tokenizer uses a `Cursor` to iterate
 
 use std::{
+    fmt::Display,
     iter::Peekable,
+    num::IntErrorKind,
     ops::{Index, Range},
     str::Chars,
 };
@@ -11,6 +13,7 @@ use unicode_xid::UnicodeXID;
 /// A cursor over the input characters. The cursor's job
 /// is to record the byte position of the characters so
 /// we can give our tokens a span.
+#[derive(Clone)]
 struct Cursor<'a> {
     /// The character iterator.
     chars: Chars<'a>,
@@ -59,6 +62,32 @@ pub struct Span {
     pub end: usize,
 }
 
+impl Span {
+    /// Creates the smallest span covering both spans.
+    /// Anything between the two spans is covered too.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Moves the span forward by the given number of bytes.
+    pub fn offset(self, offset: usize) -> Span {
+        Span {
+            start: self.start + offset,
+            end: self.end + offset,
+        }
+    }
+}
+
+/// Shows the span like a range, like `0..3`.
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
 /// Allows us to create spans from ranges.
 impl From<Range<usize>> for Span {
     fn from(value: Range<usize>) -> Self {
@@ -79,11 +108,71 @@ impl Index<Span> for str {
 }
 
 /// A token kind for special tokens
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum SpecialKind {
     /// The quit instruction. We'll use this to let the
     /// user exit the calculator.
     Quit,
+    /// Shows the IEEE-754 bit layout of the last result.
+    Bits,
+    /// Shows the unit in the last place of the last result.
+    Ulp,
+    /// Explains how the last expression was evaluated.
+    Explain,
+    /// Copies the last result to the clipboard.
+    Copy,
+    /// Toggles scientific notation for results.
+    Scientific,
+    /// Sets the number of significant digits in scientific notation.
+    Digits,
+    /// Toggles showing how long each input took to execute.
+    Time,
+    /// Toggles parsing Roman numerals as numbers.
+    Roman,
+    /// Toggles complex numbers.
+    Complex,
+    /// Sets how precisely results are shown.
+    Precision,
+    /// Sets the number of decimals every operation is rounded to.
+    FixedPoint,
+    /// Shows the last results.
+    Last,
+    /// Toggles showing results like amounts of money.
+    Money,
+    /// Compares the results of two expressions.
+    Diff,
+    /// Shows the machine epsilon and other limits of floating-point numbers.
+    Eps,
+    /// Toggles approximate equality.
+    Approx,
+    /// Toggles or resets the running total.
+    Total,
+    /// Compares two number literals.
+    Eq,
+    /// Toggles closing missing parentheses.
+    AutoClose,
+    /// Seeds the random number generator.
+    Seed,
+    /// Sets the separator between groups of digits, and groups them.
+    Sep,
+    /// Toggles integer mode.
+    Int,
+    /// Undoes or redoes a change of the settings.
+    Settings,
+    /// Toggles big integer mode.
+    BigInt,
+    /// Shows the variables as assignments.
+    Export,
+    /// Sets how overflows are handled.
+    Overflow,
+    /// Sets the thousands separator and the decimal mark.
+    FormatMarks,
+    /// Toggles counting the evaluated expression nodes.
+    Profile,
+    /// Stores the last result in a memory slot.
+    Store,
+    /// Sets the reference value that results are compared to.
+    RelErr,
     /// An unrecognized special command.
     Unrecognized,
 }
@@ -99,6 +188,51 @@ pub enum OperationKind {
     Star,
     /// `/`.
     Slash,
+    /// `^`.
+    Caret,
+    /// `!`.
+    Bang,
+    /// `%`.
+    Percent,
+    /// `<`.
+    Less,
+    /// `<=`.
+    LessEqual,
+    /// `>`.
+    Greater,
+    /// `>=`.
+    GreaterEqual,
+    /// `==`.
+    EqualEqual,
+    /// `!=`.
+    BangEqual,
+    /// `<?`, the smallest of two numbers.
+    LessQuestion,
+    /// `>?`, the largest of two numbers.
+    GreaterQuestion,
+    /// The `and` keyword.
+    And,
+    /// The `or` keyword.
+    Or,
+    /// The `not` keyword.
+    Not,
+    /// `√`, the square root.
+    Sqrt,
+}
+
+/// A token kind for assignments.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AssignmentKind {
+    /// `=`.
+    Equal,
+    /// `+=`.
+    PlusEqual,
+    /// `-=`.
+    MinusEqual,
+    /// `*=`.
+    StarEqual,
+    /// `/=`.
+    SlashEqual,
 }
 
 /// The kind of our tokens.
@@ -110,15 +244,51 @@ pub enum TokenKind {
     Special(SpecialKind),
     /// Numbers. We'll represent all numbers as f64 internally.
     Number(f64),
+    /// Imaginary numbers, like `2i`. The value is the imaginary part.
+    Imaginary(f64),
+    /// Identifiers that aren't keywords.
+    Identifier(String),
+    /// Template placeholders, like `$1`. Placeholders are numbered from 1.
+    Placeholder(usize),
     /// Symbols for arithmetic operations.
     Operation(OperationKind),
+    /// Symbols for assignments.
+    Assignment(AssignmentKind),
     /// `(`.
     OpenParenthesis,
     /// `)`.
     CloseParenthesis,
+    /// `,`. Separates the arguments of a function call.
+    Comma,
+    /// `;`. Separates the expressions compared by `?diff`.
+    Semicolon,
+    /// `_`. A separator between groups of digits for `?sep`.
+    Underscore,
+    /// `.` on its own, not in a number. A mark for `?format_marks`.
+    Dot,
+    /// Text between double quotes, like `"%.2f"`, without the quotes.
+    Text(String),
+    /// `|>`. Passes the value on its left to the function on its right.
+    Pipe,
+    /// The `const` keyword, which defines a constant.
+    Const,
+}
 
-    /// Unrecognized tokens.
-    Unrecognized,
+/// Shows the kind like its `Debug` format, but with numbers and names as
+/// they're written, like `Number(123)` instead of `Number(123.0)`.
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Special(kind) => write!(f, "Special({kind:?})"),
+            TokenKind::Number(value) => write!(f, "Number({value})"),
+            TokenKind::Imaginary(value) => write!(f, "Imaginary({value})"),
+            TokenKind::Identifier(name) => write!(f, "Identifier({name})"),
+            TokenKind::Placeholder(index) => write!(f, "Placeholder({index})"),
+            TokenKind::Operation(kind) => write!(f, "Operation({kind:?})"),
+            TokenKind::Assignment(kind) => write!(f, "Assignment({kind:?})"),
+            kind => write!(f, "{kind:?}"),
+        }
+    }
 }
 
 /// Data structure for our tokens.
@@ -130,31 +300,142 @@ pub struct Token {
     pub span: Span,
 }
 
+/// Shows the kind and the span of the token, like `Number(123) @ 0..3`.
+/// This is meant for debugging, not for users.
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} @ {}", self.kind, self.span)
+    }
+}
+
+/// An error that occured during tokenization.
+#[derive(Debug, PartialEq)]
+pub enum TokenizerError {
+    /// The error occured because a character doesn't start any token.
+    UnrecognizedCharacter(Span),
+    /// The error occured because a number literal is too large to be represented.
+    NumberOverflow(Span),
+    /// The error occured because a number literal could not be parsed.
+    MalformedNumber(Span),
+    /// The error occured because a placeholder was `$0`, or too large.
+    InvalidPlaceholder(Span),
+    /// The error occured because text, like `"%.2f"`, had no closing quote.
+    UnterminatedText(Span),
+}
+
+impl TokenizerError {
+    /// Moves the span of the error forward by the given number of bytes.
+    fn offset(self, offset: usize) -> Self {
+        match self {
+            TokenizerError::UnrecognizedCharacter(span) => {
+                TokenizerError::UnrecognizedCharacter(span.offset(offset))
+            }
+            TokenizerError::NumberOverflow(span) => {
+                TokenizerError::NumberOverflow(span.offset(offset))
+            }
+            TokenizerError::MalformedNumber(span) => {
+                TokenizerError::MalformedNumber(span.offset(offset))
+            }
+            TokenizerError::InvalidPlaceholder(span) => {
+                TokenizerError::InvalidPlaceholder(span.offset(offset))
+            }
+            TokenizerError::UnterminatedText(span) => {
+                TokenizerError::UnterminatedText(span.offset(offset))
+            }
+        }
+    }
+}
+
+/// The number of parentheses left open at the end of the tokens, like 2 for
+/// `(1 + (2`. Tokens that close more parentheses than they open have none
+/// left open, since no closing parenthesis at the end can fix them.
+pub fn unclosed_parentheses(tokens: &[Token]) -> usize {
+    let mut depth: usize = 0;
+    for token in tokens {
+        match token.kind {
+            TokenKind::OpenParenthesis => depth += 1,
+            TokenKind::CloseParenthesis => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => return 0,
+            },
+            _ => {}
+        }
+    }
+    depth
+}
+
 /// The tokenizer. Transforms an input string into an iterator of tokens.
 pub struct Tokenizer<'a> {
     /// The tokenizer input.
     input: &'a str,
     /// The source cursor.
     cursor: Cursor<'a>,
+    /// The position of the input in a larger input. It's added to every span.
+    offset: usize,
 }
 
 impl<'a> Tokenizer<'a> {
     /// Creates a new tokenizer from an input string.
     pub fn new(input: &'a str) -> Self {
+        Self::with_offset(input, 0)
+    }
+
+    /// Creates a new tokenizer from a slice of a larger input, which starts
+    /// `offset` bytes into it. Spans are positions in the larger input, so
+    /// errors still point to the right place.
+    pub fn with_offset(input: &'a str, offset: usize) -> Self {
         Self {
             input,
             cursor: Cursor::new(input),
+            offset,
         }
     }
 
     /// Creates a token iterator by calling `next_token` until all the
-    /// characters are consumed.
-    pub fn tokenize(mut self) -> Peekable<impl Iterator<Item = Token> + 'a> {
+    /// characters are consumed. Tokens that could not be created are errors.
+    pub fn tokenize(
+        mut self,
+    ) -> Peekable<impl Iterator<Item = Result<Token, TokenizerError>> + 'a> {
+        let offset = self.offset;
         std::iter::from_fn(move || self.next_token())
-            .filter(|token| !matches!(token.kind, TokenKind::Whitespace))
+            .map(move |token| match token {
+                Ok(token) => Ok(Token {
+                    span: token.span.offset(offset),
+                    ..token
+                }),
+                Err(e) => Err(e.offset(offset)),
+            })
+            .filter(|token| {
+                !matches!(
+                    token,
+                    Ok(Token {
+                        kind: TokenKind::Whitespace,
+                        ..
+                    })
+                )
+            })
             .peekable()
     }
 
+    /// Tokenizes the whole input from the start, without consuming the tokenizer,
+    /// so the same tokenizer can be used again. Stops at the first error.
+    pub fn tokens(&self) -> Result<Vec<Token>, TokenizerError> {
+        Tokenizer::with_offset(self.input, self.offset)
+            .tokenize()
+            .collect()
+    }
+
+    /// Whether the input ends inside parentheses, like `(1 + 2`. The interactive
+    /// prompt uses this to ask for more input instead of reporting an error.
+    /// Inputs that can't be tokenized, or that close more parentheses than they
+    /// open, don't need a continuation: the parser reports the error right away.
+    pub fn needs_continuation(self) -> bool {
+        match self.tokens() {
+            Ok(tokens) => unclosed_parentheses(&tokens) > 0,
+            Err(_) => false,
+        }
+    }
+
     /// Advances the cursor while the characters are whitespace.
     fn whitespace(&mut self) {
         self.cursor.skip_while(char::is_whitespace);
@@ -166,17 +447,71 @@ impl<'a> Tokenizer<'a> {
     }
 
     /// Advances the cursor while the characters are part of a single number.
+    ///
+    /// An `e` or `E` followed by a digit, or by a sign and a digit, starts the
+    /// exponent of scientific notation, like in `2e5` or `2e-3`. Any other `e`
+    /// isn't part of the number, so `2e` and `2e+x` end before the `e`, which
+    /// is left for an identifier like the constant `e`.
     fn number(&mut self) {
         self.cursor.skip_while(|c: char| c.is_ascii_digit());
         if self.cursor.peek() == Some('.') {
             self.cursor.next(); // Consume the dot
             self.cursor.skip_while(|c: char| c.is_ascii_digit());
         }
+        let mut lookahead = self.cursor.clone();
+        if matches!(lookahead.next(), Some('e' | 'E')) {
+            if matches!(lookahead.peek(), Some('+' | '-')) {
+                lookahead.next(); // Consume the sign
+            }
+            if lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.cursor = lookahead;
+                self.cursor.skip_while(|c: char| c.is_ascii_digit());
+            }
+        }
+    }
+
+    /// Advances the cursor while the characters are part of a single hexadecimal
+    /// number, after the `0x` prefix. Like `1.8p3`, the binary exponent is optional.
+    fn hex_number(&mut self) {
+        self.cursor.skip_while(|c: char| c.is_ascii_hexdigit());
+        if self.cursor.peek() == Some('.') {
+            self.cursor.next(); // Consume the dot
+            self.cursor.skip_while(|c: char| c.is_ascii_hexdigit());
+        }
+        if matches!(self.cursor.peek(), Some('p' | 'P')) {
+            self.cursor.next(); // Consume the `p`
+            if matches!(self.cursor.peek(), Some('+' | '-')) {
+                self.cursor.next(); // Consume the sign
+            }
+            self.cursor.skip_while(|c: char| c.is_ascii_digit());
+        }
+    }
+
+    /// Consumes the `i` suffix of an imaginary number, like in `2i`, if there is one.
+    /// The `i` must not be the start of a longer identifier, like in `2if`.
+    fn imaginary_suffix(&mut self) -> bool {
+        let mut lookahead = self.cursor.clone();
+        if lookahead.next() != Some('i') || lookahead.peek().is_some_and(char::is_xid_continue) {
+            return false;
+        }
+        self.cursor = lookahead;
+        true
+    }
+
+    /// Creates a two-character token if the next character is `second`,
+    /// consuming it. Otherwise, creates the single-character token.
+    fn either(&mut self, second: char, double: TokenKind, single: TokenKind) -> TokenKind {
+        if self.cursor.peek() == Some(second) {
+            self.cursor.next(); // Consume the second character
+            double
+        } else {
+            single
+        }
     }
 
     /// Advances the cursor to create the single next token.
     /// This is the main tokenizing function.
-    fn next_token(&mut self) -> Option<Token> {
+    fn next_token(&mut self) -> Option<Result<Token, TokenizerError>> {
         // Record the start of the token.
         let start = self.cursor.byte_pos;
         // First, we take one single character.
@@ -191,34 +526,224 @@ impl<'a> Tokenizer<'a> {
             }
 
             // Special token (starts with `?`).
+            // Spaces are allowed between the `?` and the command name, like `? quit`.
             Some('?') => {
+                // Look ahead past the whitespace, so that the whitespace is only
+                // consumed if a command name follows it.
+                let mut lookahead = self.cursor.clone();
+                lookahead.skip_while(char::is_whitespace);
+                if lookahead.peek().is_some_and(char::is_xid_start) {
+                    self.cursor = lookahead;
+                }
+                let name_start = self.cursor.byte_pos;
                 self.identifier();
-                let identifier = &self.input[(start + 1)..self.cursor.byte_pos];
+                let identifier = &self.input[name_start..self.cursor.byte_pos];
                 match identifier {
                     "quit" => TokenKind::Special(SpecialKind::Quit),
+                    "bits" => TokenKind::Special(SpecialKind::Bits),
+                    "ulp" => TokenKind::Special(SpecialKind::Ulp),
+                    "explain" => TokenKind::Special(SpecialKind::Explain),
+                    "copy" => TokenKind::Special(SpecialKind::Copy),
+                    "sci" => TokenKind::Special(SpecialKind::Scientific),
+                    "digits" => TokenKind::Special(SpecialKind::Digits),
+                    "time" => TokenKind::Special(SpecialKind::Time),
+                    "roman" => TokenKind::Special(SpecialKind::Roman),
+                    "complex" => TokenKind::Special(SpecialKind::Complex),
+                    "precision" => TokenKind::Special(SpecialKind::Precision),
+                    "fixedpoint" => TokenKind::Special(SpecialKind::FixedPoint),
+                    "last" => TokenKind::Special(SpecialKind::Last),
+                    "money" => TokenKind::Special(SpecialKind::Money),
+                    "diff" => TokenKind::Special(SpecialKind::Diff),
+                    "eps" => TokenKind::Special(SpecialKind::Eps),
+                    "approx" => TokenKind::Special(SpecialKind::Approx),
+                    "total" => TokenKind::Special(SpecialKind::Total),
+                    "eq" => TokenKind::Special(SpecialKind::Eq),
+                    "autoclose" => TokenKind::Special(SpecialKind::AutoClose),
+                    "seed" => TokenKind::Special(SpecialKind::Seed),
+                    "sep" => TokenKind::Special(SpecialKind::Sep),
+                    "int" => TokenKind::Special(SpecialKind::Int),
+                    "settings" => TokenKind::Special(SpecialKind::Settings),
+                    "bigint" => TokenKind::Special(SpecialKind::BigInt),
+                    "export" => TokenKind::Special(SpecialKind::Export),
+                    "overflow" => TokenKind::Special(SpecialKind::Overflow),
+                    "format_marks" => TokenKind::Special(SpecialKind::FormatMarks),
+                    "profile" => TokenKind::Special(SpecialKind::Profile),
+                    "store" => TokenKind::Special(SpecialKind::Store),
+                    "relerr" => TokenKind::Special(SpecialKind::RelErr),
                     _ => TokenKind::Special(SpecialKind::Unrecognized),
                 }
             }
 
+            // Hexadecimal number token (starts with `0x`).
+            Some('0') if matches!(self.cursor.peek(), Some('x' | 'X')) => {
+                self.cursor.next(); // Consume the `x`
+                self.hex_number();
+                let span = Span::from(start..self.cursor.byte_pos);
+                // Skip the `0x` prefix
+                match hex_float(&self.input[(start + 2)..self.cursor.byte_pos]) {
+                    Some(number) if number.is_infinite() => {
+                        return Some(Err(TokenizerError::NumberOverflow(span)))
+                    }
+                    Some(number) => TokenKind::Number(number),
+                    None => return Some(Err(TokenizerError::MalformedNumber(span))),
+                }
+            }
+
+            // Binary and octal integer tokens (start with `0b` and `0o`).
+            Some('0') if matches!(self.cursor.peek(), Some('b' | 'B' | 'o' | 'O')) => {
+                let radix = match self.cursor.next() {
+                    Some('b' | 'B') => 2,
+                    _ => 8,
+                };
+                // Consume any letters and digits too, so `0b12` is malformed
+                // instead of being `0b1` followed by `2`.
+                self.identifier();
+                let span = Span::from(start..self.cursor.byte_pos);
+                // Skip the `0b` or `0o` prefix
+                match u128::from_str_radix(&self.input[(start + 2)..self.cursor.byte_pos], radix) {
+                    Ok(number) => TokenKind::Number(number as f64),
+                    Err(e) if *e.kind() == IntErrorKind::PosOverflow => {
+                        return Some(Err(TokenizerError::NumberOverflow(span)))
+                    }
+                    Err(_) => return Some(Err(TokenizerError::MalformedNumber(span))),
+                }
+            }
+
             // Number token.
             Some(c) if c.is_ascii_digit() => {
                 self.number();
-                let number = &self.input[start..self.cursor.byte_pos];
-                TokenKind::Number(number.parse().unwrap())
+                let span = Span::from(start..self.cursor.byte_pos);
+                match self.input[span].parse::<f64>() {
+                    // Very long literals are parsed as infinity
+                    Ok(number) if number.is_infinite() => {
+                        return Some(Err(TokenizerError::NumberOverflow(span)))
+                    }
+                    // A number directly followed by `i` is imaginary, like `2i`
+                    Ok(number) if self.imaginary_suffix() => TokenKind::Imaginary(number),
+                    Ok(number) => TokenKind::Number(number),
+                    Err(_) => return Some(Err(TokenizerError::MalformedNumber(span))),
+                }
+            }
+
+            // Identifier token. Some identifiers are reserved keywords
+            // that we recognize as operations instead.
+            Some(c) if c.is_xid_start() => {
+                self.identifier();
+                let identifier = &self.input[start..self.cursor.byte_pos];
+                match identifier {
+                    "and" => TokenKind::Operation(OperationKind::And),
+                    "or" => TokenKind::Operation(OperationKind::Or),
+                    "not" => TokenKind::Operation(OperationKind::Not),
+                    "const" => TokenKind::Const,
+                    _ => TokenKind::Identifier(identifier.to_string()),
+                }
             }
 
-            // Operation tokens
-            Some('+') => TokenKind::Operation(OperationKind::Plus),
-            Some('-') => TokenKind::Operation(OperationKind::Minus),
-            Some('*') => TokenKind::Operation(OperationKind::Star),
-            Some('/') => TokenKind::Operation(OperationKind::Slash),
+            // Operation tokens. The ones followed by `=` are compound assignments,
+            // so we peek at the next character to know which one we have.
+            Some('+') => self.either(
+                '=',
+                TokenKind::Assignment(AssignmentKind::PlusEqual),
+                TokenKind::Operation(OperationKind::Plus),
+            ),
+            Some('-') => self.either(
+                '=',
+                TokenKind::Assignment(AssignmentKind::MinusEqual),
+                TokenKind::Operation(OperationKind::Minus),
+            ),
+            Some('*') => self.either(
+                '=',
+                TokenKind::Assignment(AssignmentKind::StarEqual),
+                TokenKind::Operation(OperationKind::Star),
+            ),
+            Some('/') => self.either(
+                '=',
+                TokenKind::Assignment(AssignmentKind::SlashEqual),
+                TokenKind::Operation(OperationKind::Slash),
+            ),
+
+            // Comparison and assignment tokens. Some of them are two characters long.
+            // `<` and `>` can be followed by `=` or `?`, like in `<=` and `<?`
+            Some('<') if self.cursor.peek() == Some('?') => {
+                self.cursor.next(); // Consume the `?`
+                TokenKind::Operation(OperationKind::LessQuestion)
+            }
+            Some('<') => self.either(
+                '=',
+                TokenKind::Operation(OperationKind::LessEqual),
+                TokenKind::Operation(OperationKind::Less),
+            ),
+            Some('>') if self.cursor.peek() == Some('?') => {
+                self.cursor.next(); // Consume the `?`
+                TokenKind::Operation(OperationKind::GreaterQuestion)
+            }
+            Some('>') => self.either(
+                '=',
+                TokenKind::Operation(OperationKind::GreaterEqual),
+                TokenKind::Operation(OperationKind::Greater),
+            ),
+            // A `|` alone is unrecognized
+            Some('|') if self.cursor.peek() == Some('>') => {
+                self.cursor.next(); // Consume the `>`
+                TokenKind::Pipe
+            }
+            Some('=') => self.either(
+                '=',
+                TokenKind::Operation(OperationKind::EqualEqual),
+                TokenKind::Assignment(AssignmentKind::Equal),
+            ),
+            Some('!') => self.either(
+                '=',
+                TokenKind::Operation(OperationKind::BangEqual),
+                TokenKind::Operation(OperationKind::Bang),
+            ),
+            Some('^') => TokenKind::Operation(OperationKind::Caret),
+            Some('%') => TokenKind::Operation(OperationKind::Percent),
+            Some('√') => TokenKind::Operation(OperationKind::Sqrt),
+
+            // Placeholder tokens, like `$1`. A `$` alone is unrecognized.
+            Some('$') if self.cursor.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                self.cursor.skip_while(|c: char| c.is_ascii_digit());
+                let span = Span::from(start..self.cursor.byte_pos);
+                match self.input[(start + 1)..self.cursor.byte_pos].parse::<usize>() {
+                    Ok(index) if index > 0 => TokenKind::Placeholder(index),
+                    _ => return Some(Err(TokenizerError::InvalidPlaceholder(span))),
+                }
+            }
 
             // Parenthesis tokens
             Some('(') => TokenKind::OpenParenthesis,
             Some(')') => TokenKind::CloseParenthesis,
 
+            // Comma token
+            Some(',') => TokenKind::Comma,
+
+            // Semicolon token
+            Some(';') => TokenKind::Semicolon,
+
+            // Underscore token
+            Some('_') => TokenKind::Underscore,
+
+            // Dot token. Dots in numbers are part of the number token.
+            Some('.') => TokenKind::Dot,
+
+            // Text token, between double quotes. There are no escapes,
+            // so text can't contain a double quote.
+            Some('"') => {
+                self.cursor.skip_while(|c| c != '"');
+                if self.cursor.next().is_none() {
+                    let span = Span::from(start..self.cursor.byte_pos);
+                    return Some(Err(TokenizerError::UnterminatedText(span)));
+                }
+                let text = &self.input[start + 1..self.cursor.byte_pos - 1];
+                TokenKind::Text(text.to_string())
+            }
+
             // Any other character is unrecognized
-            Some(_) => TokenKind::Unrecognized,
+            Some(_) => {
+                let span = Span::from(start..self.cursor.byte_pos);
+                return Some(Err(TokenizerError::UnrecognizedCharacter(span)));
+            }
 
             // We consumed all the characters
             None => return None,
@@ -230,32 +755,144 @@ impl<'a> Tokenizer<'a> {
         let span = Span::from(start..end);
 
         // Return the token
-        Some(Token { kind, span })
+        Some(Ok(Token { kind, span }))
     }
 }
 
+/// Computes the value of a hexadecimal floating-point literal, like `1.8p3`
+/// (without the `0x` prefix). The hexadecimal digits are the mantissa, and the
+/// number after `p` is a power of two, so `1.8p3` is `1.5 * 2^3`, which is `12`.
+/// Returns `None` if the literal is malformed.
+///
+/// Rust's `parse` doesn't accept hexadecimal floats, so we compute the value ourselves.
+fn hex_float(literal: &str) -> Option<f64> {
+    let (mantissa, exponent) = match literal.split_once(['p', 'P']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (literal, None),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    // Huge exponents saturate, they are infinity or zero anyway.
+    let mut exponent = match exponent {
+        Some(exponent) => {
+            let (negative, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, exponent.strip_prefix('+').unwrap_or(exponent)),
+            };
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let value = digits.bytes().fold(0i32, |value, digit| {
+                value
+                    .saturating_mul(10)
+                    .saturating_add(i32::from(digit - b'0'))
+            });
+            if negative {
+                -value
+            } else {
+                value
+            }
+        }
+        None => 0,
+    };
+
+    // Every fraction digit divides the mantissa by 16.
+    exponent = exponent.saturating_sub(4 * fraction.len().min(i32::MAX as usize / 4) as i32);
+
+    // Accumulate the mantissa digits as an integer. Once it's too large to hold
+    // another digit, drop the remaining digits but remember whether any of them
+    // was nonzero (the "sticky" bit), so the conversion to `f64` still rounds correctly.
+    let mut bits: u64 = 0;
+    let mut sticky = false;
+    for c in integer.chars().chain(fraction.chars()) {
+        let digit = u64::from(c.to_digit(16)?);
+        if bits >> 60 == 0 {
+            bits = bits * 16 + digit;
+        } else {
+            exponent = exponent.saturating_add(4);
+            sticky |= digit != 0;
+        }
+    }
+    // Converting an integer to a float rounds to the nearest float.
+    let mut value = (bits | u64::from(sticky)) as f64;
+
+    // Multiplying by a power of two is exact, unless the result is subnormal.
+    // The power is applied in steps, since `2^exponent` alone could overflow.
+    while exponent != 0 && value != 0.0 && value.is_finite() {
+        let step = exponent.clamp(-1000, 1000);
+        value *= 2f64.powi(step);
+        exponent -= step;
+    }
+
+    Some(value)
+}
+
 /// Tests for the tokenizer.
 #[cfg(test)]
 mod tests {
-    use crate::tokenizer::{OperationKind, SpecialKind, Token, TokenKind, Tokenizer};
+    use crate::tokenizer::{
+        hex_float, unclosed_parentheses, AssignmentKind, OperationKind, Span, SpecialKind, Token,
+        TokenKind, Tokenizer, TokenizerError,
+    };
+
+    #[test]
+    fn test_display() {
+        let tokens = Tokenizer::new("?quit 123 + 2.5i x $1 = ( ) , ; _ |> const")
+            .tokens()
+            .unwrap()
+            .iter()
+            .map(Token::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Special(Quit) @ 0..5",
+                "Number(123) @ 6..9",
+                "Operation(Plus) @ 10..11",
+                "Imaginary(2.5) @ 12..16",
+                "Identifier(x) @ 17..18",
+                "Placeholder(1) @ 19..21",
+                "Assignment(Equal) @ 22..23",
+                "OpenParenthesis @ 24..25",
+                "CloseParenthesis @ 26..27",
+                "Comma @ 28..29",
+                "Semicolon @ 30..31",
+                "Underscore @ 32..33",
+                "Pipe @ 34..36",
+                "Const @ 37..42",
+            ],
+            tokens
+        );
+        let whitespace = Token {
+            kind: TokenKind::Whitespace,
+            span: Span::from(0..2),
+        };
+        assert_eq!("Whitespace @ 0..2", whitespace.to_string());
+    }
 
     #[test]
     fn test_whitespace() {
         let input = " \n\r\t";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        // `tokenize` filters out whitespace, so we look at the raw token instead.
+        let token = Tokenizer::new(input).next_token();
         assert_eq!(
-            vec![Token {
+            Some(Ok(Token {
                 kind: super::TokenKind::Whitespace,
                 span: (0..4).into()
-            }],
-            tokens
+            })),
+            token
         );
     }
 
     #[test]
     fn test_special_quit() {
         let input = "?quit";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Special(SpecialKind::Quit),
@@ -265,10 +902,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_special_bits() {
+        let input = "?bits";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Special(SpecialKind::Bits),
+                span: (0..5).into()
+            }],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_special_spaced() {
+        for (input, end) in [("?quit", 5), ("? quit", 6), ("?  quit", 7), ("?\tquit", 6)] {
+            let tokens = Tokenizer::new(input)
+                .tokenize()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(
+                vec![Token {
+                    kind: TokenKind::Special(SpecialKind::Quit),
+                    span: (0..end).into()
+                }],
+                tokens,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_special_lone() {
+        // The space isn't part of the command if no name follows it.
+        let input = "? 5";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                Token {
+                    kind: TokenKind::Special(SpecialKind::Unrecognized),
+                    span: (0..1).into()
+                },
+                Token {
+                    kind: TokenKind::Number(5.),
+                    span: (2..3).into()
+                }
+            ],
+            tokens
+        );
+    }
+
     #[test]
     fn test_special_unrecognized() {
         let input = "?blabla";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Special(SpecialKind::Unrecognized),
@@ -281,7 +978,10 @@ mod tests {
     #[test]
     fn test_number() {
         let input = "123.123";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Number(123.123),
@@ -294,7 +994,10 @@ mod tests {
     #[test]
     fn test_number_trailing_dot() {
         let input = "123.";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Number(123.),
@@ -307,7 +1010,10 @@ mod tests {
     #[test]
     fn test_number_no_decimal() {
         let input = "123";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Number(123.),
@@ -317,10 +1023,220 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_scientific_notation() {
+        let kinds = |input| {
+            Tokenizer::new(input)
+                .tokenize()
+                .map(|token| token.unwrap().kind)
+                .collect::<Vec<_>>()
+        };
+        let e = || TokenKind::Identifier("e".to_string());
+        assert_eq!(vec![TokenKind::Number(2e5)], kinds("2e5"));
+        assert_eq!(vec![TokenKind::Number(20.0)], kinds("2e+1"));
+        assert_eq!(vec![TokenKind::Number(1.5e-3)], kinds("1.5E-3"));
+        assert_eq!(vec![TokenKind::Number(3e2)], kinds("3.e2"));
+        // An `e` without digits after it isn't an exponent.
+        assert_eq!(vec![TokenKind::Number(2.0), e()], kinds("2e"));
+        assert_eq!(vec![TokenKind::Number(2.0), e()], kinds("2 e"));
+        assert_eq!(
+            vec![
+                TokenKind::Number(2.0),
+                TokenKind::Identifier("e5".to_string())
+            ],
+            kinds("2 e5")
+        );
+        assert_eq!(
+            vec![
+                TokenKind::Number(2.0),
+                e(),
+                TokenKind::Operation(OperationKind::Plus),
+                TokenKind::Identifier("x".to_string())
+            ],
+            kinds("2e+x")
+        );
+        assert_eq!(
+            vec![
+                TokenKind::Number(2.0),
+                TokenKind::Identifier("ex".to_string())
+            ],
+            kinds("2ex")
+        );
+        assert_eq!(vec![TokenKind::Imaginary(2e3)], kinds("2e3i"));
+        assert_eq!(
+            Err(TokenizerError::NumberOverflow((0..5).into())),
+            Tokenizer::new("1e400").tokens()
+        );
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let tokens = Tokenizer::new("$1 * $12")
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                Token {
+                    kind: TokenKind::Placeholder(1),
+                    span: (0..2).into()
+                },
+                Token {
+                    kind: TokenKind::Operation(OperationKind::Star),
+                    span: (3..4).into()
+                },
+                Token {
+                    kind: TokenKind::Placeholder(12),
+                    span: (5..8).into()
+                },
+            ],
+            tokens
+        );
+        assert_eq!(
+            Err(TokenizerError::InvalidPlaceholder((0..2).into())),
+            Tokenizer::new("$0").tokens()
+        );
+        assert_eq!(
+            Err(TokenizerError::UnrecognizedCharacter((0..1).into())),
+            Tokenizer::new("$x").tokens()
+        );
+    }
+
+    #[test]
+    fn test_min_max() {
+        let input = "<? < <= >? > >=";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Operation(OperationKind::LessQuestion),
+                TokenKind::Operation(OperationKind::Less),
+                TokenKind::Operation(OperationKind::LessEqual),
+                TokenKind::Operation(OperationKind::GreaterQuestion),
+                TokenKind::Operation(OperationKind::Greater),
+                TokenKind::Operation(OperationKind::GreaterEqual),
+            ],
+            kinds
+        );
+        // Without spaces, `<?` is still a single token.
+        let tokens = Tokenizer::new("3<?5")
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            Token {
+                kind: TokenKind::Operation(OperationKind::LessQuestion),
+                span: (1..3).into()
+            },
+            tokens[1]
+        );
+    }
+
+    #[test]
+    fn test_sqrt() {
+        // `√` is 3 bytes long, so the number starts at byte 3.
+        let input = "√16";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                Token {
+                    kind: TokenKind::Operation(OperationKind::Sqrt),
+                    span: (0..3).into()
+                },
+                Token {
+                    kind: TokenKind::Number(16.),
+                    span: (3..5).into()
+                },
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_tokens_twice() {
+        let tokenizer = Tokenizer::new("(1 + x) * 2");
+        let first = tokenizer.tokens().unwrap();
+        let second = tokenizer.tokens().unwrap();
+        assert_eq!(7, first.len());
+        assert_eq!(first, second);
+        // The borrowing method gives the same tokens as the consuming one.
+        assert_eq!(
+            Ok(first),
+            tokenizer.tokenize().collect::<Result<Vec<_>, _>>()
+        );
+        assert_eq!(
+            Err(TokenizerError::UnrecognizedCharacter((2..3).into())),
+            Tokenizer::new("1 $").tokens()
+        );
+    }
+
+    #[test]
+    fn test_with_offset() {
+        let input = "x = 12 + y";
+        let tokens = Tokenizer::with_offset(&input[4..], 4).tokens().unwrap();
+        let spans = tokens.iter().map(|token| token.span).collect::<Vec<_>>();
+        assert_eq!(
+            vec![Span::from(4..6), Span::from(7..8), Span::from(9..10)],
+            spans
+        );
+        // The spans index the original input.
+        assert_eq!(
+            vec!["12", "+", "y"],
+            spans.iter().map(|span| &input[*span]).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Err(TokenizerError::UnrecognizedCharacter((7..8).into())),
+            Tokenizer::with_offset("1 @", 5).tokens()
+        );
+        assert_eq!(
+            Tokenizer::new("1 + 2").tokens(),
+            Tokenizer::with_offset("1 + 2", 0).tokens()
+        );
+    }
+
+    #[test]
+    fn test_imaginary() {
+        let input = "2i 1.5i 3if";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                Token {
+                    kind: TokenKind::Imaginary(2.),
+                    span: (0..2).into()
+                },
+                Token {
+                    kind: TokenKind::Imaginary(1.5),
+                    span: (3..7).into()
+                },
+                // `if` is an identifier, not an `i` suffix
+                Token {
+                    kind: TokenKind::Number(3.),
+                    span: (8..9).into()
+                },
+                Token {
+                    kind: TokenKind::Identifier("if".to_string()),
+                    span: (9..11).into()
+                },
+            ],
+            tokens
+        );
+    }
+
     #[test]
     fn test_plus() {
         let input = "+";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Operation(OperationKind::Plus),
@@ -332,7 +1248,10 @@ mod tests {
     #[test]
     fn test_minus() {
         let input = "-";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Operation(OperationKind::Minus),
@@ -344,7 +1263,10 @@ mod tests {
     #[test]
     fn test_star() {
         let input = "*";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Operation(OperationKind::Star),
@@ -356,7 +1278,10 @@ mod tests {
     #[test]
     fn test_slash() {
         let input = "/";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::Operation(OperationKind::Slash),
@@ -368,7 +1293,10 @@ mod tests {
     #[test]
     fn test_open_parenthesis() {
         let input = "(";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::OpenParenthesis,
@@ -380,7 +1308,10 @@ mod tests {
     #[test]
     fn test_close_parenthesis() {
         let input = ")";
-        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
         assert_eq!(
             vec![Token {
                 kind: TokenKind::CloseParenthesis,
@@ -389,4 +1320,393 @@ mod tests {
             tokens
         );
     }
+
+    #[test]
+    fn test_comma() {
+        let input = ",";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Comma,
+                span: (0..1).into()
+            }],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_underscore() {
+        let kinds = Tokenizer::new("_ _x")
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Underscore,
+                TokenKind::Underscore,
+                TokenKind::Identifier("x".to_string())
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let tokens = Tokenizer::new(r#"fmt(1, "%.2f ") """#).tokens().unwrap();
+        assert_eq!(
+            Token {
+                kind: TokenKind::Text("%.2f ".to_string()),
+                span: (7..14).into()
+            },
+            tokens[4]
+        );
+        assert_eq!(TokenKind::Text(String::new()), tokens[6].kind);
+        assert_eq!(
+            Err(TokenizerError::UnterminatedText((4..9).into())),
+            Tokenizer::new(r#"1 + "%.2f"#).tokens()
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        let kinds = Tokenizer::new(". 1.5 .")
+            .tokenize()
+            .map(|token| token.unwrap().kind);
+        assert_eq!(
+            vec![TokenKind::Dot, TokenKind::Number(1.5), TokenKind::Dot],
+            kinds.collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_pipe() {
+        let tokens = Tokenizer::new("x|>f").tokenize().collect::<Vec<_>>();
+        assert_eq!(
+            Ok(Token {
+                kind: TokenKind::Pipe,
+                span: (1..3).into()
+            }),
+            tokens[1]
+        );
+        assert_eq!(
+            vec![Err(TokenizerError::UnrecognizedCharacter((0..1).into()))],
+            Tokenizer::new("| >").tokenize().take(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let input = "< <= > >= == !=";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Operation(OperationKind::Less),
+                TokenKind::Operation(OperationKind::LessEqual),
+                TokenKind::Operation(OperationKind::Greater),
+                TokenKind::Operation(OperationKind::GreaterEqual),
+                TokenKind::Operation(OperationKind::EqualEqual),
+                TokenKind::Operation(OperationKind::BangEqual),
+            ],
+            kinds
+        );
+    }
+    #[test]
+    fn test_two_char_comparison_span() {
+        let input = "<=";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Operation(OperationKind::LessEqual),
+                span: (0..2).into()
+            }],
+            tokens
+        );
+    }
+    #[test]
+    fn test_keywords() {
+        let input = "and or not";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Operation(OperationKind::And),
+                TokenKind::Operation(OperationKind::Or),
+                TokenKind::Operation(OperationKind::Not),
+            ],
+            kinds
+        );
+    }
+    #[test]
+    fn test_identifier() {
+        let input = "android";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Identifier("android".to_string()),
+                span: (0..7).into()
+            }],
+            tokens
+        );
+    }
+    #[test]
+    fn test_assignments() {
+        let input = "= += -= *= /= ==";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Assignment(AssignmentKind::Equal),
+                TokenKind::Assignment(AssignmentKind::PlusEqual),
+                TokenKind::Assignment(AssignmentKind::MinusEqual),
+                TokenKind::Assignment(AssignmentKind::StarEqual),
+                TokenKind::Assignment(AssignmentKind::SlashEqual),
+                TokenKind::Operation(OperationKind::EqualEqual),
+            ],
+            kinds
+        );
+    }
+    #[test]
+    fn test_span_merge_disjoint() {
+        let lhs = Span::from(0..2);
+        let rhs = Span::from(5..7);
+        assert_eq!(Span::from(0..7), lhs.merge(rhs));
+        // The order doesn't matter.
+        assert_eq!(Span::from(0..7), rhs.merge(lhs));
+    }
+    #[test]
+    fn test_span_merge_overlapping() {
+        let lhs = Span::from(0..4);
+        let rhs = Span::from(2..6);
+        assert_eq!(Span::from(0..6), lhs.merge(rhs));
+    }
+    #[test]
+    fn test_span_merge_contained() {
+        let outer = Span::from(0..10);
+        let inner = Span::from(3..4);
+        assert_eq!(outer, outer.merge(inner));
+        assert_eq!(outer, inner.merge(outer));
+    }
+    #[test]
+    fn test_caret() {
+        let input = "^";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Operation(OperationKind::Caret),
+                span: (0..1).into()
+            }],
+            tokens
+        );
+    }
+    #[test]
+    fn test_percent() {
+        let input = "%";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Operation(OperationKind::Percent),
+                span: (0..1).into()
+            }],
+            tokens
+        );
+    }
+    #[test]
+    fn test_bang() {
+        let input = "! !=";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Operation(OperationKind::Bang),
+                TokenKind::Operation(OperationKind::BangEqual),
+            ],
+            kinds
+        );
+    }
+    #[test]
+    fn test_unrecognized_character() {
+        let input = "1 @ é";
+        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Ok(Token {
+                    kind: TokenKind::Number(1.),
+                    span: (0..1).into()
+                }),
+                Err(TokenizerError::UnrecognizedCharacter((2..3).into())),
+                // `é` is two bytes long
+                Ok(Token {
+                    kind: TokenKind::Identifier("é".to_string()),
+                    span: (4..6).into()
+                }),
+            ],
+            tokens
+        );
+    }
+    #[test]
+    fn test_unrecognized_multibyte_character() {
+        let input = "€";
+        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        assert_eq!(
+            vec![Err(TokenizerError::UnrecognizedCharacter((0..3).into()))],
+            tokens
+        );
+    }
+    #[test]
+    fn test_hex_float() {
+        let input = "0x1p4 0x1.8p1 0X1.8P3 0xff 0x.8 0x1p-2";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Number(16.),
+                TokenKind::Number(3.),
+                TokenKind::Number(12.),
+                TokenKind::Number(255.),
+                TokenKind::Number(0.5),
+                TokenKind::Number(0.25),
+            ],
+            kinds
+        );
+    }
+    #[test]
+    fn test_binary_and_octal() {
+        let input = "0b101 0B11 0o17 0O777 0b0";
+        let kinds = Tokenizer::new(input)
+            .tokenize()
+            .map(|token| token.unwrap().kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Number(5.),
+                TokenKind::Number(3.),
+                TokenKind::Number(15.),
+                TokenKind::Number(511.),
+                TokenKind::Number(0.),
+            ],
+            kinds
+        );
+        assert_eq!(
+            Err(TokenizerError::MalformedNumber((0..4).into())),
+            Tokenizer::new("0b12").tokens()
+        );
+        assert_eq!(
+            Err(TokenizerError::MalformedNumber((0..2).into())),
+            Tokenizer::new("0o + 1").tokens()
+        );
+        let too_long = format!("0b1{}", "0".repeat(128));
+        assert_eq!(
+            Err(TokenizerError::NumberOverflow((0..131).into())),
+            Tokenizer::new(&too_long).tokens()
+        );
+    }
+
+    #[test]
+    fn test_hex_float_span() {
+        let input = "0x1.8p+10";
+        let tokens = Tokenizer::new(input)
+            .tokenize()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![Token {
+                kind: TokenKind::Number(1536.),
+                span: (0..9).into()
+            }],
+            tokens
+        );
+    }
+    #[test]
+    fn test_hex_float_exact() {
+        assert_eq!(Some(f64::MAX), hex_float("1.fffffffffffffp1023"));
+        assert_eq!(Some(f64::MIN_POSITIVE), hex_float("1p-1022"));
+        // The smallest subnormal number.
+        assert_eq!(Some(f64::from_bits(1)), hex_float("1p-1074"));
+        assert_eq!(Some(0.0), hex_float("1p-99999999999"));
+    }
+    #[test]
+    fn test_hex_float_rounding() {
+        // Exactly halfway between `1` and the next float rounds to even.
+        assert_eq!(Some(1.0), hex_float("1.00000000000008"));
+        assert_eq!(Some(1.0), hex_float("1.0000000000000800000000"));
+        // Slightly above halfway rounds up, even if the nonzero digit is far away.
+        let next = f64::from_bits(1f64.to_bits() + 1);
+        assert_eq!(Some(next), hex_float("1.00000000000008000000001"));
+    }
+    #[test]
+    fn test_hex_float_malformed() {
+        for input in ["0x", "0xp3", "0x1p", "0x1p+", "0x.p1"] {
+            let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+            assert_eq!(
+                vec![Err(TokenizerError::MalformedNumber(
+                    (0..input.len()).into()
+                ))],
+                tokens,
+                "{input}"
+            );
+        }
+    }
+    #[test]
+    fn test_hex_float_overflow() {
+        let input = "0x1p1024";
+        let tokens = Tokenizer::new(input).tokenize().collect::<Vec<_>>();
+        assert_eq!(
+            vec![Err(TokenizerError::NumberOverflow((0..8).into()))],
+            tokens
+        );
+    }
+    #[test]
+    fn test_needs_continuation() {
+        assert!(Tokenizer::new("(1 + 2").needs_continuation());
+        assert!(Tokenizer::new("((1) + 2) * (3").needs_continuation());
+        assert!(Tokenizer::new("(\n").needs_continuation());
+    }
+    #[test]
+    fn test_no_continuation() {
+        for input in ["1 + 2", "(1 + 2)", "", "1 + 2)", ") (", "(1 @ 2"] {
+            assert!(!Tokenizer::new(input).needs_continuation(), "{input}");
+        }
+    }
+    #[test]
+    fn test_unclosed_parentheses() {
+        for (expected, input) in [(0, "1 + 2"), (2, "(1 + (2"), (1, "((1) + 2"), (0, ") (")] {
+            let tokens = Tokenizer::new(input).tokens().unwrap();
+            assert_eq!(expected, unclosed_parentheses(&tokens), "{input}");
+        }
+    }
+    #[test]
+    fn test_number_overflow() {
+        let input = "9".repeat(400);
+        let tokens = Tokenizer::new(&input).tokenize().collect::<Vec<_>>();
+        assert_eq!(
+            vec![Err(TokenizerError::NumberOverflow((0..400).into()))],
+            tokens
+        );
+    }
 }
@@ -0,0 +1,139 @@
+//! Tests for the command line flags and batch mode, running the calculator binary.
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs the calculator with the given arguments, piping the input.
+fn run(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run the calculator");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input)
+        .expect("failed to write the input");
+    child
+        .wait_with_output()
+        .expect("failed to run the calculator")
+}
+
+/// Runs the calculator like `run`, checks that it succeeded, and returns what it printed.
+fn run_ok(args: &[&str], input: &str) -> String {
+    let output = run(args, input.as_bytes());
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).expect("the output isn't UTF-8")
+}
+
+#[test]
+fn test_without_json() {
+    assert_eq!("2 + 2 = 4\n", run_ok(&["-e", "2 + 2"], ""));
+}
+
+#[test]
+fn test_json_value() {
+    assert_eq!(
+        "{\"ok\":true,\"value\":4.0}\n",
+        run_ok(&["-e", "2 + 2", "--json"], "")
+    );
+}
+
+#[test]
+fn test_json_error() {
+    assert_eq!(
+        "{\"ok\":false,\"error\":\"division by zero\",\"span\":{\"start\":0,\"end\":6}}\n",
+        run_ok(&["--json", "-e", "inv(0)"], "")
+    );
+}
+
+#[test]
+fn test_json_division_by_zero() {
+    assert_eq!(
+        "{\"ok\":false,\"error\":\"division by zero\",\"span\":{\"start\":0,\"end\":3}}\n",
+        run_ok(&["-e", "1/0", "--json"], "")
+    );
+    // Finite results are values, even if they divided by zero.
+    assert_eq!(
+        "{\"ok\":true,\"value\":0.0}\n",
+        run_ok(&["-e", "1 / (1/0)", "--json"], "")
+    );
+}
+
+#[test]
+fn test_csv_rows() {
+    assert_eq!("1 + 2,3\n\"inv(4, 5)\",\"error: expected 1 argument, found 2\"\n1/x,error: unknown variable `x`\n", run_ok(&["--csv"], "1 + 2\ninv(4, 5)\n1/x\n"));
+}
+
+#[test]
+fn test_csv_precision() {
+    // Results are formatted with the session's settings, and quoted if
+    // the format adds commas. Commands have no row.
+    assert_eq!(
+        "1/3,0.33\n1000000,\"1,000,000.00\"\n",
+        run_ok(&["--csv"], "?money on\n1/3\n1000000\n")
+    );
+}
+
+#[test]
+fn test_newline_delimiter() {
+    assert_eq!(
+        "{\"ok\":true,\"value\":3.0}\n{\"ok\":true,\"value\":6.0}\n",
+        run_ok(&["--json", "--delimiter", r"\n"], "1 + 2\n2 * 3\n")
+    );
+}
+
+#[test]
+fn test_null_delimiter() {
+    assert_eq!(
+        "{\"ok\":true,\"value\":3.0}\0{\"ok\":true,\"value\":6.0}\0",
+        run_ok(&["--json", "--delimiter", r"\0"], "1 + 2\n2 * 3\n")
+    );
+}
+
+#[test]
+fn test_overflow_warning() {
+    let output = run(&[], b"?overflow warn\n10^308 * 10\n1 + 1\n");
+    assert!(output.status.success());
+    assert_eq!(
+        "10^308 * 10 = inf\n1 + 1       = 2\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert_eq!(
+        "warning: an operation overflowed to infinity in `10^308 * 10`\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_assert_passes() {
+    assert_eq!(
+        "assert(0.1 + 0.2, 0.3) = 1\n",
+        run_ok(&["-e", "assert(0.1 + 0.2, 0.3)"], "")
+    );
+}
+
+#[test]
+fn test_assert_fails() {
+    let output = run(&["-e", "assert(2 + 3, 4)"], b"");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "assert(2 + 3, 4) = error: assertion failed, expected 4, found 5\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn test_invalid_utf8_input() {
+    let output = run(&[], b"1+\xff\n");
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not read the standard input"));
+}